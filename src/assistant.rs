@@ -0,0 +1,218 @@
+use crate::chat_completion::ChatCompletionClient;
+use crate::config::Config;
+use crate::conversation::{ChatMessage, ToolCall};
+use crate::eliza::ElizaClient;
+use crate::tools;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// ストリーミング中は返答が長くなりうるので、全体のタイムアウトは長めに取る
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// アシスタントモードの送信先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssistantBackend {
+    /// eliza-agent-server の `/chat`
+    Eliza,
+    /// OpenAI 互換の `/v1/chat/completions`
+    ChatCompletion,
+}
+
+impl AssistantBackend {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AssistantBackend::Eliza => "Eliza Agent",
+            AssistantBackend::ChatCompletion => "OpenAI-compatible",
+        }
+    }
+}
+
+/// バックグラウンドのアシスタント呼び出しから UI に届くイベント
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssistantEvent {
+    /// 返答の断片 (届いた順に連結すると全文になる)
+    Delta(String),
+    /// 返答の全文
+    Done(String),
+    /// ツール呼び出しの要求 (実行結果を添えて再度問い合わせる)
+    ToolCalls {
+        content: String,
+        calls: Vec<ToolCall>,
+    },
+    Error(String),
+}
+
+/// アシスタントの返答 (本文と、要求されたツール呼び出し)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssistantReply {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// 会話を送って返答を受け取るアシスタント
+pub trait Assistant: Send {
+    /// 返答の断片を届いた順に on_delta に渡し、最後に全文を返す
+    fn stream_chat(
+        &self,
+        messages: &[ChatMessage],
+        on_delta: &mut dyn FnMut(&str),
+    ) -> Result<AssistantReply, String>;
+}
+
+/// Build the assistant client selected in config
+pub fn from_config(config: &Config) -> Box<dyn Assistant> {
+    match config.assistant_backend {
+        AssistantBackend::Eliza => Box::new(ElizaClient::new(
+            config.eliza_url.clone(),
+            config.assistant_streaming,
+        )),
+        AssistantBackend::ChatCompletion => Box::new(ChatCompletionClient::new(
+            config.chat_completion_base_url.clone(),
+            config.chat_completion_api_key.clone(),
+            config.chat_completion_model.clone(),
+            config.chat_completion_system_prompt.clone(),
+            config.chat_completion_temperature,
+            config.assistant_streaming,
+            if config.assistant_tools_enabled {
+                tools::tool_schemas(&config.assistant_tools)
+            } else {
+                Vec::new()
+            },
+        )),
+    }
+}
+
+/// Run the assistant on a background thread and stream its events
+pub fn spawn_chat(
+    assistant: Box<dyn Assistant>,
+    messages: Vec<ChatMessage>,
+) -> Receiver<AssistantEvent> {
+    let (sender, receiver) = channel::<AssistantEvent>();
+    std::thread::spawn(move || {
+        let result = assistant.stream_chat(&messages, &mut |delta| {
+            let _ = sender.send(AssistantEvent::Delta(delta.to_string()));
+        });
+        let _ = sender.send(match result {
+            Ok(reply) if !reply.tool_calls.is_empty() => AssistantEvent::ToolCalls {
+                content: reply.content,
+                calls: reply.tool_calls,
+            },
+            Ok(reply) => AssistantEvent::Done(reply.content),
+            Err(e) => AssistantEvent::Error(e),
+        });
+    });
+    receiver
+}
+
+pub(crate) fn http_client() -> reqwest::blocking::Client {
+    reqwest::blocking::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// バックエンドの応答を読み、JSON を1つずつ on_json に渡す
+/// Content-Type が SSE (`text/event-stream`) か NDJSON なら1行ずつ、
+/// それ以外は本文全体を1つの JSON として扱う
+pub(crate) fn read_response(
+    response: reqwest::blocking::Response,
+    on_json: &mut dyn FnMut(&Value),
+) -> Result<(), String> {
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    if !status.is_success() {
+        let raw = response.text().unwrap_or_default();
+        return Err(format!("HTTP {}: {}", status, raw));
+    }
+
+    if is_stream_content_type(&content_type) {
+        read_stream(BufReader::new(response), on_json)
+    } else {
+        let raw = response
+            .text()
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+        let body: Value = serde_json::from_str(&raw)
+            .map_err(|e| format!("Failed to parse response: {}. Body was: {}", e, raw))?;
+        on_json(&body);
+        Ok(())
+    }
+}
+
+fn is_stream_content_type(content_type: &str) -> bool {
+    content_type.starts_with("text/event-stream")
+        || content_type.starts_with("application/x-ndjson")
+        || content_type.starts_with("application/jsonl")
+}
+
+/// SSE (`data: {...}`) または NDJSON の行を順に読む
+/// `data: [DONE]` で終了する
+fn read_stream<R: Read>(
+    reader: BufReader<R>,
+    on_json: &mut dyn FnMut(&Value),
+) -> Result<(), String> {
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("Failed to read stream: {}", e))?;
+        let line = line.trim();
+        // 空行・SSE のコメントや event 行は読み飛ばす
+        if line.is_empty() || line.starts_with(':') || line.starts_with("event:") {
+            continue;
+        }
+        let data = line.strip_prefix("data:").map(str::trim).unwrap_or(line);
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk: Value = serde_json::from_str(data)
+            .map_err(|e| format!("Failed to parse stream chunk: {}. Chunk was: {}", e, data))?;
+        if let Some(error) = chunk.get("error") {
+            return Err(format!("Stream error: {}", error));
+        }
+        on_json(&chunk);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_contents(body: &str) -> Result<Vec<String>, String> {
+        let mut contents = Vec::new();
+        read_stream(BufReader::new(body.as_bytes()), &mut |chunk| {
+            if let Some(content) = chunk["content"].as_str() {
+                contents.push(content.to_string());
+            }
+        })?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn test_read_sse_stream() {
+        let body = "event: message\ndata: {\"content\":\"こん\"}\n\n: keep-alive\ndata: {\"content\":\"にちは\"}\n\ndata: [DONE]\n\ndata: {\"content\":\"ignored\"}\n";
+        assert_eq!(read_contents(body).unwrap(), vec!["こん", "にちは"]);
+    }
+
+    #[test]
+    fn test_read_ndjson_stream() {
+        let body = "{\"content\":\"a\"}\n{\"done\":true}\n{\"content\":\"b\"}\n";
+        assert_eq!(read_contents(body).unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_read_stream_error_chunk() {
+        let body = "data: {\"content\":\"a\"}\ndata: {\"error\":{\"message\":\"overloaded\"}}\n";
+        let err = read_contents(body).unwrap_err();
+        assert!(err.contains("overloaded"), "{}", err);
+    }
+}
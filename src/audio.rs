@@ -1,31 +1,248 @@
+use crate::audio_monitor::AudioMonitor;
+use crate::dsp::{DspChain, DspSettings};
+use crate::level_meter::{BlockLevel, LevelMeter, LevelSnapshot};
+use crate::loopback::{self, LoopbackMixer, SystemCapture};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedSender;
 
+/// `InputSource::Separate` で文字起こしに付けるラベル
+pub const MICROPHONE_LABEL: &str = "me";
+pub const SYSTEM_AUDIO_LABEL: &str = "them";
+
+/// 設定画面で既定のデバイスを表す選択肢
+pub const DEFAULT_DEVICE_LABEL: &str = "Windows既定";
+
+/// 録音する音
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputSource {
+    #[default]
+    Microphone,
+    /// システムの再生音 (ループバック)
+    System,
+    /// マイクと再生音を混ぜて 1 本にする
+    Mixed,
+    /// マイクを "me"、再生音を "them" として別々に文字起こしする
+    Separate,
+}
+
+impl InputSource {
+    pub const ALL: [InputSource; 4] = [
+        InputSource::Microphone,
+        InputSource::System,
+        InputSource::Mixed,
+        InputSource::Separate,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputSource::Microphone => "Microphone",
+            InputSource::System => "System audio (loopback)",
+            InputSource::Mixed => "Microphone + system audio (mixed)",
+            InputSource::Separate => "Microphone + system audio (\"me\" / \"them\")",
+        }
+    }
+
+    /// マイクを開くか (入力デバイスの設定を使うか)
+    pub fn uses_microphone(&self) -> bool {
+        *self != InputSource::System
+    }
+}
+
+/// マイクを開くときの形式 (既定はデバイスの既定の設定で、全チャンネルの平均)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputFormat {
+    /// 使うチャンネル (1 始まり)。複数なら平均し、空なら全チャンネルの平均
+    pub channels: Vec<u16>,
+    /// None = デバイスの既定
+    pub sample_rate: Option<u32>,
+    /// バッファのフレーム数 (None = デバイスの既定)
+    pub buffer_size: Option<u32>,
+}
+
+/// `"2"` や `"1, 2"` をチャンネルの一覧にする (空ならすべての平均)
+pub fn parse_channel_list(text: &str) -> Result<Vec<u16>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.parse::<u16>() {
+            Ok(channel) if channel >= 1 => Ok(channel),
+            _ => Err(format!("Invalid channel '{}' (channels start at 1)", part)),
+        })
+        .collect()
+}
+
+fn describe_channels(selected: &[usize]) -> String {
+    match selected {
+        [] => "average of all channels".to_string(),
+        [channel] => format!("channel {}", channel + 1),
+        _ => format!(
+            "average of channels {}",
+            selected
+                .iter()
+                .map(|c| (c + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// 実際に開くストリームの設定
+#[derive(Debug, Clone, PartialEq)]
+struct StreamPlan {
+    config: cpal::StreamConfig,
+    sample_format: cpal::SampleFormat,
+    /// 取り出すチャンネル (0 始まり、空なら全チャンネルの平均)
+    channels: Vec<usize>,
+}
+
+/// `InputFormat` を満たすストリームの設定をデバイスの対応範囲から選ぶ
+/// 既定のチャンネル数・形式を優先し、足りなければチャンネル数の多い設定を使う
+fn plan_stream(
+    format: &InputFormat,
+    default: &cpal::SupportedStreamConfig,
+    supported: &[cpal::SupportedStreamConfigRange],
+) -> Result<StreamPlan, String> {
+    if format.channels.contains(&0) {
+        return Err("Input channels start at 1".to_string());
+    }
+    let selected: Vec<usize> = format.channels.iter().map(|&c| c as usize - 1).collect();
+    let needed = format.channels.iter().copied().max().unwrap_or(1);
+    let sample_rate = format.sample_rate.unwrap_or(default.sample_rate().0);
+
+    // 一覧を返さないバックエンドもあるので、既定の設定も候補に入れる
+    let default_range = cpal::SupportedStreamConfigRange::new(
+        default.channels(),
+        default.sample_rate(),
+        default.sample_rate(),
+        *default.buffer_size(),
+        default.sample_format(),
+    );
+    let candidates = || supported.iter().chain(std::iter::once(&default_range));
+
+    let max_channels = candidates().map(|r| r.channels()).max().unwrap_or(0);
+    if needed > max_channels {
+        return Err(format!(
+            "Input device has only {} channel(s), but channel {} was selected",
+            max_channels, needed
+        ));
+    }
+    let range = candidates()
+        .filter(|r| {
+            r.channels() >= needed
+                && (r.min_sample_rate().0..=r.max_sample_rate().0).contains(&sample_rate)
+        })
+        .min_by_key(|r| {
+            (
+                r.channels() != default.channels(),
+                r.sample_format() != default.sample_format(),
+                r.channels(),
+            )
+        })
+        .ok_or_else(|| {
+            format!(
+                "Sample rate {} Hz is not supported by the input device",
+                sample_rate
+            )
+        })?;
+
+    let buffer_size = match format.buffer_size {
+        None => cpal::BufferSize::Default,
+        Some(frames) => {
+            if let cpal::SupportedBufferSize::Range { min, max } = *range.buffer_size() {
+                if !(min..=max).contains(&frames) {
+                    return Err(format!(
+                        "Buffer size {} is outside the input device's range ({}-{})",
+                        frames, min, max
+                    ));
+                }
+            }
+            cpal::BufferSize::Fixed(frames)
+        }
+    };
+
+    Ok(StreamPlan {
+        config: cpal::StreamConfig {
+            channels: range.channels(),
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size,
+        },
+        sample_format: range.sample_format(),
+        channels: selected,
+    })
+}
+
 pub struct AudioRecorder {
     stream: Option<cpal::Stream>,
+    // 再生音の取り込み (InputSource::Microphone 以外)
+    system: Option<SystemCapture>,
     sample_rate: u32,
     last_sound_time: Arc<Mutex<Instant>>,
     silence_threshold: f32,
     recording_start_time: Arc<Mutex<Option<Instant>>>,
-    current_max_amplitude: Arc<Mutex<f32>>,
+    level: Arc<LevelMeter>,
+    // 波形とスペクトログラムの表示用 (表示する秒数)
+    monitor: Option<(Arc<Mutex<AudioMonitor>>, f32)>,
+    dsp: DspSettings,
+    input_format: InputFormat,
+    // 録音中にストリームで起きたエラー (デバイスが外れたなど)
+    stream_error: Arc<Mutex<Option<String>>>,
 }
 
 impl AudioRecorder {
     pub fn new(silence_threshold: f32) -> Result<Self, String> {
         Ok(Self {
             stream: None,
+            system: None,
             sample_rate: 0,
             last_sound_time: Arc::new(Mutex::new(Instant::now())),
             silence_threshold,
             recording_start_time: Arc::new(Mutex::new(None)),
-            current_max_amplitude: Arc::new(Mutex::new(0.0)),
+            level: Arc::new(LevelMeter::new()),
+            monitor: None,
+            dsp: DspSettings::default(),
+            input_format: InputFormat::default(),
+            stream_error: Arc::new(Mutex::new(None)),
         })
     }
 
-    pub fn get_max_amplitude(&self) -> f32 {
-        *self.current_max_amplitude.lock().unwrap()
+    /// Current input level (RMS / peak in dBFS, clip indicator)
+    pub fn level(&self) -> LevelSnapshot {
+        self.level.snapshot()
+    }
+
+    /// Keep the peak of every audio callback, read back with `peaks` (for calibration)
+    pub fn record_peaks(&mut self) {
+        self.level = Arc::new(LevelMeter::with_peak_log());
+    }
+
+    /// `(秒, ピーク)` of every callback since recording started (see `record_peaks`)
+    pub fn peaks(&self) -> Vec<(f32, f32)> {
+        self.level.peaks()
+    }
+
+    /// Also copy the recorded chunks to `monitor`, keeping the last `window_secs`
+    pub fn set_monitor(&mut self, monitor: Arc<Mutex<AudioMonitor>>, window_secs: f32) {
+        self.monitor = Some((monitor, window_secs));
+    }
+
+    /// Filter the captured audio before silence detection and STT
+    pub fn set_dsp(&mut self, dsp: DspSettings) {
+        self.dsp = dsp;
+    }
+
+    /// Channels, sample rate and buffer size to open the microphone with
+    pub fn set_input_format(&mut self, input_format: InputFormat) {
+        self.input_format = input_format;
+    }
+
+    /// Error reported by the stream while recording (e.g. the device was unplugged)
+    pub fn stream_error(&self) -> Option<String> {
+        self.stream_error.lock().unwrap().clone()
     }
 
     pub fn is_silent(&self, silence_duration_secs: f32) -> bool {
@@ -42,7 +259,7 @@ impl AudioRecorder {
         let is_silent = silence_duration >= Duration::from_secs_f32(silence_duration_secs);
 
         if is_silent {
-            println!(
+            eprintln!(
                 "SILENT DETECTED: {:.1}s >= {:.1}s",
                 silence_duration.as_secs_f32(),
                 silence_duration_secs
@@ -71,10 +288,91 @@ impl AudioRecorder {
         }
     }
 
+    /// Record from `source`; with `InputSource::Separate` the microphone goes to
+    /// `chunk_sender` and the system audio to `system_sender`, both at `get_sample_rate()`
+    pub fn start_recording_from_source(
+        &mut self,
+        source: InputSource,
+        device_name: Option<&str>,
+        chunk_sender: Option<UnboundedSender<Vec<f32>>>,
+        system_sender: Option<UnboundedSender<Vec<f32>>>,
+    ) -> Result<(), String> {
+        match source {
+            InputSource::Microphone => self.start_recording_with_device(device_name, chunk_sender),
+            InputSource::System => {
+                let capture = loopback::start(
+                    0,
+                    |sample_rate| {
+                        self.begin_recording(sample_rate);
+                        let mut state = self.capture_state(sample_rate, chunk_sender);
+                        Box::new(move |samples| state.process(samples))
+                    },
+                    Arc::clone(&self.stream_error),
+                )?;
+                self.sample_rate = capture.sample_rate();
+                self.system = Some(capture);
+                Ok(())
+            }
+            InputSource::Mixed => {
+                // マイクのコールバックで再生音を足す (レートはマイクに合わせる)
+                let mixer = Arc::new(Mutex::new(LoopbackMixer::default()));
+                self.start_microphone(device_name, chunk_sender, Some(Arc::clone(&mixer)))?;
+                let mic_rate = self.sample_rate;
+                self.start_system_audio(move |samples| {
+                    mixer.lock().unwrap().push(&samples, mic_rate)
+                })
+            }
+            InputSource::Separate => {
+                self.start_microphone(device_name, chunk_sender, None)?;
+                // 再生音は無音判定にだけ使い、DSP とメーター・波形表示はマイクだけ
+                let mut state = self.capture_state(self.sample_rate, system_sender);
+                state.dsp = None;
+                state.level = None;
+                state.monitor = None;
+                self.start_system_audio(move |samples| state.process(samples))
+            }
+        }
+    }
+
+    /// マイクを開いた後で再生音をマイクのサンプルレートにして渡す
+    fn start_system_audio(
+        &mut self,
+        mut on_samples: impl FnMut(Vec<f32>) + Send + 'static,
+    ) -> Result<(), String> {
+        let mic_rate = self.sample_rate;
+        let result = loopback::start(
+            mic_rate,
+            |rate| {
+                let mut resampler = loopback::Resampler::new(rate, mic_rate);
+                Box::new(move |samples: Vec<f32>| on_samples(resampler.process(&samples)))
+            },
+            Arc::clone(&self.stream_error),
+        );
+        match result {
+            Ok(capture) => {
+                self.system = Some(capture);
+                Ok(())
+            }
+            Err(e) => {
+                self.stream = None;
+                Err(e)
+            }
+        }
+    }
+
     pub fn start_recording_with_device(
         &mut self,
         device_name: Option<&str>,
         chunk_sender: Option<UnboundedSender<Vec<f32>>>,
+    ) -> Result<(), String> {
+        self.start_microphone(device_name, chunk_sender, None)
+    }
+
+    fn start_microphone(
+        &mut self,
+        device_name: Option<&str>,
+        chunk_sender: Option<UnboundedSender<Vec<f32>>>,
+        mixer: Option<Arc<Mutex<LoopbackMixer>>>,
     ) -> Result<(), String> {
         let host = cpal::default_host();
 
@@ -88,127 +386,76 @@ impl AudioRecorder {
                 .ok_or("No input device available")?
         };
 
-        println!("Using input device: {}", device.name().unwrap_or_default());
+        eprintln!("Using input device: {}", device.name().unwrap_or_default());
 
         let default_config = device
             .default_input_config()
             .map_err(|e| format!("Failed to get default input config: {}", e))?;
-
-        let mono_config = cpal::StreamConfig {
-            channels: 1,
-            sample_rate: default_config.sample_rate(),
-            buffer_size: cpal::BufferSize::Default,
+        let supported: Vec<_> = device
+            .supported_input_configs()
+            .map(|configs| configs.collect())
+            .unwrap_or_default();
+        let plan = plan_stream(&self.input_format, &default_config, &supported)?;
+
+        // mono でも plan のチャンネル数でもサンプルレートは同じ
+        self.sample_rate = plan.config.sample_rate.0;
+        self.begin_recording(self.sample_rate);
+
+        // 1 回目は mono で開き、失敗したら plan のチャンネル数で開き直すので、
+        // コールバックの状態はその都度作る
+        let callback = || {
+            let mut state = self.capture_state(self.sample_rate, chunk_sender.clone());
+            state.mixer = mixer.clone();
+            move |samples| state.process(samples)
         };
 
-        self.reset_silence_timer();
-
-        {
-            let mut max_amp = self.current_max_amplitude.lock().unwrap();
-            *max_amp = 0.0;
-        }
-
-        {
-            let mut start_time = self.recording_start_time.lock().unwrap();
-            *start_time = Some(Instant::now());
-        }
-
-        let last_sound_clone = Arc::clone(&self.last_sound_time);
-        let max_amplitude_clone = Arc::clone(&self.current_max_amplitude);
-        let threshold = self.silence_threshold;
-        let chunk_size = (mono_config.sample_rate.0 as f32 * 0.1) as usize; // 100ms
-
-        let stream_result = match default_config.sample_format() {
-            cpal::SampleFormat::F32 => self.build_input_stream::<f32>(
-                &device,
-                &mono_config,
-                last_sound_clone.clone(),
-                max_amplitude_clone.clone(),
-                threshold,
-                chunk_sender.clone(),
-                chunk_size,
-            ),
-            cpal::SampleFormat::I16 => self.build_input_stream::<i16>(
-                &device,
-                &mono_config,
-                last_sound_clone.clone(),
-                max_amplitude_clone.clone(),
-                threshold,
-                chunk_sender.clone(),
-                chunk_size,
-            ),
-            cpal::SampleFormat::U16 => self.build_input_stream::<u16>(
+        // 全チャンネルの平均でよければ、まず mono で開いてデバイス側で混ぜてもらう
+        let mono_result = plan.channels.is_empty().then(|| {
+            let mono_config = cpal::StreamConfig {
+                channels: 1,
+                ..plan.config.clone()
+            };
+            build_stream(
                 &device,
                 &mono_config,
-                last_sound_clone.clone(),
-                max_amplitude_clone.clone(),
-                threshold,
-                chunk_sender.clone(),
-                chunk_size,
-            ),
-            _ => return Err("Unsupported sample format".to_string()),
-        };
+                plan.sample_format,
+                &[],
+                callback(),
+                Arc::clone(&self.stream_error),
+            )
+        });
 
-        let stream = match stream_result {
-            Ok(stream) => {
-                self.sample_rate = mono_config.sample_rate.0;
-                println!(
+        let stream = match mono_result {
+            Some(Ok(stream)) => {
+                eprintln!(
                     "Sample rate: {}Hz, Channels: 1 (forced mono), Format: {:?}",
-                    self.sample_rate,
-                    default_config.sample_format()
+                    self.sample_rate, plan.sample_format
                 );
                 stream
             }
-            Err(e) => {
-                println!(
-                    "Mono config not supported ({}), falling back to default config",
-                    e
-                );
-                let default_stream_config = default_config.config();
-                self.sample_rate = default_stream_config.sample_rate.0;
-                let channels = default_stream_config.channels;
-                let fallback_chunk_size =
-                    (default_stream_config.sample_rate.0 as f32 * 0.1) as usize;
-
-                println!(
-                    "Sample rate: {}Hz, Channels: {} (using default), Format: {:?}",
+            other => {
+                if let Some(Err(e)) = other {
+                    eprintln!(
+                        "Mono config not supported ({}), falling back to {} channels",
+                        e, plan.config.channels
+                    );
+                }
+                eprintln!(
+                    "Sample rate: {}Hz, Channels: {} (using {}), Format: {:?}",
                     self.sample_rate,
-                    channels,
-                    default_config.sample_format()
+                    plan.config.channels,
+                    describe_channels(&plan.channels),
+                    plan.sample_format
                 );
 
-                match default_config.sample_format() {
-                    cpal::SampleFormat::F32 => self.build_input_stream_with_channels::<f32>(
-                        &device,
-                        &default_stream_config,
-                        last_sound_clone,
-                        max_amplitude_clone,
-                        threshold,
-                        channels,
-                        chunk_sender,
-                        fallback_chunk_size,
-                    ),
-                    cpal::SampleFormat::I16 => self.build_input_stream_with_channels::<i16>(
-                        &device,
-                        &default_stream_config,
-                        last_sound_clone,
-                        max_amplitude_clone,
-                        threshold,
-                        channels,
-                        chunk_sender,
-                        fallback_chunk_size,
-                    ),
-                    cpal::SampleFormat::U16 => self.build_input_stream_with_channels::<u16>(
-                        &device,
-                        &default_stream_config,
-                        last_sound_clone,
-                        max_amplitude_clone,
-                        threshold,
-                        channels,
-                        chunk_sender,
-                        fallback_chunk_size,
-                    ),
-                    _ => return Err("Unsupported sample format".to_string()),
-                }?
+                build_stream(
+                    &device,
+                    &plan.config,
+                    plan.sample_format,
+                    &plan.channels,
+                    callback(),
+                    Arc::clone(&self.stream_error),
+                )?
             }
         };
 
@@ -224,149 +471,195 @@ impl AudioRecorder {
         // streamをdropするとコールバッククロージャがdrop → chunk_senderがdrop
         // → UnboundedReceiver側がdisconnectを検知 → WebSocketタスクがaudio.doneを送信
         self.stream = None;
+        self.system = None;
     }
 
-    pub fn get_sample_rate(&self) -> u32 {
-        self.sample_rate
+    /// 録音を始める前にメーター・無音タイマー・波形表示を初期化する
+    fn begin_recording(&self, sample_rate: u32) {
+        self.reset_silence_timer();
+
+        self.level.reset();
+        *self.stream_error.lock().unwrap() = None;
+        if let Some((monitor, window_secs)) = &self.monitor {
+            monitor
+                .lock()
+                .unwrap()
+                .reset(sample_rate, self.silence_threshold, *window_secs);
+        }
+
+        {
+            let mut start_time = self.recording_start_time.lock().unwrap();
+            *start_time = Some(Instant::now());
+        }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn build_input_stream<T>(
+    fn capture_state(
         &self,
-        device: &cpal::Device,
-        config: &cpal::StreamConfig,
-        last_sound_time: Arc<Mutex<Instant>>,
-        current_max_amplitude: Arc<Mutex<f32>>,
-        threshold: f32,
+        sample_rate: u32,
         chunk_sender: Option<UnboundedSender<Vec<f32>>>,
-        chunk_size: usize,
-    ) -> Result<cpal::Stream, String>
-    where
-        T: cpal::Sample + cpal::SizedSample,
-        f32: cpal::FromSample<T>,
-    {
-        let err_fn = |err| eprintln!("An error occurred on the audio stream: {}", err);
-        let mut local_chunk: Vec<f32> = Vec::with_capacity(chunk_size);
-
-        let stream = device
-            .build_input_stream(
-                config,
-                move |data: &[T], _: &cpal::InputCallbackInfo| {
-                    let mut has_sound = false;
-                    let mut max_amplitude = 0.0f32;
-
-                    for &sample in data.iter() {
-                        let sample_f32: f32 = cpal::Sample::from_sample(sample);
-                        let abs_sample = sample_f32.abs();
-                        max_amplitude = max_amplitude.max(abs_sample);
-
-                        if abs_sample > threshold {
-                            has_sound = true;
-                        }
-
-                        local_chunk.push(sample_f32);
-                        if local_chunk.len() >= chunk_size {
-                            if let Some(ref tx) = chunk_sender {
-                                let _ = tx.send(local_chunk.drain(..).collect());
-                            } else {
-                                local_chunk.clear();
-                            }
-                        }
-                    }
-
-                    {
-                        let mut current_max = current_max_amplitude.lock().unwrap();
-                        *current_max = current_max.max(max_amplitude) * 0.95;
-                    }
-
-                    if has_sound {
-                        let mut last_sound = last_sound_time.lock().unwrap();
-                        *last_sound = Instant::now();
-                    }
-                },
-                err_fn,
-                None,
-            )
-            .map_err(|e| format!("Failed to build input stream: {}", e))?;
+    ) -> CaptureState {
+        CaptureState {
+            last_sound_time: Arc::clone(&self.last_sound_time),
+            level: Some(Arc::clone(&self.level)),
+            monitor: self.monitor.as_ref().map(|(m, _)| Arc::clone(m)),
+            threshold: self.silence_threshold,
+            chunk_sender,
+            chunk_size: (sample_rate as f32 * 0.1) as usize, // 100ms
+            local_chunk: Vec::new(),
+            sample_rate,
+            mixer: None,
+            dsp: self
+                .dsp
+                .is_enabled()
+                .then(|| DspChain::new(&self.dsp, sample_rate)),
+        }
+    }
 
-        Ok(stream)
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
     }
+}
 
-    #[allow(clippy::too_many_arguments)]
-    fn build_input_stream_with_channels<T>(
-        &self,
-        device: &cpal::Device,
-        config: &cpal::StreamConfig,
-        last_sound_time: Arc<Mutex<Instant>>,
-        current_max_amplitude: Arc<Mutex<f32>>,
-        threshold: f32,
-        channels: u16,
-        chunk_sender: Option<UnboundedSender<Vec<f32>>>,
-        chunk_size: usize,
-    ) -> Result<cpal::Stream, String>
-    where
-        T: cpal::Sample + cpal::SizedSample,
-        f32: cpal::FromSample<T>,
-    {
-        let err_fn = |err| eprintln!("An error occurred on the audio stream: {}", err);
-        let mut local_chunk: Vec<f32> = Vec::with_capacity(chunk_size);
-
-        let stream = device
-            .build_input_stream(
-                config,
-                move |data: &[T], _: &cpal::InputCallbackInfo| {
-                    let mut has_sound = false;
-                    let mut max_amplitude = 0.0f32;
-
-                    let mono_samples: Vec<f32> = if channels == 1 {
-                        data.iter()
-                            .map(|&s| <f32 as cpal::Sample>::from_sample(s))
-                            .collect()
-                    } else {
-                        data.chunks_exact(channels as usize)
-                            .map(|chunk| {
-                                let sum: f32 = chunk
-                                    .iter()
-                                    .map(|&s| <f32 as cpal::Sample>::from_sample(s))
-                                    .sum();
-                                sum / channels as f32
-                            })
-                            .collect()
-                    };
-
-                    for mono_sample in mono_samples {
-                        let abs_sample = mono_sample.abs();
-                        max_amplitude = max_amplitude.max(abs_sample);
-                        if abs_sample > threshold {
-                            has_sound = true;
-                        }
-
-                        local_chunk.push(mono_sample);
-                        if local_chunk.len() >= chunk_size {
-                            if let Some(ref tx) = chunk_sender {
-                                let _ = tx.send(local_chunk.drain(..).collect());
-                            } else {
-                                local_chunk.clear();
-                            }
-                        }
-                    }
-
-                    {
-                        let mut current_max = current_max_amplitude.lock().unwrap();
-                        *current_max = current_max.max(max_amplitude) * 0.95;
-                    }
-
-                    if has_sound {
-                        let mut last_sound = last_sound_time.lock().unwrap();
-                        *last_sound = Instant::now();
-                    }
-                },
-                err_fn,
-                None,
-            )
-            .map_err(|e| format!("Failed to build input stream: {}", e))?;
+/// 入力ストリームを開き、mono にしたサンプルを `on_samples` に渡す
+/// (ループバックでも使う)
+pub(crate) fn build_stream(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    sample_format: cpal::SampleFormat,
+    selected_channels: &[usize],
+    on_samples: impl FnMut(Vec<f32>) + Send + 'static,
+    stream_error: Arc<Mutex<Option<String>>>,
+) -> Result<cpal::Stream, String> {
+    let selected_channels = selected_channels.to_vec();
+    match sample_format {
+        cpal::SampleFormat::F32 => {
+            build_input_stream::<f32>(device, config, selected_channels, on_samples, stream_error)
+        }
+        cpal::SampleFormat::I16 => {
+            build_input_stream::<i16>(device, config, selected_channels, on_samples, stream_error)
+        }
+        cpal::SampleFormat::U16 => {
+            build_input_stream::<u16>(device, config, selected_channels, on_samples, stream_error)
+        }
+        _ => Err("Unsupported sample format".to_string()),
+    }
+}
 
-        Ok(stream)
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    selected_channels: Vec<usize>,
+    mut on_samples: impl FnMut(Vec<f32>) + Send + 'static,
+    stream_error: Arc<Mutex<Option<String>>>,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::Sample + cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    // 画面側で検知して録音を終わらせる (ここでは止められない)
+    let err_fn = move |err: cpal::StreamError| {
+        eprintln!("An error occurred on the audio stream: {}", err);
+        let message = match err {
+            cpal::StreamError::DeviceNotAvailable => "Input device disconnected".to_string(),
+            other => other.to_string(),
+        };
+        stream_error.lock().unwrap().get_or_insert(message);
+    };
+    let channels = config.channels.max(1) as usize;
+
+    let stream = device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let mono_samples = downmix(data, channels, &selected_channels);
+                on_samples(mono_samples);
+            },
+            err_fn,
+            None,
+        )
+        .map_err(|e| format!("Failed to build input stream: {}", e))?;
+
+    Ok(stream)
+}
+
+/// フレームごとに `selected` のチャンネル (空なら全部) を平均して mono にする
+fn downmix<T>(data: &[T], channels: usize, selected: &[usize]) -> Vec<f32>
+where
+    T: cpal::Sample,
+    f32: cpal::FromSample<T>,
+{
+    let to_f32 = |s: &T| <f32 as cpal::Sample>::from_sample(*s);
+    if channels == 1 {
+        return data.iter().map(to_f32).collect();
+    }
+    data.chunks_exact(channels)
+        .map(|frame| {
+            if selected.is_empty() {
+                frame.iter().map(to_f32).sum::<f32>() / channels as f32
+            } else {
+                selected.iter().map(|&c| to_f32(&frame[c])).sum::<f32>() / selected.len() as f32
+            }
+        })
+        .collect()
+}
+
+/// 録音コールバックの中で使う状態
+struct CaptureState {
+    last_sound_time: Arc<Mutex<Instant>>,
+    /// メーターへ書くのは 1 つのストリームだけ (LevelMeter::update)
+    level: Option<Arc<LevelMeter>>,
+    monitor: Option<Arc<Mutex<AudioMonitor>>>,
+    threshold: f32,
+    chunk_sender: Option<UnboundedSender<Vec<f32>>>,
+    chunk_size: usize,
+    local_chunk: Vec<f32>,
+    sample_rate: u32,
+    /// InputSource::Mixed で再生音を足す
+    mixer: Option<Arc<Mutex<LoopbackMixer>>>,
+    dsp: Option<DspChain>,
+}
+
+impl CaptureState {
+    /// DSP を通した音声で無音判定・メーター・チャンク送信をする
+    fn process(&mut self, mut samples: Vec<f32>) {
+        // 再生音の取り込み側が足している最中なら待たずにマイクだけにする
+        if let Some(Ok(mut mixer)) = self.mixer.as_ref().map(|m| m.try_lock()) {
+            mixer.mix(&mut samples);
+        }
+        if let Some(dsp) = &mut self.dsp {
+            dsp.process(&mut samples);
+        }
+
+        let mut has_sound = false;
+        let mut block = BlockLevel::default();
+        for sample in samples {
+            if sample.abs() > self.threshold {
+                has_sound = true;
+            }
+            block.add(sample);
+
+            self.local_chunk.push(sample);
+            if self.local_chunk.len() >= self.chunk_size {
+                // 画面が写している間は待たずにこのチャンクの表示を諦める
+                if let Some(Ok(mut monitor)) = self.monitor.as_ref().map(|m| m.try_lock()) {
+                    monitor.push(&self.local_chunk);
+                }
+                if let Some(ref tx) = self.chunk_sender {
+                    let _ = tx.send(std::mem::take(&mut self.local_chunk));
+                } else {
+                    self.local_chunk.clear();
+                }
+            }
+        }
+
+        if let Some(level) = &self.level {
+            level.update(&block, self.sample_rate);
+        }
+
+        if has_sound {
+            let mut last_sound = self.last_sound_time.lock().unwrap();
+            *last_sound = Instant::now();
+        }
     }
 }
 
@@ -392,3 +685,200 @@ pub fn get_input_devices() -> Result<Vec<String>, String> {
 
     Ok(device_names)
 }
+
+/// 録音に使う入力デバイス
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceChoice {
+    /// None = システム既定のデバイス
+    pub name: Option<String>,
+    /// Set when the preferred device was not available
+    pub fallback_from: Option<String>,
+}
+
+impl DeviceChoice {
+    /// 設定のチャンネル・レート・バッファは設定のデバイス用なので、予備のデバイスでは既定の形式で開く
+    pub fn input_format(&self, configured: &InputFormat) -> InputFormat {
+        if self.fallback_from.is_some() {
+            InputFormat::default()
+        } else {
+            configured.clone()
+        }
+    }
+}
+
+/// 設定のデバイスが無ければ、予備のデバイスを順に試し、最後に既定のデバイスを使う
+pub fn choose_input_device(
+    preferred: Option<&str>,
+    fallbacks: &[String],
+    available: &[String],
+    fallback_to_default: bool,
+) -> Result<DeviceChoice, String> {
+    let Some(preferred) = preferred else {
+        return Ok(DeviceChoice {
+            name: None,
+            fallback_from: None,
+        });
+    };
+    if available.iter().any(|d| d == preferred) {
+        return Ok(DeviceChoice {
+            name: Some(preferred.to_string()),
+            fallback_from: None,
+        });
+    }
+    let fallback_from = Some(preferred.to_string());
+    if let Some(name) = fallbacks
+        .iter()
+        .map(|d| d.trim())
+        .find(|d| !d.is_empty() && available.iter().any(|a| a == d))
+    {
+        return Ok(DeviceChoice {
+            name: Some(name.to_string()),
+            fallback_from,
+        });
+    }
+    if fallback_to_default {
+        return Ok(DeviceChoice {
+            name: None,
+            fallback_from,
+        });
+    }
+    Err(format!("Input device '{}' not found", preferred))
+}
+
+/// Pick the input device from the ones connected now (see `choose_input_device`)
+pub fn select_input_device(
+    preferred: Option<&str>,
+    fallbacks: &[String],
+    fallback_to_default: bool,
+) -> Result<DeviceChoice, String> {
+    let available = get_input_devices()?;
+    choose_input_device(preferred, fallbacks, &available, fallback_to_default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn range(channels: u16, min: u32, max: u32) -> cpal::SupportedStreamConfigRange {
+        cpal::SupportedStreamConfigRange::new(
+            channels,
+            cpal::SampleRate(min),
+            cpal::SampleRate(max),
+            cpal::SupportedBufferSize::Range { min: 64, max: 4096 },
+            cpal::SampleFormat::F32,
+        )
+    }
+
+    #[test]
+    fn test_plan_stream() {
+        // 既定は 2ch 48kHz、4ch は 44.1〜96kHz
+        let default = range(2, 48000, 48000).with_sample_rate(cpal::SampleRate(48000));
+        let supported = [range(2, 48000, 48000), range(4, 44100, 96000)];
+        let plan = |format: InputFormat| plan_stream(&format, &default, &supported);
+
+        let average = plan(InputFormat::default()).unwrap();
+        assert_eq!(average.config.channels, 2);
+        assert_eq!(average.config.sample_rate.0, 48000);
+        assert_eq!(average.config.buffer_size, cpal::BufferSize::Default);
+        assert!(average.channels.is_empty());
+
+        let second = plan(InputFormat {
+            channels: vec![2],
+            buffer_size: Some(256),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(second.config.channels, 2);
+        assert_eq!(second.channels, vec![1]);
+        assert_eq!(second.config.buffer_size, cpal::BufferSize::Fixed(256));
+
+        // 3ch 目が要るので 4ch で開く
+        let third = plan(InputFormat {
+            channels: vec![1, 3],
+            sample_rate: Some(96000),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(third.config.channels, 4);
+        assert_eq!(third.config.sample_rate.0, 96000);
+        assert_eq!(third.channels, vec![0, 2]);
+
+        assert!(plan(InputFormat {
+            channels: vec![5],
+            ..Default::default()
+        })
+        .is_err());
+        assert!(plan(InputFormat {
+            sample_rate: Some(8000),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(plan(InputFormat {
+            buffer_size: Some(8192),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_downmix_and_channel_list() {
+        let stereo = [0.2f32, 0.6, -0.4, 0.0];
+        assert_eq!(downmix(&stereo, 2, &[]), vec![0.4, -0.2]);
+        assert_eq!(downmix(&stereo, 2, &[1]), vec![0.6, 0.0]);
+        assert_eq!(downmix(&stereo, 1, &[]), stereo.to_vec());
+
+        assert_eq!(parse_channel_list(""), Ok(vec![]));
+        assert_eq!(parse_channel_list("2"), Ok(vec![2]));
+        assert_eq!(parse_channel_list(" 1, 2 "), Ok(vec![1, 2]));
+        assert!(parse_channel_list("0").is_err());
+        assert!(parse_channel_list("left").is_err());
+    }
+
+    #[test]
+    fn test_choose_input_device() {
+        let available = names(&["Built-in Mic", "USB Headset"]);
+        let fallbacks = names(&["Bluetooth Headset", "USB Headset"]);
+        let choose = |preferred, fallback_to_default| {
+            choose_input_device(preferred, &fallbacks, &available, fallback_to_default)
+        };
+
+        assert_eq!(choose(None, true).unwrap().name, None);
+        assert_eq!(
+            choose(Some("Built-in Mic"), false).unwrap(),
+            DeviceChoice {
+                name: Some("Built-in Mic".to_string()),
+                fallback_from: None
+            }
+        );
+        // 外れていたら予備のうち最初につながっているもの
+        assert_eq!(
+            choose(Some("Studio Mic"), false).unwrap(),
+            DeviceChoice {
+                name: Some("USB Headset".to_string()),
+                fallback_from: Some("Studio Mic".to_string())
+            }
+        );
+
+        let none_left = names(&["Built-in Mic"]);
+        let choice = choose_input_device(Some("Studio Mic"), &fallbacks, &none_left, true).unwrap();
+        assert_eq!(choice.name, None);
+        // Studio Mic 用のチャンネル指定を既定のデバイスには使わない
+        let configured = InputFormat {
+            channels: vec![2],
+            sample_rate: Some(96000),
+            buffer_size: Some(64),
+        };
+        assert_eq!(choice.input_format(&configured), InputFormat::default());
+        assert_eq!(
+            choose(Some("Built-in Mic"), false)
+                .unwrap()
+                .input_format(&configured),
+            configured
+        );
+        assert!(choose_input_device(Some("Studio Mic"), &fallbacks, &none_left, false).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use realfft::{RealFftPlanner, RealToComplex};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// スペクトログラムの FFT の長さとずらし幅 (サンプル数)
+const FFT_SIZE: usize = 512;
+const FFT_HOP: usize = 256;
+
+/// 表示する周波数の上限 (声の帯域だけ見えればよい)
+const SPECTROGRAM_MAX_HZ: f32 = 8000.0;
+
+/// スペクトログラムの縦方向の段数
+pub const SPECTROGRAM_BINS: usize = 96;
+
+/// 直近の録音の波形とスペクトログラム (録音が止まった後も残す)
+/// 録音コールバックが 100ms ごとのチャンクを足し、画面側は短いロックの中で
+/// 自分の複製に新しい分だけ写してから、ロックの外でスペクトログラムを計算して描く
+#[derive(Default)]
+pub struct AudioMonitor {
+    sample_rate: u32,
+    threshold: f32,
+    window_samples: usize,
+    samples: VecDeque<f32>,
+    /// Number of samples pushed since the recording started
+    total_samples: u64,
+    /// `(first sample, voice?)` of each pushed chunk
+    chunks: VecDeque<(u64, bool)>,
+    /// Sample positions where the silence timer stopped the recording
+    silence_timeouts: Vec<u64>,
+    /// Incremented on every reset, so a copy can tell a new recording started
+    generation: u64,
+    fft: Option<Arc<dyn RealToComplex<f32>>>,
+    next_frame: u64,
+    /// `(first sample, level in dB per bin)`
+    columns: VecDeque<(u64, Vec<f32>)>,
+}
+
+impl AudioMonitor {
+    /// Clear everything for a new recording
+    pub fn reset(&mut self, sample_rate: u32, threshold: f32, window_secs: f32) {
+        self.sample_rate = sample_rate;
+        self.threshold = threshold;
+        self.window_samples = (window_secs.max(1.0) * sample_rate as f32) as usize;
+        self.samples.clear();
+        self.total_samples = 0;
+        self.chunks.clear();
+        self.silence_timeouts.clear();
+        self.generation += 1;
+        self.next_frame = 0;
+        self.columns.clear();
+    }
+
+    /// 画面側の複製に、前回から増えた分だけ写す (録音コールバックを待たせないようロック中は軽くする)
+    pub fn copy_new_from(&mut self, shared: &AudioMonitor) {
+        if self.generation != shared.generation {
+            self.sample_rate = shared.sample_rate;
+            self.threshold = shared.threshold;
+            self.window_samples = shared.window_samples;
+            self.samples.clear();
+            self.total_samples = shared.window_start();
+            self.generation = shared.generation;
+            self.next_frame = 0;
+            self.columns.clear();
+        }
+        let mut new = (shared.total_samples - self.total_samples) as usize;
+        if new >= shared.samples.len() {
+            // 間が空いたら手元の分は捨てる
+            self.samples.clear();
+            new = shared.samples.len();
+        }
+        self.samples
+            .extend(shared.samples.range(shared.samples.len() - new..));
+        self.total_samples = shared.total_samples;
+        while self.samples.len() > self.window_samples {
+            self.samples.pop_front();
+        }
+        self.chunks.clone_from(&shared.chunks);
+        self.silence_timeouts.clone_from(&shared.silence_timeouts);
+    }
+
+    /// Called from the audio callback with each mono chunk
+    pub fn push(&mut self, chunk: &[f32]) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        let voice = chunk.iter().any(|s| s.abs() > self.threshold);
+        self.chunks.push_back((self.total_samples, voice));
+        self.samples.extend(chunk.iter().copied());
+        self.total_samples += chunk.len() as u64;
+
+        while self.samples.len() > self.window_samples {
+            self.samples.pop_front();
+        }
+        let start = self.window_start();
+        // 先頭のチャンクは表示範囲にかかっている間は残す
+        while self.chunks.len() > 1 && self.chunks[1].0 <= start {
+            self.chunks.pop_front();
+        }
+        self.silence_timeouts.retain(|&pos| pos >= start);
+    }
+
+    /// 無音で録音を止めた位置を記録する
+    pub fn mark_silence_timeout(&mut self) {
+        self.silence_timeouts.push(self.total_samples);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Sample position of the oldest sample still kept
+    pub fn window_start(&self) -> u64 {
+        self.total_samples - self.samples.len() as u64
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    pub fn window_samples(&self) -> usize {
+        self.window_samples
+    }
+
+    pub fn samples(&self) -> &VecDeque<f32> {
+        &self.samples
+    }
+
+    /// Voice / silence regions as `(start, end, voice)` sample ranges
+    pub fn regions(&self) -> Vec<(u64, u64, bool)> {
+        let ends = self
+            .chunks
+            .iter()
+            .skip(1)
+            .map(|&(start, _)| start)
+            .chain(std::iter::once(self.total_samples));
+        self.chunks
+            .iter()
+            .zip(ends)
+            .map(|(&(start, voice), end)| (start.max(self.window_start()), end, voice))
+            .collect()
+    }
+
+    pub fn silence_timeouts(&self) -> &[u64] {
+        &self.silence_timeouts
+    }
+
+    pub fn columns(&self) -> &VecDeque<(u64, Vec<f32>)> {
+        &self.columns
+    }
+
+    /// 新しく届いたサンプルのスペクトログラムを計算する (画面の更新ごとに呼ぶ)
+    pub fn update_spectrogram(&mut self) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        let fft = Arc::clone(
+            self.fft
+                .get_or_insert_with(|| RealFftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE)),
+        );
+        let window_start = self.window_start();
+        // 表示範囲から外れた分は計算しない
+        self.next_frame = self.next_frame.max(window_start);
+
+        let mut input = fft.make_input_vec();
+        let mut spectrum = fft.make_output_vec();
+        while self.next_frame + FFT_SIZE as u64 <= self.total_samples {
+            let offset = (self.next_frame - window_start) as usize;
+            for (i, (value, sample)) in input
+                .iter_mut()
+                .zip(self.samples.range(offset..offset + FFT_SIZE))
+                .enumerate()
+            {
+                *value = sample * hann(i, FFT_SIZE);
+            }
+            if fft.process(&mut input, &mut spectrum).is_ok() {
+                let magnitudes: Vec<f32> = spectrum
+                    .iter()
+                    .map(|c| c.norm() / (FFT_SIZE as f32 / 4.0))
+                    .collect();
+                let column = reduce_bins(&magnitudes, self.sample_rate);
+                self.columns.push_back((self.next_frame, column));
+            }
+            self.next_frame += FFT_HOP as u64;
+        }
+        while self
+            .columns
+            .front()
+            .is_some_and(|&(start, _)| start < window_start)
+        {
+            self.columns.pop_front();
+        }
+    }
+}
+
+fn hann(i: usize, len: usize) -> f32 {
+    0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / len as f32).cos()
+}
+
+/// FFT の結果を `SPECTROGRAM_BINS` 段にまとめて dB にする (各段の最大値)
+fn reduce_bins(magnitudes: &[f32], sample_rate: u32) -> Vec<f32> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let max_hz = SPECTROGRAM_MAX_HZ.min(nyquist);
+    let usable = ((magnitudes.len() - 1) as f32 * max_hz / nyquist).ceil() as usize;
+    (0..SPECTROGRAM_BINS)
+        .map(|bin| {
+            let from = 1 + bin * usable / SPECTROGRAM_BINS;
+            let to = (1 + (bin + 1) * usable / SPECTROGRAM_BINS).max(from + 1);
+            let peak = magnitudes[from.min(magnitudes.len() - 1)..to.min(magnitudes.len())]
+                .iter()
+                .fold(0.0f32, |a, &b| a.max(b));
+            crate::level_meter::amplitude_to_dbfs(peak)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_last_window_and_regions() {
+        let mut monitor = AudioMonitor::default();
+        monitor.reset(1000, 0.1, 1.0);
+        monitor.push(&[0.5; 400]);
+        monitor.push(&[0.0; 400]);
+        monitor.mark_silence_timeout();
+        monitor.push(&[0.0; 400]);
+
+        assert_eq!(monitor.samples().len(), 1000);
+        assert_eq!(monitor.window_start(), 200);
+        assert_eq!(
+            monitor.regions(),
+            vec![(200, 400, true), (400, 800, false), (800, 1200, false)]
+        );
+        assert_eq!(monitor.silence_timeouts(), &[800]);
+
+        monitor.push(&[0.0; 1000]);
+        assert!(monitor.silence_timeouts().is_empty());
+        assert_eq!(monitor.regions(), vec![(1200, 2200, false)]);
+    }
+
+    #[test]
+    fn test_copy_takes_only_new_samples() {
+        let mut shared = AudioMonitor::default();
+        let mut view = AudioMonitor::default();
+        shared.reset(1000, 0.1, 1.0);
+        shared.push(&[0.5; 400]);
+        view.copy_new_from(&shared);
+        shared.push(&[0.0; 400]);
+        shared.mark_silence_timeout();
+        shared.push(&[0.2; 400]);
+        view.copy_new_from(&shared);
+        assert_eq!(view.samples(), shared.samples());
+        assert_eq!(view.window_start(), 200);
+        assert_eq!(view.regions(), shared.regions());
+        assert_eq!(view.silence_timeouts(), &[800]);
+
+        // 新しい録音が始まったら前の分は捨てる
+        shared.reset(1000, 0.1, 1.0);
+        shared.push(&[0.3; 100]);
+        view.copy_new_from(&shared);
+        assert_eq!(view.samples(), shared.samples());
+        assert_eq!(view.total_samples(), 100);
+    }
+
+    #[test]
+    fn test_spectrogram_finds_tone() {
+        let sample_rate = 16000;
+        let mut monitor = AudioMonitor::default();
+        monitor.reset(sample_rate, 0.01, 1.0);
+        // 1 kHz のサイン波
+        let tone: Vec<f32> = (0..4000)
+            .map(|i| {
+                (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate as f32).sin() * 0.5
+            })
+            .collect();
+        monitor.push(&tone);
+        monitor.update_spectrogram();
+
+        let columns = monitor.columns();
+        assert_eq!(columns.len(), (4000 - FFT_SIZE) / FFT_HOP + 1);
+        let (_, column) = &columns[0];
+        let loudest = column
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(bin, _)| bin)
+            .unwrap();
+        // 0〜8 kHz を 96 段に分けているので 1 kHz は 12 段目あたり
+        assert!((11..=12).contains(&loudest), "loudest bin {}", loudest);
+        assert!(column[loudest] > -12.0);
+        assert!(column[SPECTROGRAM_BINS - 1] < -40.0);
+    }
+}
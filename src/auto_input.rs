@@ -11,8 +11,9 @@ pub fn call_qvpen() -> Result<(), String> {
     Ok(())
 }
 
+/// Synchronous version of call_qvpen (blocks while switching windows)
 #[cfg(windows)]
-fn call_qvpen_sync() -> Result<(), String> {
+pub fn call_qvpen_sync() -> Result<(), String> {
     use windows_sys::Win32::UI::WindowsAndMessaging::{FindWindowW, SetForegroundWindow};
 
     let title: Vec<u16> = "VRChat\0".encode_utf16().collect();
@@ -41,7 +42,7 @@ fn call_qvpen_sync() -> Result<(), String> {
 }
 
 #[cfg(not(windows))]
-fn call_qvpen_sync() -> Result<(), String> {
+pub fn call_qvpen_sync() -> Result<(), String> {
     Err("call_qvpen is only supported on Windows".to_string())
 }
 
@@ -97,8 +98,8 @@ pub fn type_text_with_enter(text: &str) -> Result<(), String> {
     Ok(())
 }
 
-/// Internal synchronous implementation of text typing
-fn type_text_sync(text: &str) -> Result<(), String> {
+/// Synchronous implementation of text typing (blocks until typed)
+pub fn type_text_sync(text: &str) -> Result<(), String> {
     let mut enigo =
         Enigo::new(&Settings::default()).map_err(|e| format!("Failed to create Enigo: {:?}", e))?;
 
@@ -107,7 +108,7 @@ fn type_text_sync(text: &str) -> Result<(), String> {
 
     // Type the text using the text() method which handles Unicode properly
     enigo
-        .text(&text)
+        .text(text)
         .map_err(|e| format!("Failed to type text: {:?}", e))?;
 
     Ok(())
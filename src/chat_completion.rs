@@ -0,0 +1,356 @@
+use crate::assistant::{self, Assistant, AssistantReply};
+use crate::conversation::{ChatMessage, ToolCall};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    stream: bool,
+    #[serde(skip_serializing_if = "<[Value]>::is_empty")]
+    tools: &'a [Value],
+}
+
+/// OpenAI 互換の `/v1/chat/completions` を話すクライアント
+/// (llama.cpp server, Ollama などのローカルサーバーも想定)
+pub struct ChatCompletionClient {
+    base_url: String,
+    api_key: String,
+    model: String,
+    system_prompt: String,
+    temperature: f32,
+    stream: bool,
+    /// `tools` パラメータに渡す関数定義 (空ならツール呼び出しなし)
+    tools: Vec<Value>,
+}
+
+impl ChatCompletionClient {
+    pub fn new(
+        base_url: String,
+        api_key: String,
+        model: String,
+        system_prompt: String,
+        temperature: f32,
+        stream: bool,
+        tools: Vec<Value>,
+    ) -> Self {
+        Self {
+            base_url,
+            api_key,
+            model,
+            system_prompt,
+            temperature,
+            stream,
+            tools,
+        }
+    }
+
+    /// システムプロンプトを先頭に付けたメッセージ列
+    fn request_messages(&self, messages: &[ChatMessage]) -> Vec<ChatMessage> {
+        let mut request_messages = Vec::with_capacity(messages.len() + 1);
+        if !self.system_prompt.trim().is_empty() {
+            request_messages.push(ChatMessage::system(&self.system_prompt));
+        }
+        request_messages.extend_from_slice(messages);
+        request_messages
+    }
+}
+
+/// ストリームのチャンクなら `choices[0].delta`、通常の応答なら `choices[0].message`
+fn choice_message(value: &Value) -> Option<&Value> {
+    let choice = value.get("choices")?.get(0)?;
+    choice.get("delta").or_else(|| choice.get("message"))
+}
+
+/// `tool_calls` の断片を index ごとに連結する
+/// (ストリームでは id と name が最初の断片に、arguments が分割されて届く)
+fn merge_tool_calls(tool_calls: &mut Vec<ToolCall>, fragments: &[Value]) {
+    for (position, fragment) in fragments.iter().enumerate() {
+        let index = fragment["index"].as_u64().map_or(position, |i| i as usize);
+        if tool_calls.len() <= index {
+            tool_calls.resize_with(index + 1, || ToolCall {
+                call_type: "function".to_string(),
+                ..Default::default()
+            });
+        }
+        let call = &mut tool_calls[index];
+        if let Some(id) = fragment["id"].as_str() {
+            call.id = id.to_string();
+        }
+        if let Some(name) = fragment.pointer("/function/name").and_then(Value::as_str) {
+            call.function.name.push_str(name);
+        }
+        if let Some(arguments) = fragment
+            .pointer("/function/arguments")
+            .and_then(Value::as_str)
+        {
+            call.function.arguments.push_str(arguments);
+        }
+    }
+}
+
+impl Assistant for ChatCompletionClient {
+    /// Send the conversation to the chat completion endpoint and return the reply
+    fn stream_chat(
+        &self,
+        messages: &[ChatMessage],
+        on_delta: &mut dyn FnMut(&str),
+    ) -> Result<AssistantReply, String> {
+        let request = ChatCompletionRequest {
+            model: &self.model,
+            messages: self.request_messages(messages),
+            temperature: self.temperature,
+            stream: self.stream,
+            tools: &self.tools,
+        };
+
+        let endpoint = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let mut builder = assistant::http_client().post(&endpoint).json(&request);
+        if !self.api_key.is_empty() {
+            builder = builder.bearer_auth(&self.api_key);
+        }
+        let response = builder
+            .send()
+            .map_err(|e| format!("Failed to send to chat completion API: {}", e))?;
+
+        let mut reply = AssistantReply::default();
+        assistant::read_response(response, &mut |chunk| {
+            let Some(message) = choice_message(chunk) else {
+                return;
+            };
+            if let Some(delta) = message["content"].as_str().filter(|d| !d.is_empty()) {
+                on_delta(delta);
+                reply.content.push_str(delta);
+            }
+            if let Some(fragments) = message["tool_calls"].as_array() {
+                merge_tool_calls(&mut reply.tool_calls, fragments);
+            }
+        })
+        .map_err(|e| format!("Chat completion API error: {}", e))?;
+        if reply.content.is_empty() && reply.tool_calls.is_empty() {
+            return Err("Chat completion API returned an empty reply".to_string());
+        }
+        println!(
+            "[ChatCompletionClient] Response from {}: {} ({} tool calls)",
+            endpoint,
+            reply.content,
+            reply.tool_calls.len()
+        );
+        Ok(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// 1リクエストだけ受けて固定のレスポンスを返すモックサーバー
+    /// 受け取ったリクエストの (パス, Authorization, ボディ) を返す
+    fn mock_server(
+        status: &'static str,
+        content_type: &'static str,
+        body: &'static str,
+    ) -> (String, std::thread::JoinHandle<(String, String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            let mut authorization = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                let lower = line.to_lowercase();
+                if let Some(v) = lower.strip_prefix("content-length:") {
+                    content_length = v.trim().parse().unwrap();
+                }
+                if lower.starts_with("authorization:") {
+                    authorization = line["authorization:".len()..].trim().to_string();
+                }
+            }
+            let mut request_body = vec![0u8; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                body.len(),
+                body
+            )
+            .unwrap();
+            let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+            (
+                path,
+                authorization,
+                String::from_utf8(request_body).unwrap(),
+            )
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_send_chat_with_mock_server() {
+        let (url, server) = mock_server(
+            "200 OK",
+            "application/json",
+            r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"こんにちは！"}}]}"#,
+        );
+        let client = ChatCompletionClient::new(
+            url,
+            "secret".to_string(),
+            "llama3".to_string(),
+            "You are a helpful assistant.".to_string(),
+            0.3,
+            false,
+            Vec::new(),
+        );
+
+        let reply = client
+            .stream_chat(&[ChatMessage::user("やあ")], &mut |_| {})
+            .unwrap();
+        assert_eq!(reply.content, "こんにちは！");
+
+        let (path, authorization, body) = server.join().unwrap();
+        assert_eq!(path, "/v1/chat/completions");
+        assert_eq!(authorization, "Bearer secret");
+        let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(request["model"], "llama3");
+        assert!((request["temperature"].as_f64().unwrap() - 0.3).abs() < 1e-6);
+        assert_eq!(request["messages"][0]["role"], "system");
+        assert_eq!(request["messages"][1]["content"], "やあ");
+    }
+
+    #[test]
+    fn test_send_chat_reports_http_error() {
+        let (url, server) = mock_server(
+            "500 Internal Server Error",
+            "application/json",
+            r#"{"error":"boom"}"#,
+        );
+        let client = ChatCompletionClient::new(
+            url,
+            String::new(),
+            "llama3".to_string(),
+            String::new(),
+            0.7,
+            false,
+            Vec::new(),
+        );
+
+        let err = client
+            .stream_chat(&[ChatMessage::user("やあ")], &mut |_| {})
+            .unwrap_err();
+        assert!(err.contains("500"), "{}", err);
+
+        let (_, authorization, body) = server.join().unwrap();
+        assert!(authorization.is_empty());
+        let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+        // system prompt が空なら system メッセージは付けない
+        assert_eq!(request["messages"][0]["role"], "user");
+    }
+
+    #[test]
+    fn test_stream_chat_with_sse() {
+        let (url, server) = mock_server(
+            "200 OK",
+            "text/event-stream",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n\
+             data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"こんに\"}}]}\n\n\
+             data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"ちは！\"}}]}\n\n\
+             data: [DONE]\n\n",
+        );
+        let client = ChatCompletionClient::new(
+            url,
+            String::new(),
+            "llama3".to_string(),
+            String::new(),
+            0.7,
+            true,
+            Vec::new(),
+        );
+
+        let mut deltas = Vec::new();
+        let reply = client
+            .stream_chat(&[ChatMessage::user("やあ")], &mut |d| {
+                deltas.push(d.to_string())
+            })
+            .unwrap();
+        assert_eq!(reply.content, "こんにちは！");
+        assert_eq!(deltas, vec!["こんに", "ちは！"]);
+
+        let (_, _, body) = server.join().unwrap();
+        let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(request["stream"], true);
+    }
+
+    #[test]
+    fn test_stream_tool_calls() {
+        let (url, server) = mock_server(
+            "200 OK",
+            "text/event-stream",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"send_chatbox\",\"arguments\":\"\"}}]}}]}\n\n\
+             data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"text\\\":\"}}]}}]}\n\n\
+             data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"hi\\\"}\"}}]}}]}\n\n\
+             data: [DONE]\n\n",
+        );
+        let tools = crate::tools::tool_schemas(&crate::tools::default_tools());
+        let client = ChatCompletionClient::new(
+            url,
+            String::new(),
+            "llama3".to_string(),
+            String::new(),
+            0.7,
+            true,
+            tools,
+        );
+
+        let reply = client
+            .stream_chat(&[ChatMessage::user("chatbox に hi と出して")], &mut |_| {})
+            .unwrap();
+        assert_eq!(reply.content, "");
+        assert_eq!(reply.tool_calls.len(), 1);
+        assert_eq!(reply.tool_calls[0].id, "call_1");
+        assert_eq!(reply.tool_calls[0].function.name, "send_chatbox");
+        assert_eq!(reply.tool_calls[0].function.arguments, r#"{"text":"hi"}"#);
+
+        let (_, _, body) = server.join().unwrap();
+        let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(request["tools"][1]["function"]["name"], "send_chatbox");
+    }
+
+    #[test]
+    fn test_tool_result_messages_are_serialized() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            call_type: "function".to_string(),
+            function: crate::conversation::FunctionCall {
+                name: "call_qvpen".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+        let messages = [
+            ChatMessage::assistant_tool_calls("", vec![call]),
+            ChatMessage::tool_result("call_1", "called"),
+        ];
+        let json = serde_json::to_value(&messages).unwrap();
+        assert_eq!(json[0]["tool_calls"][0]["type"], "function");
+        assert_eq!(json[1]["role"], "tool");
+        assert_eq!(json[1]["tool_call_id"], "call_1");
+        // 通常のメッセージには余計なフィールドを付けない
+        let user = serde_json::to_value(ChatMessage::user("やあ")).unwrap();
+        assert_eq!(user.as_object().unwrap().len(), 2);
+    }
+}
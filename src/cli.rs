@@ -0,0 +1,511 @@
+use crate::audio::{self, AudioRecorder, InputSource};
+use crate::config::{Config, Override};
+use crate::speech_to_text::SpeechToTextClient;
+use crate::tts;
+use crate::TranscriptionMessage;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 既定の入力デバイスを表す名前 (設定画面の選択肢と同じ扱い)
+const DEFAULT_DEVICE_NAMES: [&str; 2] = ["default", audio::DEFAULT_DEVICE_LABEL];
+
+/// Voice transcription for Windows 11 and VRChat.
+/// Without a subcommand the GUI is started.
+#[derive(Debug, Parser, PartialEq)]
+#[command(name = "winh", version)]
+pub struct Cli {
+    /// Config file to use instead of the default location
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Profile to switch to on startup
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Input device for this run ("default" for the system default)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub device: Option<String>,
+    /// Speech recognition language for this run (e.g. ja, en)
+    #[arg(long, global = true, value_name = "LANG")]
+    pub language: Option<String>,
+    /// Recording hotkey for this run (e.g. Ctrl+Shift+H)
+    #[arg(long, global = true, value_name = "KEYS")]
+    pub hotkey: Option<String>,
+    /// Do not send to VRChat in this run
+    #[arg(long, global = true)]
+    pub no_vrchat: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum Command {
+    /// List audio input devices
+    ListDevices,
+    /// Transcribe a WAV file and print the text
+    Transcribe {
+        /// WAV file to transcribe
+        file: PathBuf,
+    },
+    /// Record from the microphone and print the text
+    Record {
+        /// Stop after one utterance (recording ends on silence)
+        #[arg(long)]
+        once: bool,
+    },
+    /// Show or change settings in the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum ConfigAction {
+    /// Print a setting (or every setting if KEY is omitted)
+    Get { key: Option<String> },
+    /// Change a setting; VALUE is parsed as JSON, otherwise taken as a string
+    Set { key: String, value: String },
+    /// Print the config file location
+    Path,
+}
+
+impl Cli {
+    /// Parse arguments (exits with usage on error or `--help`)
+    pub fn parse_args(args: Vec<String>) -> Self {
+        match Self::try_parse_from(args) {
+            Ok(cli) => cli,
+            Err(e) => e.exit(),
+        }
+    }
+
+    /// Apply `--profile` and the per-run overrides to the loaded config
+    pub fn apply_to(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(profile) = &self.profile {
+            config.select_profile(profile)?;
+        }
+        if let Some(device) = &self.device {
+            let device = device.trim();
+            let device = if DEFAULT_DEVICE_NAMES.contains(&device) {
+                None
+            } else {
+                Some(device.to_string())
+            };
+            config.apply_override(Override::InputDevice(device));
+        }
+        if let Some(language) = &self.language {
+            config.apply_override(Override::Language(language.trim().to_string()));
+        }
+        if let Some(hotkey) = &self.hotkey {
+            Config::parse_hotkey_str(hotkey)?;
+            config.apply_override(Override::Hotkey(hotkey.trim().to_string()));
+        }
+        if self.no_vrchat {
+            config.apply_override(Override::VrchatEnabled(false));
+        }
+        Ok(())
+    }
+}
+
+/// Run a subcommand and return the process exit code
+pub fn run(command: Command, config: Config) -> i32 {
+    let result = match command {
+        Command::ListDevices => list_devices(&config),
+        Command::Transcribe { file } => transcribe_file(&config, &file),
+        Command::Record { once } => record(&config, once),
+        Command::Config { action } => run_config(config, action),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn list_devices(config: &Config) -> Result<(), String> {
+    for name in audio::get_input_devices()? {
+        let marker = if config.input_device_name.as_deref() == Some(name.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{} {}", marker, name);
+    }
+    Ok(())
+}
+
+fn transcribe_file(config: &Config, file: &PathBuf) -> Result<(), String> {
+    let bytes = std::fs::read(file).map_err(|e| format!("Failed to read {:?}: {}", file, e))?;
+    let pcm = tts::decode_wav(&bytes)?;
+    let (chunk_tx, chunk_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+    // 100ms ずつ送る (送り終えたら sender を drop して audio.done にする)
+    let chunk_len = (pcm.sample_rate as usize / 10).max(1);
+    for chunk in pcm.samples.chunks(chunk_len) {
+        let _ = chunk_tx.send(chunk.to_vec());
+    }
+    drop(chunk_tx);
+
+    let text = transcribe(config, pcm.sample_rate, chunk_rx, None, || {})?;
+    println!("{}", text);
+    Ok(())
+}
+
+fn record(config: &Config, once: bool) -> Result<(), String> {
+    loop {
+        let text = record_utterance(config)?;
+        println!("{}", text);
+        if once {
+            return Ok(());
+        }
+    }
+}
+
+/// 無音になるまで録音して文字起こしする
+fn record_utterance(config: &Config) -> Result<String, String> {
+    let mut recorder = AudioRecorder::new(config.silence_threshold)?;
+    recorder.set_dsp(config.dsp.clone());
+    let source = config.input_source;
+    let device_name = if source.uses_microphone() {
+        let device = audio::select_input_device(
+            config
+                .input_device_name
+                .as_deref()
+                .filter(|name| !DEFAULT_DEVICE_NAMES.contains(name)),
+            &config.input_device_fallbacks,
+            config.input_device_fallback_to_default,
+        )?;
+        if let Some(missing) = &device.fallback_from {
+            eprintln!(
+                "Input device '{}' not found, using {}",
+                missing,
+                device.name.as_deref().unwrap_or("the default device")
+            );
+        }
+        recorder.set_input_format(device.input_format(&config.input_format));
+        device.name
+    } else {
+        None
+    };
+    let (chunk_tx, chunk_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+    let (system_tx, system_rx) = if source == InputSource::Separate {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+    recorder.start_recording_from_source(
+        source,
+        device_name.as_deref(),
+        Some(chunk_tx),
+        system_tx,
+    )?;
+    eprintln!(
+        "Recording... (stops after {:.1}s of silence)",
+        config.silence_duration_secs
+    );
+
+    let sample_rate = recorder.get_sample_rate();
+    transcribe(config, sample_rate, chunk_rx, system_rx, move || {
+        while !recorder.is_silent(config.silence_duration_secs) {
+            if let Some(e) = recorder.stream_error() {
+                eprintln!("{} - finishing with the audio captured so far", e);
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        recorder.stop_recording();
+        eprintln!("Transcribing...");
+    })
+}
+
+/// Stream audio chunks to the STT service; `wait_for_audio` runs while the
+/// transcription is in progress and should return once the audio is finished
+/// `system_rx` があれば再生音も別に文字起こしして "me" / "them" のラベルを付ける
+fn transcribe(
+    config: &Config,
+    sample_rate: u32,
+    chunk_rx: tokio::sync::mpsc::UnboundedReceiver<Vec<f32>>,
+    system_rx: Option<tokio::sync::mpsc::UnboundedReceiver<Vec<f32>>>,
+    wait_for_audio: impl FnOnce(),
+) -> Result<String, String> {
+    if config.xai_api_key.is_empty() {
+        return Err("xAI API key not set (set XAI_API_KEY or configure it in the GUI)".to_string());
+    }
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Failed to start tokio runtime: {}", e))?;
+
+    let (msg_tx, mut msg_rx) = tokio::sync::mpsc::unbounded_channel::<TranscriptionMessage>();
+    let client = SpeechToTextClient::new(
+        config.xai_api_key.clone(),
+        config.stt_language.clone(),
+        config.remove_punctuation,
+    );
+    let task = rt.spawn(async move {
+        if let Err(e) = client
+            .stream_transcribe_recording(sample_rate, chunk_rx, system_rx, msg_tx.clone())
+            .await
+        {
+            let _ = msg_tx.send(TranscriptionMessage::Error(e.to_string()));
+        }
+    });
+
+    wait_for_audio();
+
+    rt.block_on(async {
+        let _ = task.await;
+        let mut result = Err("No transcription result".to_string());
+        while let Some(message) = msg_rx.recv().await {
+            match message {
+                TranscriptionMessage::Success(text) => result = Ok(text),
+                TranscriptionMessage::Error(e) => result = Err(e),
+                TranscriptionMessage::InProgress
+                | TranscriptionMessage::Partial(_)
+                | TranscriptionMessage::Segment(_) => {}
+            }
+        }
+        result
+    })
+}
+
+fn run_config(mut config: Config, action: ConfigAction) -> Result<(), String> {
+    match action {
+        ConfigAction::Path => {
+            let path = match &config.path {
+                Some(path) => path.clone(),
+                None => Config::config_path()?,
+            };
+            println!("{}", path.display());
+        }
+        ConfigAction::Get { key } => {
+            let value = config_value(&config)?;
+            let output = match key {
+                Some(key) => value
+                    .get(&key)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown setting: {}", key))?,
+                None => value,
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).map_err(|e| e.to_string())?
+            );
+        }
+        ConfigAction::Set { key, value } => {
+            config = set_config_value(&config, &key, &value)?;
+            config.save()?;
+            println!("{} = {}", key, value);
+        }
+    }
+    Ok(())
+}
+
+/// `config get` で伏せる設定
+const SECRET_KEYS: &[&str] = &[
+    "xai_api_key",
+    "chat_completion_api_key",
+    "translation_api_key",
+    "rewrite_api_key",
+];
+
+/// 表示用の設定 (API キーは伏せる)
+fn config_value(config: &Config) -> Result<serde_json::Value, String> {
+    let mut value = serde_json::to_value(config).map_err(|e| e.to_string())?;
+    for key in SECRET_KEYS {
+        if value
+            .get(*key)
+            .and_then(|v| v.as_str())
+            .is_some_and(|v| !v.is_empty())
+        {
+            value[*key] = serde_json::Value::from("********");
+        }
+    }
+    Ok(value)
+}
+
+/// Return a copy of the config with one top-level setting changed
+pub fn set_config_value(config: &Config, key: &str, value: &str) -> Result<Config, String> {
+    // 引数は他のプロセスから見えるので、xAI API キーは受け付けない
+    if key == "xai_api_key" {
+        return Err(
+            "Refusing to take the xAI API key as an argument; set XAI_API_KEY or enter it in Settings"
+                .to_string(),
+        );
+    }
+    // コマンドラインでの一時的な上書きは保存しない
+    let mut base = config.clone();
+    base.restore_overrides();
+    let mut json = serde_json::to_value(&base).map_err(|e| e.to_string())?;
+    let object = json.as_object_mut().ok_or("Config is not a JSON object")?;
+    if !object.contains_key(key) {
+        return Err(format!("Unknown setting: {}", key));
+    }
+    let parsed = serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::from(value));
+    object.insert(key.to_string(), parsed);
+
+    let mut updated: Config =
+        serde_json::from_value(json).map_err(|e| format!("Invalid value for {}: {}", key, e))?;
+    updated.path = config.path.clone();
+    // プロファイル単位の項目は選択中のプロファイルにも反映する
+    updated.sync_active_profile();
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(args)
+    }
+
+    #[test]
+    fn test_cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_gui_with_overrides() {
+        let cli = parse(&[
+            "winh",
+            "--config",
+            "my.json",
+            "--profile",
+            "english",
+            "--device",
+            "USB Mic",
+            "--language",
+            "en",
+            "--hotkey",
+            "Alt+1",
+            "--no-vrchat",
+        ])
+        .unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("my.json")));
+        assert_eq!(cli.profile.as_deref(), Some("english"));
+        assert_eq!(cli.device.as_deref(), Some("USB Mic"));
+        assert_eq!(cli.language.as_deref(), Some("en"));
+        assert_eq!(cli.hotkey.as_deref(), Some("Alt+1"));
+        assert!(cli.no_vrchat);
+        assert_eq!(cli.command, None);
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        assert_eq!(
+            parse(&["winh", "list-devices"]).unwrap().command,
+            Some(Command::ListDevices)
+        );
+        assert_eq!(
+            parse(&["winh", "transcribe", "a.wav", "--language", "en"])
+                .unwrap()
+                .command,
+            Some(Command::Transcribe {
+                file: PathBuf::from("a.wav")
+            })
+        );
+        assert_eq!(
+            parse(&["winh", "record", "--once"]).unwrap().command,
+            Some(Command::Record { once: true })
+        );
+        assert_eq!(
+            parse(&["winh", "config", "set", "silence_threshold", "0.02"])
+                .unwrap()
+                .command,
+            Some(Command::Config {
+                action: ConfigAction::Set {
+                    key: "silence_threshold".to_string(),
+                    value: "0.02".to_string()
+                }
+            })
+        );
+        assert!(parse(&["winh", "transcribe"]).is_err());
+        assert!(parse(&["winh", "--unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_help_lists_subcommands() {
+        let err = parse(&["winh", "--help"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayHelp);
+        let help = Cli::command().render_help().to_string();
+        for word in [
+            "list-devices",
+            "transcribe",
+            "record",
+            "config",
+            "--no-vrchat",
+        ] {
+            assert!(help.contains(word), "{} missing from help", word);
+        }
+    }
+
+    #[test]
+    fn test_api_key_is_not_accepted_as_argument() {
+        // 引数は他のプロセスから見えるので、キーは環境変数か資格情報ストアからだけ読む
+        assert!(parse(&["winh", "--xai-api-key=xai-1"]).is_err());
+        assert!(parse(&["winh", "XAI_API_KEY=xai-2"]).is_err());
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut config = Config::default();
+        config.ensure_profiles();
+        config.vrchat_enabled = true;
+        let cli = parse(&[
+            "winh",
+            "--device",
+            "default",
+            "--language",
+            "en",
+            "--no-vrchat",
+        ])
+        .unwrap();
+        cli.apply_to(&mut config).unwrap();
+        assert_eq!(config.input_device_name, None);
+        assert_eq!(config.stt_language, "en");
+        assert!(!config.vrchat_enabled);
+
+        assert!(parse(&["winh", "--profile", "missing"])
+            .unwrap()
+            .apply_to(&mut config)
+            .is_err());
+        assert!(parse(&["winh", "--hotkey", "Nope+Q"])
+            .unwrap()
+            .apply_to(&mut config)
+            .is_err());
+    }
+
+    #[test]
+    fn test_set_config_value() {
+        let mut config = Config::default();
+        config.ensure_profiles();
+        let updated = set_config_value(&config, "silence_threshold", "0.05").unwrap();
+        assert_eq!(updated.silence_threshold, 0.05);
+        assert_eq!(updated.profiles[0].silence_threshold, 0.05);
+        let updated = set_config_value(&config, "eliza_url", "http://example:9096").unwrap();
+        assert_eq!(updated.eliza_url, "http://example:9096");
+        assert!(set_config_value(&config, "no_such_setting", "1").is_err());
+        assert!(set_config_value(&config, "silence_threshold", "loud").is_err());
+    }
+
+    #[test]
+    fn test_config_keeps_api_keys_out_of_arguments_and_output() {
+        let mut config = Config::default();
+        config.ensure_profiles();
+        assert!(set_config_value(&config, "xai_api_key", "xai-1").is_err());
+        config.xai_api_key = "xai-1".to_string();
+        assert!(set_config_value(&config, "xai_api_key", "xai-2").is_err());
+
+        config.chat_completion_api_key = "sk-chat".to_string();
+        config.translation_api_key = "sk-translate".to_string();
+        config.rewrite_api_key = "sk-rewrite".to_string();
+        let shown = config_value(&config).unwrap().to_string();
+        for secret in ["xai-1", "sk-chat", "sk-translate", "sk-rewrite"] {
+            assert!(!shown.contains(secret), "{} is shown", secret);
+        }
+    }
+}
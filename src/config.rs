@@ -1,18 +1,65 @@
+use crate::assistant::AssistantBackend;
+use crate::audio::{InputFormat, InputSource};
+use crate::dictation::ContinuousSettings;
+use crate::dsp::DspSettings;
+use crate::foreground::ProfileRule;
+use crate::migration::{self, CONFIG_VERSION};
+use crate::osc_trigger::{self, OscTriggerRule};
+use crate::profile::{Profile, DEFAULT_PROFILE_NAME};
+use crate::rewrite::{self, RewriteTemplate};
+use crate::secrets::{self, KeyringStore, SecretStore, XAI_API_KEY};
+use crate::tools::{self, ToolDefinition};
+use crate::translation::{
+    TranslationBackend, TranslationHotkey, TranslationLanguages, TranslationOutput,
+};
+use crate::tts::{self, TtsEngine};
+use crate::vrchat::{self, AvatarStateBinding};
 use global_hotkey::hotkey::{Code, HotKey, Modifiers};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// 設定ファイルの形式のバージョン (無ければ 0、読み込み時に migration で上げる)
     #[serde(default)]
+    pub config_version: u32,
+    /// 資格情報ストアに保存する (ストアが使えない環境でだけ設定ファイルに残る)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub xai_api_key: String,
     pub silence_duration_secs: f32,
     pub silence_threshold: f32,
+    /// マイク・システムの再生音 (ループバック)・その両方
+    #[serde(default)]
+    pub input_source: InputSource,
     #[serde(default)]
     pub input_device_name: Option<String>,
+    /// マイクのチャンネル・サンプルレート・バッファサイズ
+    #[serde(default)]
+    pub input_format: InputFormat,
+    /// 設定の入力デバイスが無いときに順に試すデバイス
+    #[serde(default)]
+    pub input_device_fallbacks: Vec<String>,
+    /// 予備のデバイスも無ければシステム既定のデバイスを使う
+    #[serde(default = "default_input_device_fallback_to_default")]
+    pub input_device_fallback_to_default: bool,
+    /// 録音した音声の前処理 (ハイパス・ノイズ抑制・AGC)
+    #[serde(default)]
+    pub dsp: DspSettings,
+    /// 連続ディクテーション (無音で区切りながら録音を続ける)
+    #[serde(default)]
+    pub continuous: ContinuousSettings,
+    /// 波形・スペクトログラムの表示で残す秒数
+    #[serde(default = "default_monitor_window_secs")]
+    pub monitor_window_secs: f32,
     #[serde(default = "default_hotkey")]
     pub hotkey: String,
+    #[serde(default = "default_stt_language")]
+    pub stt_language: String,
+    #[serde(default = "default_remove_punctuation")]
+    pub remove_punctuation: bool,
     #[serde(default = "default_clipboard_enabled")]
     pub clipboard_enabled: bool,
     #[serde(default = "default_auto_input_enabled")]
@@ -21,18 +68,164 @@ pub struct Config {
     pub auto_input_send_enter: bool,
     #[serde(default = "default_vrchat_enabled")]
     pub vrchat_enabled: bool,
+    #[serde(default = "default_osc_send_addr")]
+    pub osc_send_addr: String,
+    #[serde(default = "default_osc_listen_addr")]
+    pub osc_listen_addr: String,
+    #[serde(default = "default_oscquery_enabled")]
+    pub oscquery_enabled: bool,
+    #[serde(default = "default_osc_trigger_rules")]
+    pub osc_trigger_rules: Vec<OscTriggerRule>,
+    #[serde(default = "default_osc_double_toggle_secs")]
+    pub osc_double_toggle_secs: f32,
+    #[serde(default = "default_avatar_parameters_enabled")]
+    pub avatar_parameters_enabled: bool,
+    #[serde(default = "default_avatar_state_bindings")]
+    pub avatar_state_bindings: Vec<AvatarStateBinding>,
+    #[serde(default = "default_chatbox_page_dwell_secs")]
+    pub chatbox_page_dwell_secs: f32,
+    #[serde(default = "default_chatbox_page_marker")]
+    pub chatbox_page_marker: bool,
     #[serde(default = "default_eliza_enabled")]
     pub eliza_enabled: bool,
     #[serde(default = "default_eliza_url")]
     pub eliza_url: String,
     #[serde(default = "default_eliza_gesture")]
     pub eliza_gesture: i32,
+    #[serde(default = "default_eliza_max_turns")]
+    pub eliza_max_turns: usize,
+    #[serde(default = "default_eliza_max_tokens")]
+    pub eliza_max_tokens: usize,
+    #[serde(default = "default_eliza_persist_conversation")]
+    pub eliza_persist_conversation: bool,
+    #[serde(default)]
+    pub eliza_reset_hotkey: String,
+    #[serde(default = "default_assistant_backend")]
+    pub assistant_backend: AssistantBackend,
+    #[serde(default = "default_assistant_streaming")]
+    pub assistant_streaming: bool,
+    #[serde(default = "default_chat_completion_base_url")]
+    pub chat_completion_base_url: String,
+    #[serde(default)]
+    pub chat_completion_api_key: String,
+    #[serde(default = "default_chat_completion_model")]
+    pub chat_completion_model: String,
+    #[serde(default)]
+    pub chat_completion_system_prompt: String,
+    #[serde(default = "default_chat_completion_temperature")]
+    pub chat_completion_temperature: f32,
+    #[serde(default = "default_assistant_tools_enabled")]
+    pub assistant_tools_enabled: bool,
+    #[serde(default = "default_assistant_tools")]
+    pub assistant_tools: Vec<ToolDefinition>,
+    #[serde(default = "default_translation_enabled")]
+    pub translation_enabled: bool,
+    #[serde(default = "default_translation_backend")]
+    pub translation_backend: TranslationBackend,
+    #[serde(default = "default_translation_url")]
+    pub translation_url: String,
+    #[serde(default = "default_translation_model")]
+    pub translation_model: String,
+    #[serde(default)]
+    pub translation_api_key: String,
+    #[serde(default = "default_translation_source_lang")]
+    pub translation_source_lang: String,
+    #[serde(default = "default_translation_target_lang")]
+    pub translation_target_lang: String,
+    #[serde(default = "default_translation_output")]
+    pub translation_output: TranslationOutput,
+    #[serde(default)]
+    pub translation_hotkeys: Vec<TranslationHotkey>,
+    #[serde(default)]
+    pub rewrite_template: String,
+    #[serde(default = "default_rewrite_templates")]
+    pub rewrite_templates: Vec<RewriteTemplate>,
+    #[serde(default = "default_rewrite_url")]
+    pub rewrite_url: String,
+    #[serde(default = "default_rewrite_model")]
+    pub rewrite_model: String,
+    #[serde(default)]
+    pub rewrite_api_key: String,
+    #[serde(default = "default_rewrite_preview")]
+    pub rewrite_preview: bool,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default = "default_active_profile")]
+    pub active_profile: String,
+    /// 録音開始時に最前面のアプリでプロファイルを選ぶ
+    #[serde(default)]
+    pub auto_profile_switching: bool,
+    #[serde(default)]
+    pub profile_rules: Vec<ProfileRule>,
+    #[serde(default = "default_tts_enabled")]
+    pub tts_enabled: bool,
+    #[serde(default = "default_tts_engine")]
+    pub tts_engine: TtsEngine,
+    #[serde(default)]
+    pub tts_output_device: Option<String>,
+    /// 読み込んだ設定ファイルの場所 (`--config` で変えられる)
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// コマンドラインで一時的に上書きした値 (元の値, 上書きした値)
+    #[serde(skip)]
+    overrides: Vec<(Override, Override)>,
+}
+
+/// コマンドラインで起動中だけ上書きできる設定
+/// 保存するときは、画面で変更されていなければ元の値に戻す
+#[derive(Debug, Clone, PartialEq)]
+pub enum Override {
+    InputDevice(Option<String>),
+    Language(String),
+    Hotkey(String),
+    VrchatEnabled(bool),
+    /// 環境変数 XAI_API_KEY のキー (資格情報ストアには保存しない)
+    ApiKey(String),
+}
+
+impl Override {
+    /// The same kind of override holding the config's current value
+    fn current(&self, config: &Config) -> Override {
+        match self {
+            Override::InputDevice(_) => Override::InputDevice(config.input_device_name.clone()),
+            Override::Language(_) => Override::Language(config.stt_language.clone()),
+            Override::Hotkey(_) => Override::Hotkey(config.hotkey.clone()),
+            Override::VrchatEnabled(_) => Override::VrchatEnabled(config.vrchat_enabled),
+            Override::ApiKey(_) => Override::ApiKey(config.xai_api_key.clone()),
+        }
+    }
+
+    fn apply(&self, config: &mut Config) {
+        match self {
+            Override::InputDevice(device) => config.input_device_name = device.clone(),
+            Override::Language(language) => config.stt_language = language.clone(),
+            Override::Hotkey(hotkey) => config.hotkey = hotkey.clone(),
+            Override::VrchatEnabled(enabled) => config.vrchat_enabled = *enabled,
+            Override::ApiKey(key) => config.xai_api_key = key.clone(),
+        }
+    }
+}
+
+fn default_input_device_fallback_to_default() -> bool {
+    true
+}
+
+fn default_monitor_window_secs() -> f32 {
+    10.0
 }
 
 fn default_hotkey() -> String {
     "Ctrl+Shift+H".to_string()
 }
 
+fn default_stt_language() -> String {
+    "ja".to_string()
+}
+
+fn default_remove_punctuation() -> bool {
+    true
+}
+
 fn default_clipboard_enabled() -> bool {
     true
 }
@@ -49,6 +242,42 @@ fn default_vrchat_enabled() -> bool {
     false
 }
 
+fn default_osc_send_addr() -> String {
+    "127.0.0.1:9000".to_string()
+}
+
+fn default_osc_listen_addr() -> String {
+    "0.0.0.0:9001".to_string()
+}
+
+fn default_oscquery_enabled() -> bool {
+    false
+}
+
+fn default_osc_trigger_rules() -> Vec<OscTriggerRule> {
+    osc_trigger::default_rules()
+}
+
+fn default_osc_double_toggle_secs() -> f32 {
+    osc_trigger::DEFAULT_DOUBLE_TOGGLE_SECS
+}
+
+fn default_avatar_parameters_enabled() -> bool {
+    false
+}
+
+fn default_avatar_state_bindings() -> Vec<AvatarStateBinding> {
+    vrchat::default_state_bindings()
+}
+
+fn default_chatbox_page_dwell_secs() -> f32 {
+    3.0
+}
+
+fn default_chatbox_page_marker() -> bool {
+    true
+}
+
 fn default_eliza_enabled() -> bool {
     false
 }
@@ -61,30 +290,333 @@ fn default_eliza_gesture() -> i32 {
     7
 }
 
+fn default_eliza_max_turns() -> usize {
+    10
+}
+
+fn default_eliza_max_tokens() -> usize {
+    4000
+}
+
+fn default_eliza_persist_conversation() -> bool {
+    false
+}
+
+fn default_assistant_backend() -> AssistantBackend {
+    AssistantBackend::Eliza
+}
+
+fn default_assistant_streaming() -> bool {
+    true
+}
+
+fn default_chat_completion_base_url() -> String {
+    "http://localhost:11434/v1".to_string()
+}
+
+fn default_chat_completion_model() -> String {
+    "llama3.1".to_string()
+}
+
+fn default_chat_completion_temperature() -> f32 {
+    0.7
+}
+
+fn default_assistant_tools_enabled() -> bool {
+    false
+}
+
+fn default_assistant_tools() -> Vec<ToolDefinition> {
+    tools::default_tools()
+}
+
+fn default_translation_enabled() -> bool {
+    false
+}
+
+fn default_translation_backend() -> TranslationBackend {
+    TranslationBackend::ChatCompletion
+}
+
+fn default_translation_url() -> String {
+    "http://localhost:11434/v1".to_string()
+}
+
+fn default_translation_model() -> String {
+    "llama3.1".to_string()
+}
+
+fn default_translation_source_lang() -> String {
+    "ja".to_string()
+}
+
+fn default_translation_target_lang() -> String {
+    "en".to_string()
+}
+
+fn default_translation_output() -> TranslationOutput {
+    TranslationOutput::Both
+}
+
+fn default_rewrite_templates() -> Vec<RewriteTemplate> {
+    rewrite::default_templates()
+}
+
+fn default_rewrite_url() -> String {
+    "http://localhost:11434/v1".to_string()
+}
+
+fn default_rewrite_model() -> String {
+    "llama3.1".to_string()
+}
+
+fn default_rewrite_preview() -> bool {
+    true
+}
+
+fn default_active_profile() -> String {
+    DEFAULT_PROFILE_NAME.to_string()
+}
+
+fn default_tts_enabled() -> bool {
+    false
+}
+
+fn default_tts_engine() -> TtsEngine {
+    tts::default_engine()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             xai_api_key: String::new(),
             silence_duration_secs: 2.0,
             silence_threshold: 0.01,
+            input_device_fallbacks: Vec::new(),
+            input_device_fallback_to_default: default_input_device_fallback_to_default(),
+            dsp: DspSettings::default(),
+            continuous: ContinuousSettings::default(),
+            monitor_window_secs: default_monitor_window_secs(),
+            input_source: InputSource::default(),
             input_device_name: None,
+            input_format: InputFormat::default(),
             hotkey: default_hotkey(),
+            stt_language: default_stt_language(),
+            remove_punctuation: default_remove_punctuation(),
             clipboard_enabled: default_clipboard_enabled(),
             auto_input_enabled: default_auto_input_enabled(),
             auto_input_send_enter: default_auto_input_send_enter(),
             vrchat_enabled: default_vrchat_enabled(),
+            osc_send_addr: default_osc_send_addr(),
+            osc_listen_addr: default_osc_listen_addr(),
+            oscquery_enabled: default_oscquery_enabled(),
+            osc_trigger_rules: default_osc_trigger_rules(),
+            osc_double_toggle_secs: default_osc_double_toggle_secs(),
+            avatar_parameters_enabled: default_avatar_parameters_enabled(),
+            avatar_state_bindings: default_avatar_state_bindings(),
+            chatbox_page_dwell_secs: default_chatbox_page_dwell_secs(),
+            chatbox_page_marker: default_chatbox_page_marker(),
             eliza_enabled: default_eliza_enabled(),
             eliza_url: default_eliza_url(),
             eliza_gesture: default_eliza_gesture(),
+            eliza_max_turns: default_eliza_max_turns(),
+            eliza_max_tokens: default_eliza_max_tokens(),
+            eliza_persist_conversation: default_eliza_persist_conversation(),
+            eliza_reset_hotkey: String::new(),
+            assistant_backend: default_assistant_backend(),
+            assistant_streaming: default_assistant_streaming(),
+            chat_completion_base_url: default_chat_completion_base_url(),
+            chat_completion_api_key: String::new(),
+            chat_completion_model: default_chat_completion_model(),
+            chat_completion_system_prompt: String::new(),
+            chat_completion_temperature: default_chat_completion_temperature(),
+            assistant_tools_enabled: default_assistant_tools_enabled(),
+            assistant_tools: default_assistant_tools(),
+            translation_enabled: default_translation_enabled(),
+            translation_backend: default_translation_backend(),
+            translation_url: default_translation_url(),
+            translation_model: default_translation_model(),
+            translation_api_key: String::new(),
+            translation_source_lang: default_translation_source_lang(),
+            translation_target_lang: default_translation_target_lang(),
+            translation_output: default_translation_output(),
+            translation_hotkeys: Vec::new(),
+            rewrite_template: String::new(),
+            rewrite_templates: default_rewrite_templates(),
+            rewrite_url: default_rewrite_url(),
+            rewrite_model: default_rewrite_model(),
+            rewrite_api_key: String::new(),
+            rewrite_preview: default_rewrite_preview(),
+            profiles: Vec::new(),
+            active_profile: default_active_profile(),
+            auto_profile_switching: false,
+            profile_rules: Vec::new(),
+            tts_enabled: default_tts_enabled(),
+            tts_engine: default_tts_engine(),
+            tts_output_device: None,
+            path: None,
+            overrides: Vec::new(),
         }
     }
 }
 
 impl Config {
+    /// プロファイルが無い (古い設定ファイル) 場合は現在の設定から "default" を作る
+    /// 選択中のプロファイルが見つからなければ先頭のプロファイルを選ぶ
+    pub fn ensure_profiles(&mut self) {
+        if self.profiles.is_empty() {
+            eprintln!(
+                "Migrating settings into the \"{}\" profile",
+                DEFAULT_PROFILE_NAME
+            );
+            self.profiles
+                .push(Profile::from_config(DEFAULT_PROFILE_NAME, self));
+            self.active_profile = DEFAULT_PROFILE_NAME.to_string();
+        }
+        if !self.profiles.iter().any(|p| p.name == self.active_profile) {
+            let first = self.profiles[0].clone();
+            self.active_profile = first.name.clone();
+            first.apply_to(self);
+        }
+    }
+
+    /// Override a setting for this run only (it is not written back on save)
+    pub fn apply_override(&mut self, value: Override) {
+        let original = value.current(self);
+        value.apply(self);
+        self.overrides.push((original, value));
+    }
+
+    /// 上書きしたままの値を元に戻す (画面などで変えた値はそのまま)
+    pub fn restore_overrides(&mut self) {
+        for (original, applied) in self.overrides.clone().iter().rev() {
+            if applied.current(self) == *applied {
+                original.apply(self);
+            }
+        }
+        self.overrides.clear();
+    }
+
+    /// Apply the command line overrides of another config (used after reloading)
+    pub fn reapply_overrides(&mut self, from: &Config) {
+        for (_, applied) in &from.overrides {
+            self.apply_override(applied.clone());
+        }
+    }
+
+    /// 読み直した設定に、起動中だけの状態 (保存先・API キー・上書き) を引き継ぐ
+    /// ファイルに無い API キーは上書き前の値を使う (環境変数のキーを保存させないため)
+    pub fn carry_over(&mut self, running: &Config) {
+        self.path = running.path.clone();
+        if self.xai_api_key.is_empty() {
+            let mut original = running.clone();
+            original.restore_overrides();
+            self.xai_api_key = original.xai_api_key;
+        }
+        self.reapply_overrides(running);
+    }
+
+    /// 保存した場合に同じ内容になるか (上書きとプロファイルへの反映を考慮して比べる)
+    pub fn same_settings(&self, other: &Config) -> bool {
+        let normalized = |config: &Config| {
+            let mut config = config.clone();
+            config.restore_overrides();
+            config.sync_active_profile();
+            serde_json::to_value(&config).ok()
+        };
+        normalized(self) == normalized(other)
+    }
+
+    /// チャットボックスの各ページを表示しておく時間 (変な値なら既定値)
+    pub fn chatbox_page_dwell(&self) -> Duration {
+        let default = || Duration::from_secs_f32(default_chatbox_page_dwell_secs());
+        if !self.chatbox_page_dwell_secs.is_finite() {
+            return default();
+        }
+        Duration::try_from_secs_f32(self.chatbox_page_dwell_secs.max(0.0))
+            .unwrap_or_else(|_| default())
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.iter().map(|p| p.name.clone()).collect()
+    }
+
+    /// 現在の作業中の設定を選択中のプロファイルに書き戻す
+    pub fn sync_active_profile(&mut self) {
+        let mut snapshot = self.clone();
+        snapshot.restore_overrides();
+        if let Some(profile) = self
+            .profiles
+            .iter_mut()
+            .find(|p| p.name == snapshot.active_profile)
+        {
+            let switch_hotkey = std::mem::take(&mut profile.switch_hotkey);
+            *profile = Profile::from_config(&snapshot.active_profile, &snapshot);
+            profile.switch_hotkey = switch_hotkey;
+        }
+    }
+
+    /// Switch to a profile (the current one is saved first)
+    pub fn select_profile(&mut self, name: &str) -> Result<(), String> {
+        let profile = self
+            .profiles
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .ok_or_else(|| format!("Profile '{}' not found", name))?;
+        self.sync_active_profile();
+        // 上書きは切り替え前のプロファイルに対するものなので捨てる
+        self.overrides.clear();
+        profile.apply_to(self);
+        self.active_profile = profile.name;
+        Ok(())
+    }
+
+    /// 現在の設定をコピーして新しいプロファイルを作り、それを選択する
+    pub fn create_profile(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Profile name is empty".to_string());
+        }
+        if self.profiles.iter().any(|p| p.name == name) {
+            return Err(format!("Profile '{}' already exists", name));
+        }
+        self.sync_active_profile();
+        self.profiles.push(Profile::from_config(name, self));
+        self.active_profile = name.to_string();
+        Ok(())
+    }
+
+    /// Delete the active profile and switch to the first remaining one
+    pub fn delete_active_profile(&mut self) -> Result<(), String> {
+        if self.profiles.len() <= 1 {
+            return Err("Cannot delete the last profile".to_string());
+        }
+        self.profiles.retain(|p| p.name != self.active_profile);
+        let first = self.profiles[0].clone();
+        first.apply_to(self);
+        self.active_profile = first.name;
+        Ok(())
+    }
+
+    /// 設定の既定の翻訳言語
+    pub fn translation_languages(&self) -> TranslationLanguages {
+        TranslationLanguages {
+            source_lang: self.translation_source_lang.clone(),
+            target_lang: self.translation_target_lang.clone(),
+        }
+    }
+
     /// Parse hotkey string into HotKey
     /// Format: "Ctrl+Shift+R", "Alt+S", "Ctrl+Alt+T", etc.
     pub fn parse_hotkey(&self) -> Result<HotKey, String> {
-        let parts: Vec<&str> = self.hotkey.split('+').map(|s| s.trim()).collect();
+        Self::parse_hotkey_str(&self.hotkey)
+    }
+
+    /// Parse an arbitrary hotkey string (same format as `parse_hotkey`)
+    pub fn parse_hotkey_str(hotkey: &str) -> Result<HotKey, String> {
+        let parts: Vec<&str> = hotkey.split('+').map(|s| s.trim()).collect();
 
         if parts.is_empty() {
             return Err("Hotkey cannot be empty".to_string());
@@ -175,62 +707,311 @@ impl Config {
     }
 
     /// Load config from file
-    pub fn load() -> Self {
+    /// 読み込めなかった場合は既定の設定を返し、画面に出す警告も返す
+    pub fn load() -> (Self, Option<String>) {
         match Self::config_path() {
-            Ok(path) => {
-                if path.exists() {
-                    match fs::read_to_string(&path) {
-                        Ok(content) => match serde_json::from_str(&content) {
-                            Ok(config) => {
-                                println!("Config loaded from: {:?}", path);
-                                return config;
-                            }
-                            Err(e) => {
-                                eprintln!("Failed to parse config: {}", e);
+            Ok(path) => Self::load_path(&path),
+            Err(e) => {
+                let env_key = std::env::var("XAI_API_KEY").ok();
+                eprintln!("Failed to get config path: {}", e);
+                let mut config = Self::default_with_profiles();
+                if let Some(key) = env_key.filter(|k| !k.trim().is_empty()) {
+                    config.apply_override(Override::ApiKey(key.trim().to_string()));
+                }
+                (config, Some(e))
+            }
+        }
+    }
+
+    /// Load config from the given file (and the API key from the credential store)
+    pub fn load_path(path: &Path) -> (Self, Option<String>) {
+        let env_key = std::env::var("XAI_API_KEY").ok();
+        let (mut config, warning) = Self::load_from(path);
+        let secrets_warning = config.load_secrets(&KeyringStore, env_key, path);
+        config.path = Some(path.to_path_buf());
+        (config, warning.or(secrets_warning))
+    }
+
+    /// xAI API キーを資格情報ストアから読む
+    /// 設定ファイルに平文で残っていたキーはストアへ移してファイルから消す (一度だけ)
+    /// 環境変数 XAI_API_KEY があればそれを優先する (起動中だけ使い、保存はしない)
+    pub fn load_secrets(
+        &mut self,
+        store: &dyn SecretStore,
+        env_key: Option<String>,
+        path: &Path,
+    ) -> Option<String> {
+        let mut warning = None;
+        if !self.xai_api_key.is_empty() {
+            match secrets::store_secret(store, XAI_API_KEY, &self.xai_api_key) {
+                Ok(()) => {
+                    eprintln!("Moved xAI API key from config file to the credential store");
+                    if let Err(e) = self.save_with_store(path, store) {
+                        eprintln!("Failed to save config: {}", e);
+                    }
+                    scrub_backups(path);
+                }
+                Err(e) => {
+                    let message = format!("{}; the xAI API key stays in config.json", e);
+                    eprintln!("{}", message);
+                    warning = Some(message);
+                }
+            }
+        } else {
+            match store.get(XAI_API_KEY) {
+                Ok(key) => self.xai_api_key = key.unwrap_or_default(),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    warning = Some(e);
+                }
+            }
+        }
+
+        if let Some(key) = env_key.filter(|k| !k.trim().is_empty()) {
+            eprintln!("xAI API key set from XAI_API_KEY environment variable");
+            self.apply_override(Override::ApiKey(key.trim().to_string()));
+        }
+        warning
+    }
+
+    fn default_with_profiles() -> Self {
+        let mut config = Self::default();
+        config.ensure_profiles();
+        config
+    }
+
+    /// Load (and if needed migrate) the config at `path`
+    pub fn load_from(path: &Path) -> (Self, Option<String>) {
+        if !path.exists() {
+            eprintln!("Using default config");
+            return (Self::default_with_profiles(), None);
+        }
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                let warning = format!("Failed to read config file: {}", e);
+                eprintln!("{}", warning);
+                return (Self::default_with_profiles(), Some(warning));
+            }
+        };
+
+        match Self::parse(&content) {
+            Ok((config, from_version)) => {
+                eprintln!("Config loaded from: {:?}", path);
+                let mut warning = None;
+                if from_version > CONFIG_VERSION {
+                    warning = Some(format!(
+                        "Config file version {} is newer than this winh (version {}); unknown settings will be lost on save",
+                        from_version, CONFIG_VERSION
+                    ));
+                } else if from_version < CONFIG_VERSION {
+                    // 変換前のファイルを残してから、新しい形式で書き直す
+                    let backup = backup_path(path, &format!("v{}.bak", from_version));
+                    match fs::copy(path, &backup) {
+                        Ok(_) => {
+                            eprintln!("Config backup written to: {:?}", backup);
+                            if let Err(e) = config.save_to(path) {
+                                eprintln!("Failed to save migrated config: {}", e);
                             }
-                        },
+                        }
                         Err(e) => {
-                            eprintln!("Failed to read config file: {}", e);
+                            eprintln!("Failed to back up config before migration: {}", e);
                         }
                     }
                 }
+                (config, warning)
             }
             Err(e) => {
-                eprintln!("Failed to get config path: {}", e);
+                // 壊れたファイルは上書きされる前に退避しておく
+                let backup = backup_path(path, &format!("invalid-{}", unix_time()));
+                let kept = match fs::copy(path, &backup) {
+                    Ok(_) => {
+                        // バックアップに平文の API キーを残さない
+                        scrub_backups(path);
+                        format!("the original was kept as {}", backup.display())
+                    }
+                    Err(copy_err) => format!("failed to back it up: {}", copy_err),
+                };
+                let warning = format!(
+                    "Config file could not be loaded ({}); using default settings, {}",
+                    e, kept
+                );
+                eprintln!("{}", warning);
+                (Self::default_with_profiles(), Some(warning))
             }
         }
+    }
 
-        println!("Using default config");
-        Self::default()
+    /// Parse config JSON, applying migrations. Returns the config and its original version
+    pub fn parse(content: &str) -> Result<(Self, u32), String> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(content).map_err(|e| format!("invalid JSON: {}", e))?;
+        let from_version = migration::migrate(&mut value)?;
+        let mut config: Config = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        config.ensure_profiles();
+        // 手で書き換えた値でも Duration にできるようにする
+        config.chatbox_page_dwell_secs = valid_secs(
+            config.chatbox_page_dwell_secs,
+            default_chatbox_page_dwell_secs(),
+        );
+        config.osc_double_toggle_secs = valid_secs(
+            config.osc_double_toggle_secs,
+            default_osc_double_toggle_secs(),
+        );
+        Ok((config, from_version))
     }
 
     /// Save config to file
     pub fn save(&self) -> Result<(), String> {
-        let path = Self::config_path()?;
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => Self::config_path()?,
+        };
+        self.save_with_store(&path, &KeyringStore)
+    }
+
+    /// API キーは資格情報ストアに、それ以外は設定ファイルに保存する
+    /// (環境変数のキーは上書きと同じく保存しない)
+    fn save_with_store(&self, path: &Path, store: &dyn SecretStore) -> Result<(), String> {
+        let mut config = self.clone();
+        config.restore_overrides();
+        match secrets::store_secret(store, XAI_API_KEY, &config.xai_api_key) {
+            Ok(()) => config.xai_api_key.clear(),
+            Err(e) if config.xai_api_key.is_empty() => eprintln!("{}", e),
+            Err(e) => eprintln!("{}; keeping the xAI API key in config.json", e),
+        }
+        config.save_to(path)
+    }
+
+    /// 一時ファイルに書いてから rename する (書き込み途中で落ちても壊れないように)
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        // 選択中のプロファイルにも現在の設定を反映してから保存する
+        // (コマンドラインでの一時的な上書きは保存しない)
+        let mut config = self.clone();
+        config.restore_overrides();
+        config.sync_active_profile();
+        config.config_version = config.config_version.max(CONFIG_VERSION);
 
-        let json = serde_json::to_string_pretty(self)
+        let json = serde_json::to_string_pretty(&config)
             .map_err(|e| format!("Failed to serialize config: {}", e))?;
 
-        fs::write(&path, json).map_err(|e| format!("Failed to write config file: {}", e))?;
+        let tmp_path = backup_path(path, "tmp");
+        let write_tmp = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()
+        };
+        if let Err(e) = write_tmp() {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(format!("Failed to write config file: {}", e));
+        }
+        fs::rename(&tmp_path, path).map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            format!("Failed to replace config file: {}", e)
+        })?;
 
-        println!("Config saved to: {:?}", path);
+        eprintln!("Config saved to: {:?}", path);
         Ok(())
     }
+}
+
+/// 秒数の設定値を Duration にできる範囲に収める (無限大や大きすぎる値は既定値)
+fn valid_secs(secs: f32, default: f32) -> f32 {
+    if !secs.is_finite() || Duration::try_from_secs_f32(secs.max(0.0)).is_err() {
+        return default;
+    }
+    secs.max(0.0)
+}
+
+/// `config.json` → `config.<suffix>.json`
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "config".to_string());
+    path.with_file_name(format!("{}.{}.json", stem, suffix))
+}
 
-    /// Apply command line arguments
-    pub fn apply_args(&mut self, args: &[String]) {
-        for arg in args {
-            if let Some(key) = arg.strip_prefix("--xai-api-key=") {
-                self.xai_api_key = key.to_string();
-                println!("xAI API key set from command line");
-            } else if let Some(key) = arg.strip_prefix("XAI_API_KEY=") {
-                self.xai_api_key = key.to_string();
-                println!("xAI API key set from command line (XAI_API_KEY=...)");
+/// 移行前のバックアップに残った平文の API キーを消す
+fn scrub_backups(path: &Path) {
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_stem().unwrap_or_default().to_string_lossy()
+    );
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !(name.starts_with(&prefix) && name.ends_with(".json")) || entry.path() == path {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&content) else {
+            // 読めなかった設定ファイルのバックアップは文字列のまま消す
+            if let Some(stripped) = strip_api_key_text(&content) {
+                let _ = fs::write(entry.path(), stripped);
+            }
+            continue;
+        };
+        if let Some(object) = value.as_object_mut() {
+            if object.remove("xai_api_key").is_some() {
+                if let Ok(json) = serde_json::to_string_pretty(&value) {
+                    let _ = fs::write(entry.path(), json);
+                }
             }
         }
     }
 }
 
+/// JSON として読めない内容から `"xai_api_key": "..."` の値を空にする (無ければ None)
+fn strip_api_key_text(content: &str) -> Option<String> {
+    const KEY: &str = "\"xai_api_key\"";
+    let mut stripped = String::new();
+    let mut rest = content;
+    let mut changed = false;
+    while let Some(start) = rest.find(KEY) {
+        let after_key = &rest[start + KEY.len()..];
+        let Some(value) = after_key
+            .trim_start()
+            .strip_prefix(':')
+            .and_then(|v| v.trim_start().strip_prefix('"'))
+        else {
+            stripped.push_str(&rest[..start + KEY.len()]);
+            rest = after_key;
+            continue;
+        };
+        let value_start = rest.len() - value.len();
+        // 閉じる引用符まで (閉じていなければ最後まで) を消す
+        let mut escaped = false;
+        let value_len = value
+            .char_indices()
+            .find(|&(_, c)| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            })
+            .map_or(value.len(), |(i, _)| i);
+        changed |= value_len > 0;
+        stripped.push_str(&rest[..value_start]);
+        rest = &value[value_len..];
+    }
+    stripped.push_str(rest);
+    changed.then_some(stripped)
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -241,4 +1022,391 @@ mod tests {
         assert_eq!(config.silence_duration_secs, 2.0);
         assert_eq!(config.silence_threshold, 0.01);
     }
+
+    #[test]
+    fn test_old_config_gets_default_osc_rules() {
+        let json = r#"{"silence_duration_secs": 1.3, "silence_threshold": 0.02}"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.osc_trigger_rules, osc_trigger::default_rules());
+        assert_eq!(config.osc_double_toggle_secs, 1.0);
+    }
+
+    #[test]
+    fn test_flat_config_migrates_into_default_profile() {
+        let json = r#"{"silence_duration_secs": 2.0, "silence_threshold": 0.02, "hotkey": "Alt+1", "vrchat_enabled": true}"#;
+        let mut config: Config = serde_json::from_str(json).unwrap();
+        config.ensure_profiles();
+        assert_eq!(config.profile_names(), vec!["default".to_string()]);
+        assert_eq!(config.active_profile, "default");
+        assert_eq!(config.profiles[0].hotkey, "Alt+1");
+        assert_eq!(config.profiles[0].silence_threshold, 0.02);
+        assert!(config.profiles[0].vrchat_enabled);
+        assert_eq!(config.profiles[0].stt_language, "ja");
+    }
+
+    #[test]
+    fn test_switch_profile_applies_and_keeps_edits() {
+        let mut config = Config::default();
+        config.ensure_profiles();
+        config.create_profile("english").unwrap();
+        config.stt_language = "en".to_string();
+        config.hotkey = "Alt+2".to_string();
+        config.vrchat_enabled = false;
+
+        config.select_profile("default").unwrap();
+        assert_eq!(config.active_profile, "default");
+        assert_eq!(config.stt_language, "ja");
+        assert_eq!(config.hotkey, default_hotkey());
+
+        // 切り替え前の編集はプロファイルに残っている
+        config.select_profile("english").unwrap();
+        assert_eq!(config.stt_language, "en");
+        assert_eq!(config.hotkey, "Alt+2");
+        assert!(!config.vrchat_enabled);
+
+        assert!(config.select_profile("missing").is_err());
+        assert!(config.create_profile("english").is_err());
+    }
+
+    #[test]
+    fn test_invalid_chatbox_page_dwell() {
+        let parse_dwell = |value: f64| {
+            let mut json = serde_json::to_value(Config::default()).unwrap();
+            json["chatbox_page_dwell_secs"] = serde_json::json!(value);
+            Config::parse(&json.to_string()).unwrap().0
+        };
+        assert_eq!(parse_dwell(-2.0).chatbox_page_dwell(), Duration::ZERO);
+        // f32 に収まらない値は無限大になるので既定値に戻す
+        assert_eq!(parse_dwell(1e39).chatbox_page_dwell_secs, 3.0);
+        assert_eq!(
+            parse_dwell(1e30).chatbox_page_dwell(),
+            Duration::from_secs(3)
+        );
+        let config = Config {
+            chatbox_page_dwell_secs: f32::NAN,
+            ..Default::default()
+        };
+        assert_eq!(config.chatbox_page_dwell(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_invalid_osc_double_toggle_secs() {
+        let parse_window = |value: f64| {
+            let mut json = serde_json::to_value(Config::default()).unwrap();
+            json["osc_double_toggle_secs"] = serde_json::json!(value);
+            Config::parse(&json.to_string())
+                .unwrap()
+                .0
+                .osc_double_toggle_secs
+        };
+        assert_eq!(parse_window(1e39), 1.0);
+        assert_eq!(parse_window(1e20), 1.0);
+        assert_eq!(parse_window(-1.0), 0.0);
+        assert_eq!(parse_window(0.8), 0.8);
+    }
+
+    #[test]
+    fn test_reload_keeps_overrides_and_detects_own_save() {
+        let mut running = Config::default();
+        running.ensure_profiles();
+        running.apply_override(Override::Language("en".to_string()));
+
+        // 自分で保存した内容を読み直しても変化なし
+        let saved = serde_json::to_string(&{
+            let mut c = running.clone();
+            c.restore_overrides();
+            c
+        })
+        .unwrap();
+        let (mut reloaded, _) = Config::parse(&saved).unwrap();
+        reloaded.reapply_overrides(&running);
+        assert_eq!(reloaded.stt_language, "en");
+        assert!(reloaded.same_settings(&running));
+
+        // 外部で編集された値は反映し、上書きは残す
+        let (mut edited, _) =
+            Config::parse(&saved.replace(r#""vrchat_enabled":false"#, r#""vrchat_enabled":true"#))
+                .unwrap();
+        edited.reapply_overrides(&running);
+        assert!(edited.vrchat_enabled);
+        assert_eq!(edited.stt_language, "en");
+        assert!(!edited.same_settings(&running));
+    }
+
+    #[test]
+    fn test_delete_profile_keeps_last_one() {
+        let mut config = Config::default();
+        config.ensure_profiles();
+        assert!(config.delete_active_profile().is_err());
+        config.create_profile("meeting").unwrap();
+        config.delete_active_profile().unwrap();
+        assert_eq!(config.profile_names(), vec!["default".to_string()]);
+        assert_eq!(config.active_profile, "default");
+    }
+
+    /// テスト用の一時ディレクトリ (テストごとに分ける)
+    fn temp_config_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("winh-config-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_golden_v0_0_4_0() {
+        let (config, from) =
+            Config::parse(include_str!("../testdata/config/v0-0.4.0.json")).unwrap();
+        assert_eq!(from, 0);
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert_eq!(config.xai_api_key, "xai-test-key");
+        assert_eq!(config.silence_duration_secs, 1.3);
+        assert_eq!(
+            config.input_device_name.as_deref(),
+            Some("Microphone (USB Audio)")
+        );
+        assert_eq!(config.hotkey, "Ctrl+Alt+F1");
+        assert!(!config.clipboard_enabled);
+        assert!(config.auto_input_send_enter);
+        assert_eq!(config.eliza_url, "http://192.168.0.10:9096");
+        assert_eq!(config.eliza_gesture, 3);
+        // 平らな設定は default プロファイルに入る
+        assert_eq!(config.profile_names(), vec!["default".to_string()]);
+        assert_eq!(config.profiles[0].hotkey, "Ctrl+Alt+F1");
+        assert!(config.profiles[0].vrchat_enabled);
+    }
+
+    #[test]
+    fn test_golden_v0_early_fills_required_fields() {
+        let (config, from) =
+            Config::parse(include_str!("../testdata/config/v0-early.json")).unwrap();
+        assert_eq!(from, 0);
+        assert_eq!(config.xai_api_key, "xai-early-key");
+        assert_eq!(config.hotkey, "Alt+1");
+        assert_eq!(config.silence_duration_secs, 2.0);
+        assert_eq!(config.silence_threshold, 0.01);
+    }
+
+    #[test]
+    fn test_golden_v0_osc_eliza() {
+        let (config, _) =
+            Config::parse(include_str!("../testdata/config/v0-osc-eliza.json")).unwrap();
+        assert_eq!(config.osc_listen_addr, "127.0.0.1:9011");
+        assert!(!config.oscquery_enabled);
+        assert_eq!(config.osc_trigger_rules.len(), 2);
+        assert_eq!(config.osc_trigger_rules[1].parameter, "Gesture*");
+        assert_eq!(config.osc_double_toggle_secs, 0.8);
+        assert_eq!(config.chatbox_page_dwell_secs, 4.0);
+        assert_eq!(config.eliza_max_turns, 3);
+        assert!(config.eliza_persist_conversation);
+        assert_eq!(config.eliza_reset_hotkey, "Ctrl+Shift+R");
+        assert_eq!(config.profiles[0].silence_duration_secs, 2.5);
+    }
+
+    #[test]
+    fn test_load_migrates_with_backup() {
+        let dir = temp_config_dir("migrate");
+        let path = dir.join("config.json");
+        let original = include_str!("../testdata/config/v0-0.4.0.json");
+        fs::write(&path, original).unwrap();
+
+        let (config, warning) = Config::load_from(&path);
+        assert_eq!(warning, None);
+        assert_eq!(config.hotkey, "Ctrl+Alt+F1");
+        assert_eq!(
+            fs::read_to_string(dir.join("config.v0.bak.json")).unwrap(),
+            original
+        );
+        assert!(!dir.join("config.tmp.json").exists());
+
+        // 書き直されたファイルは現在の形式で、もう一度読んでも変換しない
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(migration::version_of(&saved), CONFIG_VERSION);
+        let (reloaded, warning) = Config::load_from(&path);
+        assert_eq!(warning, None);
+        assert_eq!(reloaded.profile_names(), config.profile_names());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_invalid_warns_and_keeps_file() {
+        let dir = temp_config_dir("invalid");
+        let path = dir.join("config.json");
+        let original = include_str!("../testdata/config/invalid.json");
+        fs::write(&path, original).unwrap();
+
+        let (config, warning) = Config::load_from(&path);
+        let warning = warning.expect("warning for broken config");
+        assert!(warning.contains("could not be loaded"), "{}", warning);
+        assert_eq!(config.hotkey, default_hotkey());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        let backups: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with("config.invalid-")
+            })
+            .collect();
+        assert_eq!(backups.len(), 1);
+        // バックアップは平文の API キーだけ消した元の内容
+        assert_eq!(
+            fs::read_to_string(backups[0].path()).unwrap(),
+            original.replace("xai-broken", "")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_config_backup_drops_api_key() {
+        let dir = temp_config_dir("invalid-key");
+        let path = dir.join("config.json");
+        for original in [
+            // JSON として読めない
+            r#"{"xai_api_key": "xai-se\"cret", "hotkey": "Ctrl+"#,
+            // JSON だが設定として読めない
+            r#"{"config_version": 1, "xai_api_key": "xai-secret", "hotkey": 5}"#,
+        ] {
+            fs::write(&path, original).unwrap();
+            let (_, warning) = Config::load_from(&path);
+            assert!(warning.is_some());
+        }
+        // 元のファイルはそのまま残す
+        assert!(fs::read_to_string(&path).unwrap().contains("xai-secret"));
+        for entry in fs::read_dir(&dir).unwrap().filter_map(|e| e.ok()) {
+            if entry.path() == path {
+                continue;
+            }
+            let backup = fs::read_to_string(entry.path()).unwrap();
+            assert!(!backup.contains("xai-se"), "{}", backup);
+            assert!(!backup.contains("cret"), "{}", backup);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strip_api_key_text() {
+        assert_eq!(
+            strip_api_key_text(r#"{"a": 1, "xai_api_key" : "k\\", "b": ["#).as_deref(),
+            Some(r#"{"a": 1, "xai_api_key" : "", "b": ["#)
+        );
+        assert_eq!(
+            strip_api_key_text(r#"{"xai_api_key": "unterminated"#).as_deref(),
+            Some(r#"{"xai_api_key": ""#)
+        );
+        assert_eq!(strip_api_key_text(r#"{"xai_api_key": "", "#), None);
+        assert_eq!(strip_api_key_text(r#"{"hotkey": "#), None);
+    }
+
+    #[test]
+    fn test_save_to_round_trip() {
+        let dir = temp_config_dir("save");
+        let path = dir.join("config.json");
+        let mut config = Config::default_with_profiles();
+        config.hotkey = "Alt+9".to_string();
+        config.save_to(&path).unwrap();
+        config.silence_threshold = 0.05;
+        config.save_to(&path).unwrap();
+
+        let (loaded, warning) = Config::load_from(&path);
+        assert_eq!(warning, None);
+        assert_eq!(loaded.hotkey, "Alt+9");
+        assert_eq!(loaded.silence_threshold, 0.05);
+        assert_eq!(loaded.profiles[0].silence_threshold, 0.05);
+        assert!(!dir.join("config.v0.bak.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_api_key_moves_to_credential_store() {
+        let dir = temp_config_dir("secrets");
+        let path = dir.join("config.json");
+        fs::write(&path, include_str!("../testdata/config/v0-0.4.0.json")).unwrap();
+        let (mut config, _) = Config::load_from(&path);
+        assert!(fs::read_to_string(dir.join("config.v0.bak.json"))
+            .unwrap()
+            .contains("xai-test-key"));
+
+        let store = secrets::MemoryStore::default();
+        assert_eq!(config.load_secrets(&store, None, &path), None);
+        assert_eq!(config.xai_api_key, "xai-test-key");
+        assert_eq!(
+            store.get(XAI_API_KEY).unwrap().as_deref(),
+            Some("xai-test-key")
+        );
+        // 設定ファイルとバックアップから平文のキーが消えている
+        assert!(!fs::read_to_string(&path).unwrap().contains("xai-test-key"));
+        assert!(!fs::read_to_string(dir.join("config.v0.bak.json"))
+            .unwrap()
+            .contains("xai-test-key"));
+
+        // 次回の起動ではストアから読む。環境変数があればそちらを優先
+        let (mut reloaded, _) = Config::load_from(&path);
+        assert_eq!(reloaded.xai_api_key, "");
+        reloaded.load_secrets(&store, None, &path);
+        assert_eq!(reloaded.xai_api_key, "xai-test-key");
+        reloaded.load_secrets(&store, Some("xai-env".to_string()), &path);
+        assert_eq!(reloaded.xai_api_key, "xai-env");
+
+        // 環境変数のキーは保存しても資格情報ストアに書かない
+        reloaded.save_with_store(&path, &store).unwrap();
+        assert_eq!(
+            store.get(XAI_API_KEY).unwrap().as_deref(),
+            Some("xai-test-key")
+        );
+        assert!(!fs::read_to_string(&path).unwrap().contains("xai-env"));
+        // 画面で入力し直したキーは保存する
+        reloaded.xai_api_key = "xai-typed".to_string();
+        reloaded.save_with_store(&path, &store).unwrap();
+        assert_eq!(
+            store.get(XAI_API_KEY).unwrap().as_deref(),
+            Some("xai-typed")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_does_not_store_env_api_key() {
+        let dir = temp_config_dir("reload-env-key");
+        let path = dir.join("config.json");
+        let store = secrets::MemoryStore::default();
+        store.set(XAI_API_KEY, "xai-stored").unwrap();
+        let mut running = Config::default_with_profiles();
+        running.save_to(&path).unwrap();
+        running.load_secrets(&store, Some("xai-env".to_string()), &path);
+        assert_eq!(running.xai_api_key, "xai-env");
+
+        // 自分で保存した内容の読み直しは変化なしと判定され、環境変数のキーは保存されない
+        running.save_with_store(&path, &store).unwrap();
+        let (mut reloaded, _) = Config::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        reloaded.carry_over(&running);
+        assert_eq!(reloaded.xai_api_key, "xai-env");
+        assert!(reloaded.same_settings(&running));
+        reloaded.save_with_store(&path, &store).unwrap();
+        assert_eq!(
+            store.get(XAI_API_KEY).unwrap().as_deref(),
+            Some("xai-stored")
+        );
+        assert!(!fs::read_to_string(&path).unwrap().contains("xai-env"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_api_key_stays_in_file_without_credential_store() {
+        let dir = temp_config_dir("no-keyring");
+        let path = dir.join("config.json");
+        let store = secrets::MemoryStore::unavailable();
+        let mut config = Config::default_with_profiles();
+        config.xai_api_key = "xai-plain".to_string();
+        config.save_with_store(&path, &store).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("xai-plain"));
+
+        let (mut loaded, _) = Config::load_from(&path);
+        let warning = loaded.load_secrets(&store, None, &path);
+        assert!(warning.is_some());
+        assert_eq!(loaded.xai_api_key, "xai-plain");
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,141 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+/// 変更が続いている間は待つ (エディタは保存時に何度も書き込むことがある)
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 設定ファイルの変更を監視する
+/// 保存は一時ファイルからの rename なので、ファイルではなくディレクトリを監視する
+pub struct ConfigWatcher {
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<()>,
+    debounce: Debounce,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        let file_name = path
+            .file_name()
+            .ok_or("Config path has no file name")?
+            .to_os_string();
+
+        let (sender, receiver) = channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    if is_relevant(&event, &file_name) {
+                        let _ = sender.send(());
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to watch config: {}", e))?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {:?}: {}", dir, e))?;
+        eprintln!("Watching config file for changes: {:?}", path);
+
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+            debounce: Debounce::default(),
+        })
+    }
+
+    /// Returns true once the file has changed and then stayed quiet for a moment
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        while self.receiver.try_recv().is_ok() {
+            self.debounce.changed(now);
+        }
+        self.debounce.settled(now)
+    }
+}
+
+/// 最後の変更から `DEBOUNCE` 経つまで待つ
+#[derive(Debug, Default)]
+struct Debounce {
+    pending_since: Option<Instant>,
+}
+
+impl Debounce {
+    fn changed(&mut self, now: Instant) {
+        self.pending_since = Some(now);
+    }
+
+    /// Returns true once per burst of changes, after it has been quiet for `DEBOUNCE`
+    fn settled(&mut self, now: Instant) -> bool {
+        match self.pending_since {
+            Some(since) if now.duration_since(since) >= DEBOUNCE => {
+                self.pending_since = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn is_relevant(event: &notify::Event, file_name: &std::ffi::OsStr) -> bool {
+    use notify::EventKind;
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
+    ) && event
+        .paths
+        .iter()
+        .any(|p: &PathBuf| p.file_name() == Some(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+    use notify::{Event, EventKind};
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_only_config_file_changes_are_relevant() {
+        let name = OsStr::new("config.json");
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+
+        // 保存と同じく一時ファイルから置き換えた
+        let renamed = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/cfg/config.tmp.json"))
+            .add_path(PathBuf::from("/cfg/config.json"));
+        assert!(is_relevant(&renamed, name));
+        assert!(is_relevant(
+            &event(EventKind::Create(CreateKind::File), "/cfg/config.json"),
+            name
+        ));
+        // 別のファイルの変更や削除は無視する
+        assert!(!is_relevant(
+            &event(EventKind::Modify(ModifyKind::Any), "/cfg/other.json"),
+            name
+        ));
+        assert!(!is_relevant(
+            &event(EventKind::Remove(RemoveKind::File), "/cfg/config.json"),
+            name
+        ));
+    }
+
+    #[test]
+    fn test_debounce_waits_for_quiet() {
+        let start = Instant::now();
+        let mut debounce = Debounce::default();
+        assert!(!debounce.settled(start));
+
+        // 続けて書き込まれている間は待つ
+        debounce.changed(start);
+        debounce.changed(start + DEBOUNCE / 2);
+        assert!(!debounce.settled(start + DEBOUNCE));
+        assert!(debounce.settled(start + DEBOUNCE / 2 + DEBOUNCE));
+        // 一度知らせたら次の変更まで知らせない
+        assert!(!debounce.settled(start + DEBOUNCE * 3));
+    }
+}
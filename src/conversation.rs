@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    /// assistant がツール呼び出しを要求したときの呼び出し内容
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// role が "tool" のとき、どの呼び出しへの結果か
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system(content: &str) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self::new("user", content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self::new("assistant", content)
+    }
+
+    pub fn assistant_tool_calls(content: &str, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls,
+            ..Self::new("assistant", content)
+        }
+    }
+
+    pub fn tool_result(tool_call_id: &str, content: &str) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..Self::new("tool", content)
+        }
+    }
+}
+
+/// OpenAI 形式のツール呼び出し
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "default_tool_call_type")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON 文字列の引数
+    #[serde(default)]
+    pub arguments: String,
+}
+
+fn default_tool_call_type() -> String {
+    "function".to_string()
+}
+
+/// アシスタントとの会話履歴 (ユーザー発言と返答の組を1ターンとする)
+/// max_turns と max_tokens を超えたら古いターンから捨てる
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    messages: Vec<ChatMessage>,
+    pub max_turns: usize,
+    pub max_tokens: usize,
+}
+
+impl Conversation {
+    pub fn new(max_turns: usize, max_tokens: usize) -> Self {
+        Self {
+            messages: Vec::new(),
+            max_turns,
+            max_tokens,
+        }
+    }
+
+    pub fn turn_count(&self) -> usize {
+        self.messages.iter().filter(|m| m.role == "user").count()
+    }
+
+    pub fn reset(&mut self) {
+        self.messages.clear();
+    }
+
+    /// 履歴に新しいユーザー発言を加えた、送信用のメッセージ列を返す
+    /// (履歴自体は返答が届くまで変更しない)
+    pub fn request_messages(&self, text: &str) -> Vec<ChatMessage> {
+        let mut messages = self.messages.clone();
+        messages.push(ChatMessage::user(text));
+        trim_messages(&mut messages, self.max_turns, self.max_tokens);
+        messages
+    }
+
+    /// 返答が届いたターンを履歴に記録する
+    pub fn record_turn(&mut self, user: &str, reply: &str) {
+        self.messages.push(ChatMessage::user(user));
+        self.messages.push(ChatMessage::assistant(reply));
+        trim_messages(&mut self.messages, self.max_turns, self.max_tokens);
+    }
+
+    /// Get the default history file path
+    pub fn default_path() -> Result<PathBuf, String> {
+        let config_dir = dirs::config_dir().ok_or("Failed to get config directory")?;
+        Ok(config_dir.join("winh").join("conversation.json"))
+    }
+
+    /// Load history from file (missing file means empty history)
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read conversation file: {}", e))?;
+        self.messages = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse conversation file: {}", e))?;
+        trim_messages(&mut self.messages, self.max_turns, self.max_tokens);
+        Ok(())
+    }
+
+    /// Save history to file
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create conversation directory: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(&self.messages)
+            .map_err(|e| format!("Failed to serialize conversation: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write conversation file: {}", e))
+    }
+}
+
+/// トークン数のおおまかな見積もり
+/// ASCII は4文字で1トークン、それ以外 (日本語など) は1文字1トークンとみなす
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(|c| c.is_ascii()).count();
+    let other = text.chars().count() - ascii;
+    ascii.div_ceil(4) + other
+}
+
+/// 古いターンから削って max_turns / max_tokens に収める
+/// 最後のユーザー発言は (予算を超えていても) 必ず残す
+fn trim_messages(messages: &mut Vec<ChatMessage>, max_turns: usize, max_tokens: usize) {
+    let over_budget = |messages: &[ChatMessage]| {
+        let turns = messages.iter().filter(|m| m.role == "user").count();
+        let tokens: usize = messages.iter().map(|m| estimate_tokens(&m.content)).sum();
+        turns > max_turns.max(1) || tokens > max_tokens
+    };
+
+    while over_budget(messages) {
+        // 先頭のターン (次のユーザー発言の手前まで) を削除する
+        let next_user = messages
+            .iter()
+            .skip(1)
+            .position(|m| m.role == "user")
+            .map(|i| i + 1);
+        match next_user {
+            Some(end) => {
+                messages.drain(..end);
+            }
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello world!"), 3);
+        assert_eq!(estimate_tokens("こんにちは"), 5);
+    }
+
+    #[test]
+    fn test_trim_by_turns() {
+        let mut conversation = Conversation::new(2, 10_000);
+        conversation.record_turn("1", "a");
+        conversation.record_turn("2", "b");
+        conversation.record_turn("3", "c");
+        assert_eq!(conversation.turn_count(), 2);
+        assert_eq!(conversation.messages[0], ChatMessage::user("2"));
+
+        let request = conversation.request_messages("4");
+        assert_eq!(request.len(), 3);
+        assert_eq!(request[0], ChatMessage::user("3"));
+        assert_eq!(request[2], ChatMessage::user("4"));
+        // 送信用メッセージを作っても履歴は変わらない
+        assert_eq!(conversation.turn_count(), 2);
+    }
+
+    #[test]
+    fn test_trim_by_tokens_keeps_latest_user_message() {
+        let mut conversation = Conversation::new(10, 12);
+        conversation.record_turn("あいうえお", "かきくけこ");
+        let request = conversation.request_messages("さしすせそ");
+        assert_eq!(request, vec![ChatMessage::user("さしすせそ")]);
+
+        let long = "あ".repeat(50);
+        assert_eq!(
+            conversation.request_messages(&long),
+            vec![ChatMessage::user(&long)]
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!(
+            "winh-conversation-test-{}.json",
+            std::process::id()
+        ));
+        let mut conversation = Conversation::new(5, 1000);
+        conversation.record_turn("こんにちは", "やあ");
+        conversation.save(&path).unwrap();
+
+        let mut loaded = Conversation::new(5, 1000);
+        loaded.load(&path).unwrap();
+        assert_eq!(loaded.messages, conversation.messages);
+        fs::remove_file(&path).unwrap();
+    }
+}
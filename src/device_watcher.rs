@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// 入力デバイスの一覧を調べる間隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+type DeviceSource = Box<dyn Fn() -> Result<Vec<String>, String> + Send>;
+
+/// 入力デバイスの抜き差しを検知する
+/// cpal には変更通知が無いので、別スレッドで一覧を定期的に取り直して比べる
+/// DeviceWatcher を drop するとスレッドを止め、終わるまで待つ
+pub struct DeviceWatcher {
+    receiver: Receiver<Vec<String>>,
+    // drop するとスレッドの待ちが解けて終わる
+    stop: Option<Sender<()>>,
+    paused: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatcher {
+    pub fn new() -> Self {
+        Self::with_source(Box::new(crate::audio::get_input_devices), POLL_INTERVAL)
+    }
+
+    fn with_source(source: DeviceSource, interval: Duration) -> Self {
+        let (sender, receiver) = channel();
+        let (stop, stopped) = channel::<()>();
+        let paused = Arc::new(AtomicBool::new(false));
+        let thread_paused = Arc::clone(&paused);
+        let thread = std::thread::spawn(move || {
+            let mut last: Option<Vec<String>> = None;
+            loop {
+                if !thread_paused.load(Ordering::Relaxed) {
+                    match source() {
+                        Ok(devices) if last.as_ref() != Some(&devices) => {
+                            if sender.send(devices.clone()).is_err() {
+                                // 受け取り側が無くなったら終わる
+                                return;
+                            }
+                            last = Some(devices);
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Failed to get input devices: {}", e),
+                    }
+                }
+                if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+            }
+        });
+        Self {
+            receiver,
+            stop: Some(stop),
+            paused,
+            thread: Some(thread),
+        }
+    }
+
+    /// 録音中はデバイスを調べない (一覧の取り直しで録音が途切れないように)
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Latest device list if it changed since the last call
+    pub fn poll(&self) -> Option<Vec<String>> {
+        self.receiver.try_iter().last()
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// 変わった部分 (つながったデバイス, 外れたデバイス)
+pub fn diff(before: &[String], after: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = after
+        .iter()
+        .filter(|d| !before.contains(d))
+        .cloned()
+        .collect();
+    let removed = before
+        .iter()
+        .filter(|d| !after.contains(d))
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    #[test]
+    fn test_reports_only_changes() {
+        let devices = Arc::new(Mutex::new(vec!["Mic A".to_string()]));
+        let source = Arc::clone(&devices);
+        let watcher = DeviceWatcher::with_source(
+            Box::new(move || Ok(source.lock().unwrap().clone())),
+            Duration::from_millis(10),
+        );
+
+        let wait = |watcher: &DeviceWatcher| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if let Some(list) = watcher.poll() {
+                    return Some(list);
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            None
+        };
+        assert_eq!(wait(&watcher), Some(vec!["Mic A".to_string()]));
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(watcher.poll(), None);
+
+        devices.lock().unwrap().push("USB Headset".to_string());
+        assert_eq!(
+            wait(&watcher),
+            Some(vec!["Mic A".to_string(), "USB Headset".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_pause_and_stop() {
+        let calls = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&calls);
+        let watcher = DeviceWatcher::with_source(
+            Box::new(move || {
+                *counter.lock().unwrap() += 1;
+                Ok(Vec::new())
+            }),
+            Duration::from_millis(10),
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *calls.lock().unwrap() == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(*calls.lock().unwrap() > 0);
+
+        // 一時停止中は調べない (切り替わり途中の 1 回は許す)
+        watcher.set_paused(true);
+        let paused_at = *calls.lock().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(*calls.lock().unwrap() <= paused_at + 1);
+        watcher.set_paused(false);
+
+        // drop したらスレッドは終わっている
+        drop(watcher);
+        // 呼び出し元が持つ分だけになっている (スレッドの clone が解放された)
+        assert_eq!(Arc::strong_count(&calls), 1);
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = vec!["A".to_string(), "B".to_string()];
+        let after = vec!["B".to_string(), "C".to_string()];
+        assert_eq!(
+            diff(&before, &after),
+            (vec!["C".to_string()], vec!["A".to_string()])
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// 連続ディクテーションの設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContinuousSettings {
+    /// 無音で録音を終わらせず、無音ごとに区切って出力する
+    pub enabled: bool,
+    /// セッションの最大の長さ (秒、0 なら無制限)
+    pub max_session_secs: f32,
+    /// この秒数話さなければセッションを終える (0 なら終えない)
+    pub idle_timeout_secs: f32,
+}
+
+impl Default for ContinuousSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_session_secs: 0.0,
+            idle_timeout_secs: 60.0,
+        }
+    }
+}
+
+/// 録音中に毎フレーム判断すること
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DictationAction {
+    Continue,
+    /// 話し終えたので、ここまでを 1 つの区切りにする
+    EndSegment,
+    /// セッションを終える (理由)
+    Stop(&'static str),
+}
+
+/// 連続ディクテーションのセッション
+#[derive(Debug, Clone)]
+pub struct ContinuousSession {
+    settings: ContinuousSettings,
+    /// この秒数の無音で区切る (無音検出の秒数と同じ)
+    pause_secs: f32,
+    /// Recording time of the last segment boundary
+    segment_end_secs: f32,
+    segments: usize,
+}
+
+impl ContinuousSession {
+    pub fn new(settings: ContinuousSettings, pause_secs: f32) -> Self {
+        Self {
+            settings,
+            pause_secs,
+            segment_end_secs: 0.0,
+            segments: 0,
+        }
+    }
+
+    /// Number of segments ended by a pause so far
+    pub fn segments(&self) -> usize {
+        self.segments
+    }
+
+    /// 録音開始からの秒数と、最後に音があってからの秒数で判断する
+    pub fn check(&mut self, recording_secs: f32, silence_secs: f32) -> DictationAction {
+        let max = self.settings.max_session_secs;
+        if max > 0.0 && recording_secs >= max {
+            return DictationAction::Stop("maximum session length reached");
+        }
+        let idle = self.settings.idle_timeout_secs;
+        if idle > 0.0 && silence_secs >= idle {
+            return DictationAction::Stop("idle timeout");
+        }
+        // 前の区切りの後に話していて、そこから無音が続いたら区切る
+        let last_sound_secs = recording_secs - silence_secs;
+        if silence_secs >= self.pause_secs && last_sound_secs > self.segment_end_secs {
+            self.segment_end_secs = recording_secs;
+            self.segments += 1;
+            return DictationAction::EndSegment;
+        }
+        DictationAction::Continue
+    }
+}
+
+/// 出力待ちの区切り
+/// 前の区切りの出力 (翻訳・書き直し・アシスタントの返答) が終わるまで次を渡さない
+#[derive(Debug)]
+pub struct SegmentQueue<T> {
+    segments: VecDeque<T>,
+}
+
+impl<T> Default for SegmentQueue<T> {
+    fn default() -> Self {
+        Self {
+            segments: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> SegmentQueue<T> {
+    pub fn push(&mut self, segment: T) {
+        self.segments.push_back(segment);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// 前の区切りを出力中 (busy) でなければ次の区切りを返す
+    pub fn next(&mut self, busy: bool) -> Option<T> {
+        if busy {
+            return None;
+        }
+        self.segments.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::Conversation;
+
+    #[test]
+    fn test_segments_on_pauses_and_stops() {
+        let settings = ContinuousSettings {
+            enabled: true,
+            max_session_secs: 120.0,
+            idle_timeout_secs: 30.0,
+        };
+        let mut session = ContinuousSession::new(settings, 1.5);
+
+        // 話し始める前の無音では区切らない
+        assert_eq!(session.check(2.0, 2.0), DictationAction::Continue);
+        assert_eq!(session.check(5.0, 0.1), DictationAction::Continue);
+        assert_eq!(session.check(6.5, 1.5), DictationAction::EndSegment);
+        // 区切った後、話していなければもう一度は区切らない
+        assert_eq!(session.check(8.0, 3.0), DictationAction::Continue);
+        assert_eq!(session.check(12.0, 2.0), DictationAction::EndSegment);
+        assert_eq!(session.segments(), 2);
+
+        assert_eq!(
+            session.check(45.0, 33.0),
+            DictationAction::Stop("idle timeout")
+        );
+        assert_eq!(
+            session.check(120.0, 0.0),
+            DictationAction::Stop("maximum session length reached")
+        );
+    }
+
+    #[test]
+    fn test_segments_wait_for_assistant_reply() {
+        let mut queue = SegmentQueue::default();
+        let mut conversation = Conversation::new(10, 10_000);
+        queue.push("first".to_string());
+        queue.push("second".to_string());
+
+        // 1 つ目を送り、返答が届くまでは 2 つ目を渡さない
+        let first = queue.next(false).unwrap();
+        assert_eq!(queue.next(true), None);
+        conversation.record_turn(&first, "reply 1");
+
+        let second = queue.next(false).unwrap();
+        conversation.record_turn(&second, "reply 2");
+        assert!(queue.is_empty());
+        assert_eq!(conversation.turn_count(), 2);
+        let messages = conversation.request_messages("third");
+        assert!(messages.iter().any(|m| m.content == "first"));
+        assert!(messages.iter().any(|m| m.content == "second"));
+    }
+}
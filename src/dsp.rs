@@ -0,0 +1,420 @@
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// 録音した音声を STT と無音判定に渡す前の処理 (すべて既定で無効)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DspSettings {
+    /// Remove rumble / hum below `high_pass_hz`
+    pub high_pass_enabled: bool,
+    pub high_pass_hz: f32,
+    /// 定常的な雑音 (ファン・ホワイトノイズ) を周波数ごとに下げる
+    /// 推定した雑音との比で下げるスペクトルゲーティングで、RNNoise のような学習済みモデルではない
+    pub noise_suppression_enabled: bool,
+    /// How much the noise is attenuated at most (dB)
+    pub noise_reduction_db: f32,
+    /// 小さい声を目標の音量まで上げる
+    pub agc_enabled: bool,
+    pub agc_target_dbfs: f32,
+    pub agc_max_gain_db: f32,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            high_pass_enabled: false,
+            high_pass_hz: 100.0,
+            noise_suppression_enabled: false,
+            noise_reduction_db: 20.0,
+            agc_enabled: false,
+            agc_target_dbfs: -20.0,
+            agc_max_gain_db: 30.0,
+        }
+    }
+}
+
+impl DspSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.high_pass_enabled || self.noise_suppression_enabled || self.agc_enabled
+    }
+}
+
+/// ハイパスフィルタ → ノイズ抑制 → AGC の順に処理する
+pub struct DspChain {
+    high_pass: Option<Biquad>,
+    noise_suppressor: Option<NoiseSuppressor>,
+    agc: Option<Agc>,
+}
+
+impl DspChain {
+    pub fn new(settings: &DspSettings, sample_rate: u32) -> Self {
+        Self {
+            high_pass: settings
+                .high_pass_enabled
+                .then(|| Biquad::high_pass(settings.high_pass_hz, sample_rate)),
+            noise_suppressor: settings
+                .noise_suppression_enabled
+                .then(|| NoiseSuppressor::new(settings.noise_reduction_db)),
+            agc: settings.agc_enabled.then(|| {
+                Agc::new(
+                    settings.agc_target_dbfs,
+                    settings.agc_max_gain_db,
+                    sample_rate,
+                )
+            }),
+        }
+    }
+
+    /// Process a block of mono samples (the output has the same length)
+    pub fn process(&mut self, samples: &mut Vec<f32>) {
+        if let Some(high_pass) = &mut self.high_pass {
+            high_pass.process(samples);
+        }
+        if let Some(noise_suppressor) = &mut self.noise_suppressor {
+            *samples = noise_suppressor.process(samples);
+        }
+        if let Some(agc) = &mut self.agc {
+            agc.process(samples);
+        }
+    }
+}
+
+/// 2 次の Butterworth フィルタ (RBJ の biquad)
+pub struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    pub fn high_pass(cutoff_hz: f32, sample_rate: u32) -> Self {
+        let (cos, alpha) = Self::prewarp(cutoff_hz, sample_rate);
+        let a0 = 1.0 + alpha;
+        Self::new(
+            [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    pub fn low_pass(cutoff_hz: f32, sample_rate: u32) -> Self {
+        let (cos, alpha) = Self::prewarp(cutoff_hz, sample_rate);
+        let a0 = 1.0 + alpha;
+        Self::new(
+            [
+                (1.0 - cos) / 2.0 / a0,
+                (1.0 - cos) / a0,
+                (1.0 - cos) / 2.0 / a0,
+            ],
+            [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    fn new(b: [f32; 3], a: [f32; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// `(cos w0, alpha)` for a Butterworth (Q = 1/√2) section
+    fn prewarp(cutoff_hz: f32, sample_rate: u32) -> (f32, f32) {
+        let nyquist = sample_rate as f32 / 2.0;
+        let cutoff = cutoff_hz.clamp(10.0, nyquist * 0.9);
+        let w0 = 2.0 * std::f32::consts::PI * cutoff / sample_rate as f32;
+        (w0.cos(), w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2))
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            let x0 = *sample;
+            let y0 = self.b[0] * x0 + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+                - self.a[0] * self.y[0]
+                - self.a[1] * self.y[1];
+            self.x = [x0, self.x[0]];
+            self.y = [y0, self.y[0]];
+            *sample = y0;
+        }
+    }
+}
+
+/// ノイズ抑制の FFT の長さ (50% ずつ重ねる)
+const NS_FRAME: usize = 512;
+const NS_HOP: usize = NS_FRAME / 2;
+
+/// 最初のフレームは全部雑音とみなして平均する
+const NS_INITIAL_FRAMES: u32 = 20;
+
+/// 雑音とみなした成分で推定値を更新する割合
+const NS_NOISE_SMOOTHING: f32 = 0.05;
+
+/// 声が続いている間も、雑音の推定値を少しずつ上げて変化に追従する
+const NS_NOISE_RISE: f32 = 1.002;
+
+/// 推定した雑音の何倍を引くか (大きいほど強く抑える)
+const NS_OVER_SUBTRACTION: f32 = 2.0;
+
+/// スペクトルゲーティングによるノイズ抑制
+/// 周波数ごとに雑音の大きさを推定し (雑音らしい成分だけで平均する)、雑音に近い成分ほど下げる
+struct NoiseSuppressor {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    window: Vec<f32>,
+    floor_gain: f32,
+    frame: Vec<f32>,
+    pending: Vec<f32>,
+    overlap: Vec<f32>,
+    output: VecDeque<f32>,
+    noise: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<f32>,
+    frames: u32,
+}
+
+impl NoiseSuppressor {
+    fn new(reduction_db: f32) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(NS_FRAME);
+        let inverse = planner.plan_fft_inverse(NS_FRAME);
+        // sqrt-Hann を分析と合成の両方に掛けると、50% の重ね合わせで元に戻る
+        let window = (0..NS_FRAME)
+            .map(|i| {
+                (0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / NS_FRAME as f32).cos()).sqrt()
+            })
+            .collect();
+        let bins = NS_FRAME / 2 + 1;
+        Self {
+            spectrum: forward.make_output_vec(),
+            scratch: forward.make_input_vec(),
+            forward,
+            inverse,
+            window,
+            floor_gain: 10f32.powf(-reduction_db.abs() / 20.0),
+            frame: vec![0.0; NS_FRAME],
+            pending: Vec::with_capacity(NS_HOP),
+            overlap: vec![0.0; NS_FRAME],
+            // 出力の長さを入力と揃えるため 1 hop 分遅らせる
+            output: VecDeque::from(vec![0.0; NS_HOP]),
+            noise: vec![0.0; bins],
+            frames: 0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        for &sample in input {
+            self.pending.push(sample);
+            if self.pending.len() == NS_HOP {
+                if let Err(e) = self.process_frame() {
+                    eprintln!("Noise suppression failed: {}", e);
+                    self.output.extend(self.pending.drain(..));
+                }
+            }
+        }
+        self.output.drain(..input.len()).collect()
+    }
+
+    fn process_frame(&mut self) -> Result<(), String> {
+        self.frame.copy_within(NS_HOP.., 0);
+        self.frame[NS_FRAME - NS_HOP..].copy_from_slice(&self.pending);
+
+        for ((value, sample), w) in self.scratch.iter_mut().zip(&self.frame).zip(&self.window) {
+            *value = sample * w;
+        }
+        self.forward
+            .process(&mut self.scratch, &mut self.spectrum)
+            .map_err(|e| e.to_string())?;
+
+        for (k, bin) in self.spectrum.iter_mut().enumerate() {
+            let power = bin.norm_sqr();
+            if self.frames < NS_INITIAL_FRAMES {
+                self.noise[k] += (power - self.noise[k]) / (self.frames + 1) as f32;
+            } else if power < self.noise[k] * 4.0 {
+                self.noise[k] += NS_NOISE_SMOOTHING * (power - self.noise[k]);
+            } else {
+                self.noise[k] *= NS_NOISE_RISE;
+            }
+            let snr = power / self.noise[k].max(f32::MIN_POSITIVE);
+            let gain = (1.0 - NS_OVER_SUBTRACTION / snr.max(1e-6)).max(self.floor_gain);
+            *bin *= gain;
+        }
+        self.frames += 1;
+        // 直流とナイキストの虚部は 0 でないと逆変換できない
+        self.spectrum[0].im = 0.0;
+        self.spectrum[NS_FRAME / 2].im = 0.0;
+
+        self.inverse
+            .process(&mut self.spectrum, &mut self.scratch)
+            .map_err(|e| e.to_string())?;
+        for ((acc, value), w) in self.overlap.iter_mut().zip(&self.scratch).zip(&self.window) {
+            *acc += value * w / NS_FRAME as f32;
+        }
+        self.output.extend(self.overlap.drain(..NS_HOP));
+        self.overlap.resize(NS_FRAME, 0.0);
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// この音量より小さいときは無音とみなしてゲインを変えない
+const AGC_GATE_DBFS: f32 = -50.0;
+
+/// 自動ゲイン調整 (声の RMS を目標に近づけ、0 dBFS を超えないよう抑える)
+struct Agc {
+    target_db: f32,
+    max_gain_db: f32,
+    gain_db: f32,
+    power: f32,
+    /// Smoothing per sample for the level and for raising the gain
+    level_alpha: f32,
+    rise_db_per_sample: f32,
+    fall_db_per_sample: f32,
+}
+
+impl Agc {
+    fn new(target_db: f32, max_gain_db: f32, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1) as f32;
+        Self {
+            target_db,
+            max_gain_db: max_gain_db.max(0.0),
+            gain_db: 0.0,
+            power: 0.0,
+            level_alpha: 1.0 - (-1.0 / (0.3 * sample_rate)).exp(),
+            // 上げるのは 10 dB/秒、下げるのは 60 dB/秒
+            rise_db_per_sample: 10.0 / sample_rate,
+            fall_db_per_sample: 60.0 / sample_rate,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            self.power += self.level_alpha * (*sample * *sample - self.power);
+            let level_db = 10.0 * self.power.max(1e-12).log10();
+            if level_db > AGC_GATE_DBFS {
+                let wanted = (self.target_db - level_db).clamp(-self.max_gain_db, self.max_gain_db);
+                if wanted > self.gain_db {
+                    self.gain_db = (self.gain_db + self.rise_db_per_sample).min(wanted);
+                } else {
+                    self.gain_db = (self.gain_db - self.fall_db_per_sample).max(wanted);
+                }
+            }
+            let out = *sample * 10f32.powf(self.gain_db / 20.0);
+            // ソフトリミッタ
+            *sample = if out.abs() > 0.9 {
+                out.signum() * (0.9 + 0.1 * ((out.abs() - 0.9) / 0.1).tanh())
+            } else {
+                out
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn sine(freq: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin() * amplitude)
+            .collect()
+    }
+
+    /// 再現できる疑似乱数の白色雑音
+    fn noise(amplitude: f32, len: usize) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        let power = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        10.0 * power.log10()
+    }
+
+    fn run(settings: DspSettings, mut samples: Vec<f32>) -> Vec<f32> {
+        let mut chain = DspChain::new(&settings, RATE);
+        let len = samples.len();
+        // コールバックと同じく小さいブロックに分けて流す
+        let mut out = Vec::new();
+        for block in samples.chunks_mut(160) {
+            let mut block = block.to_vec();
+            chain.process(&mut block);
+            out.extend(block);
+        }
+        assert_eq!(out.len(), len);
+        out
+    }
+
+    #[test]
+    fn test_disabled_chain_is_passthrough() {
+        let input = noise(0.3, 1000);
+        assert!(!DspSettings::default().is_enabled());
+        assert_eq!(run(DspSettings::default(), input.clone()), input);
+    }
+
+    #[test]
+    fn test_high_pass_removes_hum() {
+        let settings = DspSettings {
+            high_pass_enabled: true,
+            ..Default::default()
+        };
+        let hum = run(settings.clone(), sine(30.0, 0.5, RATE as usize));
+        let voice = run(settings, sine(1000.0, 0.5, RATE as usize));
+        let tail = RATE as usize / 2..;
+        assert!(rms_db(&hum[tail.clone()]) < rms_db(&sine(30.0, 0.5, 8000)) - 18.0);
+        assert!((rms_db(&voice[tail]) - rms_db(&sine(1000.0, 0.5, 8000))).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_noise_suppression_keeps_tone() {
+        let settings = DspSettings {
+            noise_suppression_enabled: true,
+            ..Default::default()
+        };
+        // 1 秒の雑音のあと、雑音 + 1 kHz の音
+        let len = RATE as usize;
+        let background = noise(0.02, len * 2);
+        let mut input = background.clone();
+        for (sample, tone) in input[len..].iter_mut().zip(sine(1000.0, 0.3, len)) {
+            *sample += tone;
+        }
+        let output = run(settings, input.clone());
+
+        let noise_only = len / 2..len;
+        assert!(rms_db(&output[noise_only.clone()]) < rms_db(&input[noise_only]) - 10.0);
+        let with_tone = len + len / 2..len * 2;
+        assert!((rms_db(&output[with_tone.clone()]) - rms_db(&input[with_tone])).abs() < 1.5);
+    }
+
+    #[test]
+    fn test_agc_raises_quiet_voice_but_not_silence() {
+        let settings = DspSettings {
+            agc_enabled: true,
+            ..Default::default()
+        };
+        let quiet = run(settings.clone(), sine(300.0, 0.014, RATE as usize * 4));
+        assert!((rms_db(&quiet[RATE as usize * 3..]) - settings.agc_target_dbfs).abs() < 1.0);
+
+        let silence = run(settings.clone(), noise(0.001, RATE as usize * 2));
+        assert!(rms_db(&silence) < -55.0);
+
+        // 大きすぎる声は下げて、クリップさせない
+        let loud = run(settings, sine(300.0, 1.0, RATE as usize * 2));
+        assert!(loud.iter().all(|s| s.abs() <= 1.0));
+        assert!(rms_db(&loud[RATE as usize..]) < -15.0);
+    }
+}
@@ -1,73 +1,75 @@
-use serde::{Deserialize, Serialize};
-use serde_json;
+use crate::assistant::{self, Assistant, AssistantReply};
+use crate::conversation::ChatMessage;
+use serde::Serialize;
+use serde_json::Value;
 
 #[derive(Debug, Serialize)]
-struct Message {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Serialize)]
-struct ChatRequest {
-    messages: Vec<Message>,
+struct ChatRequest<'a> {
+    messages: &'a [ChatMessage],
     use_memory: bool,
     detect_sleep: bool,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatResponseMessage {
-    content: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatResponse {
-    message: ChatResponseMessage,
+    stream: bool,
 }
 
 pub struct ElizaClient {
     url: String,
+    stream: bool,
 }
 
 impl ElizaClient {
-    pub fn new(url: String) -> Self {
-        Self { url }
+    pub fn new(url: String, stream: bool) -> Self {
+        Self { url, stream }
     }
+}
+
+/// eliza-agent-server の返答 (またはストリームの1チャンク) からテキストを取り出す
+/// 全文は `{"message": {"content": ...}}`、断片は `{"delta": ...}` の形で届く
+fn extract_reply(value: &Value) -> Option<String> {
+    value
+        .get("delta")
+        .and_then(Value::as_str)
+        .or_else(|| value.pointer("/message/content").and_then(Value::as_str))
+        .map(str::to_string)
+}
 
-    /// Send transcribed text to eliza-agent-server and return the response message
-    pub fn send_chat(&self, text: &str) -> Result<String, String> {
+impl Assistant for ElizaClient {
+    /// Send the conversation (history + latest user message) to eliza-agent-server
+    /// and return the response message
+    /// 会話履歴は winh 側で管理するので use_memory は使わない
+    fn stream_chat(
+        &self,
+        messages: &[ChatMessage],
+        on_delta: &mut dyn FnMut(&str),
+    ) -> Result<AssistantReply, String> {
         let request = ChatRequest {
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: text.to_string(),
-            }],
+            messages,
             use_memory: false,
             detect_sleep: false,
+            stream: self.stream,
         };
 
-        let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(60))
-            .build()
-            .unwrap_or_default();
         let endpoint = format!("{}/chat", self.url.trim_end_matches('/'));
-
-        let response = client
+        let response = assistant::http_client()
             .post(&endpoint)
             .json(&request)
             .send()
             .map_err(|e| format!("Failed to send to eliza: {}", e))?;
 
-        let raw = response
-            .text()
-            .map_err(|e| format!("Failed to read eliza response: {}", e))?;
-        println!("[ElizaClient] Raw response from {}: {}", endpoint, raw);
-
-        let body: ChatResponse = serde_json::from_str(&raw)
-            .map_err(|e| format!("Failed to parse eliza response: {}. Body was: {}", e, raw))?;
-
-        println!(
-            "[ElizaClient] Response from {}: {}",
-            endpoint, body.message.content
-        );
-        Ok(body.message.content)
+        let mut reply = String::new();
+        assistant::read_response(response, &mut |chunk| {
+            if let Some(delta) = extract_reply(chunk).filter(|d| !d.is_empty()) {
+                on_delta(&delta);
+                reply.push_str(&delta);
+            }
+        })
+        .map_err(|e| format!("Eliza error: {}", e))?;
+        if reply.is_empty() {
+            return Err("Eliza returned an empty reply".to_string());
+        }
+        println!("[ElizaClient] Response from {}: {}", endpoint, reply);
+        Ok(AssistantReply {
+            content: reply,
+            tool_calls: Vec::new(),
+        })
     }
 }
@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+
+/// 最前面のウィンドウの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForegroundWindow {
+    /// Executable name without directory (e.g. "VRChat.exe", "slack")
+    pub process: String,
+    pub title: String,
+}
+
+/// 最前面のウィンドウを調べる方法 (テストではスタブに差し替える)
+pub trait ForegroundSource {
+    fn foreground_window(&self) -> Option<ForegroundWindow>;
+}
+
+/// OS の API で最前面のウィンドウを調べる
+pub struct SystemForeground;
+
+impl ForegroundSource for SystemForeground {
+    fn foreground_window(&self) -> Option<ForegroundWindow> {
+        match system_foreground_window() {
+            Ok(window) => window,
+            Err(e) => {
+                eprintln!("Failed to get foreground window: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// 最前面のアプリに応じてプロファイルを選ぶルール
+/// `process` / `title_contains` は空なら条件にしない (大文字小文字は区別しない)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRule {
+    #[serde(default)]
+    pub process: String,
+    #[serde(default)]
+    pub title_contains: String,
+    pub profile: String,
+}
+
+impl ProfileRule {
+    pub fn matches(&self, window: &ForegroundWindow) -> bool {
+        if self.process.trim().is_empty() && self.title_contains.trim().is_empty() {
+            return false;
+        }
+        let process_matches = self.process.trim().is_empty()
+            || process_stem(&window.process).eq_ignore_ascii_case(process_stem(&self.process));
+        let title_matches = self.title_contains.trim().is_empty()
+            || window
+                .title
+                .to_lowercase()
+                .contains(&self.title_contains.trim().to_lowercase());
+        process_matches && title_matches
+    }
+}
+
+/// "VRChat.exe" と "vrchat" を同じものとして扱う
+fn process_stem(name: &str) -> &str {
+    let name = name.trim();
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    match name.len().checked_sub(4) {
+        Some(i) if name.is_char_boundary(i) && name[i..].eq_ignore_ascii_case(".exe") => &name[..i],
+        _ => name,
+    }
+}
+
+/// Find the profile for the current foreground window (first matching rule wins)
+pub fn profile_for_foreground<'a>(
+    source: &dyn ForegroundSource,
+    rules: &'a [ProfileRule],
+) -> Option<(&'a str, ForegroundWindow)> {
+    if rules.is_empty() {
+        return None;
+    }
+    let window = source.foreground_window()?;
+    // winh 自身が最前面 (Start ボタンを押した) ときは切り替えない
+    if is_own_process(&window.process) {
+        return None;
+    }
+    rules
+        .iter()
+        .find(|rule| rule.matches(&window))
+        .map(|rule| (rule.profile.as_str(), window))
+}
+
+fn is_own_process(process: &str) -> bool {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
+        .is_some_and(|own| process_stem(&own).eq_ignore_ascii_case(process_stem(process)))
+}
+
+#[cfg(windows)]
+fn system_foreground_window() -> Result<Option<ForegroundWindow>, String> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId,
+    };
+
+    let hwnd = unsafe { GetForegroundWindow() };
+    if hwnd.is_null() {
+        return Ok(None);
+    }
+
+    let mut title_buf = [0u16; 512];
+    let len = unsafe { GetWindowTextW(hwnd, title_buf.as_mut_ptr(), title_buf.len() as i32) };
+    let title = String::from_utf16_lossy(&title_buf[..len.max(0) as usize]);
+
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+    let mut process = String::new();
+    if pid != 0 {
+        let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+        if !handle.is_null() {
+            let mut path_buf = [0u16; 1024];
+            let mut size = path_buf.len() as u32;
+            let ok = unsafe {
+                QueryFullProcessImageNameW(
+                    handle,
+                    PROCESS_NAME_WIN32,
+                    path_buf.as_mut_ptr(),
+                    &mut size,
+                )
+            };
+            unsafe { CloseHandle(handle) };
+            if ok != 0 {
+                let path = String::from_utf16_lossy(&path_buf[..size as usize]);
+                process = path.rsplit('\\').next().unwrap_or(&path).to_string();
+            }
+        }
+    }
+
+    Ok(Some(ForegroundWindow { process, title }))
+}
+
+/// X11 の `_NET_ACTIVE_WINDOW` から調べる
+/// (Wayland には共通の API が無いので、XWayland のアプリだけが対象)
+#[cfg(target_os = "linux")]
+fn system_foreground_window() -> Result<Option<ForegroundWindow>, String> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+    use x11rb::rust_connection::RustConnection;
+
+    if std::env::var_os("DISPLAY").is_none() {
+        return Ok(None);
+    }
+    let (conn, screen_num) =
+        RustConnection::connect(None).map_err(|e| format!("X11 connect failed: {}", e))?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let atom = |name: &[u8]| -> Result<u32, String> {
+        conn.intern_atom(false, name)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map(|r| r.atom)
+            .map_err(|e| e.to_string())
+    };
+    let property = |window: u32, prop: u32, prop_type: u32| {
+        conn.get_property(false, window, prop, prop_type, 0, 1024)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())
+    };
+
+    let active = property(root, atom(b"_NET_ACTIVE_WINDOW")?, AtomEnum::WINDOW.into())?;
+    let window = match active.value32().and_then(|mut v| v.next()) {
+        Some(w) if w != 0 => w,
+        _ => return Ok(None),
+    };
+
+    let name = property(window, atom(b"_NET_WM_NAME")?, atom(b"UTF8_STRING")?)?;
+    let title = String::from_utf8_lossy(&name.value).to_string();
+
+    let pid = property(window, atom(b"_NET_WM_PID")?, AtomEnum::CARDINAL.into())?;
+    let process = pid
+        .value32()
+        .and_then(|mut v| v.next())
+        .and_then(|pid| std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok())
+        .map(|comm| comm.trim().to_string())
+        .unwrap_or_default();
+
+    Ok(Some(ForegroundWindow { process, title }))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn system_foreground_window() -> Result<Option<ForegroundWindow>, String> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubForeground(Option<ForegroundWindow>);
+
+    impl ForegroundSource for StubForeground {
+        fn foreground_window(&self) -> Option<ForegroundWindow> {
+            self.0.clone()
+        }
+    }
+
+    fn window(process: &str, title: &str) -> StubForeground {
+        StubForeground(Some(ForegroundWindow {
+            process: process.to_string(),
+            title: title.to_string(),
+        }))
+    }
+
+    fn rule(process: &str, title_contains: &str, profile: &str) -> ProfileRule {
+        ProfileRule {
+            process: process.to_string(),
+            title_contains: title_contains.to_string(),
+            profile: profile.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_profile_for_foreground() {
+        let rules = vec![
+            rule("vrchat", "", "vrchat"),
+            rule("", "Slack", "slack"),
+            rule("Code.exe", "winh", "vscode-winh"),
+            rule("Code.exe", "", "vscode"),
+        ];
+        let pick = |source: StubForeground| {
+            profile_for_foreground(&source, &rules).map(|(name, _)| name.to_string())
+        };
+
+        assert_eq!(
+            pick(window("VRChat.exe", "VRChat")).as_deref(),
+            Some("vrchat")
+        );
+        assert_eq!(
+            pick(window("slack", "general - Acme - slack")).as_deref(),
+            Some("slack")
+        );
+        assert_eq!(
+            pick(window("C:\\Apps\\Code.exe", "main.rs - winh")).as_deref(),
+            Some("vscode-winh")
+        );
+        assert_eq!(
+            pick(window("code.exe", "notes.md")).as_deref(),
+            Some("vscode")
+        );
+        assert_eq!(pick(window("firefox", "Mozilla Firefox")), None);
+        assert_eq!(pick(StubForeground(None)), None);
+    }
+
+    #[test]
+    fn test_empty_rule_never_matches() {
+        assert!(!rule("", "", "x").matches(&ForegroundWindow::default()));
+        assert_eq!(process_stem("VRChat.EXE"), "VRChat");
+        assert_eq!(process_stem("/usr/bin/slack"), "slack");
+    }
+}
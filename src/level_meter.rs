@@ -0,0 +1,369 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// メーターの下限 (これより小さい音は -60 dBFS として扱う)
+pub const METER_FLOOR_DB: f32 = -60.0;
+
+/// この値以上のサンプルはクリップとみなす (≈ -0.01 dBFS)
+const CLIP_LEVEL: f32 = 0.999;
+
+/// ピーク表示が下がる速さ (dB/秒)
+const PEAK_FALLOFF_DB_PER_SEC: f32 = 20.0;
+
+/// RMS の平滑化の時定数 (秒)
+const RMS_TIME_CONSTANT_SECS: f32 = 0.3;
+
+/// クリップ表示を続ける時間
+const CLIP_HOLD_MS: u64 = 1000;
+
+/// キャリブレーションで貯めるブロックの数 (1 ms のバッファでも 30 秒分)
+const PEAK_LOG_CAPACITY: usize = 1 << 15;
+
+pub fn amplitude_to_dbfs(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return f32::NEG_INFINITY;
+    }
+    20.0 * amplitude.log10()
+}
+
+pub fn dbfs_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// One audio callback worth of samples, accumulated without allocating
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BlockLevel {
+    peak: f32,
+    sum_squares: f32,
+    count: usize,
+}
+
+impl BlockLevel {
+    pub fn add(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.sum_squares += sample * sample;
+        self.count += 1;
+    }
+}
+
+/// 入力レベルの表示用の値
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelSnapshot {
+    /// Smoothed RMS level in dBFS
+    pub rms_db: f32,
+    /// Peak with a slow falloff, in dBFS
+    pub peak_db: f32,
+    /// Peak of the most recent callback (linear, as compared with `silence_threshold`)
+    pub block_peak: f32,
+    /// A sample hit full scale within the last second
+    pub clipped: bool,
+}
+
+/// 録音コールバックで更新し、画面からロックせずに読む入力メーター
+/// f32 はビット列として atomic に入れる
+pub struct LevelMeter {
+    epoch: Instant,
+    rms: AtomicU32,
+    peak: AtomicU32,
+    block_peak: AtomicU32,
+    /// Milliseconds since `epoch` of the last clipped sample (+1, 0 = never)
+    last_clip_ms: AtomicU64,
+    peak_log: Option<PeakLog>,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            rms: AtomicU32::new(0f32.to_bits()),
+            peak: AtomicU32::new(0f32.to_bits()),
+            block_peak: AtomicU32::new(0f32.to_bits()),
+            last_clip_ms: AtomicU64::new(0),
+            peak_log: None,
+        }
+    }
+
+    /// A meter that also keeps the peak of every callback (for calibration)
+    pub fn with_peak_log() -> Self {
+        Self {
+            peak_log: Some(PeakLog::new(PEAK_LOG_CAPACITY)),
+            ..Self::new()
+        }
+    }
+
+    /// Called from the audio callback (only one writer)
+    pub fn update(&self, block: &BlockLevel, sample_rate: u32) {
+        if block.count == 0 || sample_rate == 0 {
+            return;
+        }
+        let dt = block.count as f32 / sample_rate as f32;
+
+        let block_rms = (block.sum_squares / block.count as f32).sqrt();
+        let rms = load(&self.rms);
+        let alpha = 1.0 - (-dt / RMS_TIME_CONSTANT_SECS).exp();
+        // パワーで平滑化する
+        let power = rms * rms + alpha * (block_rms * block_rms - rms * rms);
+        store(&self.rms, power.max(0.0).sqrt());
+
+        let falloff = dbfs_to_amplitude(-PEAK_FALLOFF_DB_PER_SEC * dt);
+        store(&self.peak, block.peak.max(load(&self.peak) * falloff));
+        store(&self.block_peak, block.peak);
+        if let Some(log) = &self.peak_log {
+            log.push(block, sample_rate);
+        }
+
+        if block.peak >= CLIP_LEVEL {
+            let now_ms = self.epoch.elapsed().as_millis() as u64 + 1;
+            self.last_clip_ms.store(now_ms, Ordering::Relaxed);
+        }
+    }
+
+    pub fn reset(&self) {
+        store(&self.rms, 0.0);
+        store(&self.peak, 0.0);
+        store(&self.block_peak, 0.0);
+        self.last_clip_ms.store(0, Ordering::Relaxed);
+        if let Some(log) = &self.peak_log {
+            log.clear();
+        }
+    }
+
+    /// `(秒, ピーク)` of every callback since the last reset (empty without a peak log)
+    pub fn peaks(&self) -> Vec<(f32, f32)> {
+        self.peak_log
+            .as_ref()
+            .map(PeakLog::read)
+            .unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> LevelSnapshot {
+        let last_clip = self.last_clip_ms.load(Ordering::Relaxed);
+        let now_ms = self.epoch.elapsed().as_millis() as u64 + 1;
+        LevelSnapshot {
+            rms_db: amplitude_to_dbfs(load(&self.rms)),
+            peak_db: amplitude_to_dbfs(load(&self.peak)),
+            block_peak: load(&self.block_peak),
+            clipped: last_clip != 0 && now_ms.saturating_sub(last_clip) < CLIP_HOLD_MS,
+        }
+    }
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 録音コールバックごとのピークを確保済みの領域に貯める (書き込みはコールバックだけ)
+/// 画面の更新の間隔に関係なく、全部のブロックを測定に使える
+struct PeakLog {
+    entries: Vec<(AtomicU32, AtomicU32)>,
+    len: AtomicUsize,
+    frames: AtomicU64,
+}
+
+impl PeakLog {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: (0..capacity)
+                .map(|_| (AtomicU32::new(0), AtomicU32::new(0)))
+                .collect(),
+            len: AtomicUsize::new(0),
+            frames: AtomicU64::new(0),
+        }
+    }
+
+    fn push(&self, block: &BlockLevel, sample_rate: u32) {
+        let frames = self.frames.load(Ordering::Relaxed);
+        self.frames
+            .store(frames + block.count as u64, Ordering::Relaxed);
+        let len = self.len.load(Ordering::Relaxed);
+        // 一杯になったら後は捨てる
+        let Some((time, peak)) = self.entries.get(len) else {
+            return;
+        };
+        store(time, frames as f32 / sample_rate as f32);
+        store(peak, block.peak);
+        self.len.store(len + 1, Ordering::Release);
+    }
+
+    fn clear(&self) {
+        self.len.store(0, Ordering::Release);
+        self.frames.store(0, Ordering::Relaxed);
+    }
+
+    fn read(&self) -> Vec<(f32, f32)> {
+        let len = self.len.load(Ordering::Acquire);
+        self.entries[..len]
+            .iter()
+            .map(|(time, peak)| (load(time), load(peak)))
+            .collect()
+    }
+}
+
+fn load(value: &AtomicU32) -> f32 {
+    f32::from_bits(value.load(Ordering::Relaxed))
+}
+
+fn store(value: &AtomicU32, v: f32) {
+    value.store(v.to_bits(), Ordering::Relaxed);
+}
+
+/// キャリブレーションで測った値と、そこから提案する設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationResult {
+    pub noise_floor_db: f32,
+    pub speech_db: Option<f32>,
+    pub silence_threshold: f32,
+    pub silence_duration_secs: Option<f32>,
+}
+
+/// 部屋の雑音 (と任意で話し声) のレベルを集める
+/// 値は `(経過秒, ピーク)` で、録音コールバックごとに `LevelMeter` が貯めたもの
+#[derive(Debug, Default, Clone)]
+pub struct Calibration {
+    pub noise: Vec<(f32, f32)>,
+    pub speech: Vec<(f32, f32)>,
+}
+
+impl Calibration {
+    /// Propose settings, or None if nothing was measured
+    pub fn result(&self) -> Option<CalibrationResult> {
+        let noise_peaks: Vec<f32> = self.noise.iter().map(|&(_, p)| p).collect();
+        // 雑音の 95 パーセンタイルの 2 倍 (+6 dB) を下限にする
+        let noise_floor = percentile(&noise_peaks, 0.95)?;
+        let min_threshold = (noise_floor * 2.0).max(0.001);
+
+        let speech_peaks: Vec<f32> = self.speech.iter().map(|&(_, p)| p).collect();
+        let speech_level = percentile(&speech_peaks, 0.5).filter(|&s| s > min_threshold);
+
+        // 話し声が測れたら、雑音と話し声の中間 (dB で) にする
+        let threshold = match speech_level {
+            Some(speech) => (noise_floor.max(0.0005) * speech).sqrt().max(min_threshold),
+            None => min_threshold,
+        }
+        .clamp(0.001, 0.3);
+
+        let silence_duration_secs = speech_level.map(|_| {
+            let pause = longest_pause(&self.speech, threshold);
+            ((pause + 0.7).clamp(1.0, 10.0) * 10.0).round() / 10.0
+        });
+
+        Some(CalibrationResult {
+            noise_floor_db: amplitude_to_dbfs(noise_floor),
+            speech_db: speech_level.map(amplitude_to_dbfs),
+            silence_threshold: threshold,
+            silence_duration_secs,
+        })
+    }
+}
+
+fn percentile(values: &[f32], p: f32) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+    Some(sorted[index])
+}
+
+/// 話している間の一番長い間 (最初の発声より前と最後の発声より後は数えない)
+fn longest_pause(samples: &[(f32, f32)], threshold: f32) -> f32 {
+    let mut longest = 0.0f32;
+    let mut last_sound: Option<f32> = None;
+    for &(time, peak) in samples {
+        if peak > threshold {
+            if let Some(last) = last_sound {
+                longest = longest.max(time - last);
+            }
+            last_sound = Some(time);
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(samples: &[f32]) -> BlockLevel {
+        let mut block = BlockLevel::default();
+        for &s in samples {
+            block.add(s);
+        }
+        block
+    }
+
+    #[test]
+    fn test_dbfs_conversion() {
+        assert_eq!(amplitude_to_dbfs(1.0), 0.0);
+        assert!((amplitude_to_dbfs(0.5) + 6.02).abs() < 0.01);
+        assert_eq!(amplitude_to_dbfs(0.0), f32::NEG_INFINITY);
+        assert!((dbfs_to_amplitude(-20.0) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_meter_peak_falloff_and_clip() {
+        let meter = LevelMeter::new();
+        assert_eq!(meter.snapshot().peak_db, f32::NEG_INFINITY);
+
+        meter.update(&block(&[0.5, -0.5, 0.5, -0.5]), 4);
+        let first = meter.snapshot();
+        assert!((first.peak_db + 6.02).abs() < 0.01);
+        assert!(!first.clipped);
+
+        // 1 秒の無音でピークは 20 dB 下がる
+        meter.update(&block(&[0.0; 4]), 4);
+        let second = meter.snapshot();
+        assert!((second.peak_db + 26.02).abs() < 0.01);
+        assert_eq!(second.block_peak, 0.0);
+        assert!(second.rms_db < first.rms_db);
+
+        meter.update(&block(&[1.0, -1.0]), 4);
+        assert!(meter.snapshot().clipped);
+        meter.reset();
+        assert!(!meter.snapshot().clipped);
+    }
+
+    #[test]
+    fn test_peak_log_keeps_every_block() {
+        assert!(LevelMeter::new().peaks().is_empty());
+
+        let meter = LevelMeter::with_peak_log();
+        meter.update(&block(&[0.1, -0.2]), 4);
+        meter.update(&block(&[0.0, 0.05]), 4);
+        meter.update(&block(&[0.4; 4]), 4);
+        assert_eq!(meter.peaks(), vec![(0.0, 0.2), (0.5, 0.05), (1.0, 0.4)]);
+        meter.reset();
+        assert!(meter.peaks().is_empty());
+    }
+
+    #[test]
+    fn test_calibration_proposes_threshold_between_noise_and_speech() {
+        let mut calibration = Calibration::default();
+        assert_eq!(calibration.result(), None);
+
+        calibration.noise = (0..30).map(|i| (i as f32 * 0.1, 0.002)).collect();
+        let noise_only = calibration.result().unwrap();
+        assert!((noise_only.silence_threshold - 0.004).abs() < 1e-6);
+        assert_eq!(noise_only.silence_duration_secs, None);
+
+        // 0.2 の声で、1.5 秒の間がある
+        calibration.speech = (0..50)
+            .map(|i| {
+                let time = i as f32 * 0.1;
+                let peak = if (1.5..3.0).contains(&time) {
+                    0.002
+                } else {
+                    0.2
+                };
+                (time, peak)
+            })
+            .collect();
+        let result = calibration.result().unwrap();
+        assert!((result.silence_threshold - 0.02).abs() < 1e-4);
+        assert!((result.speech_db.unwrap() + 13.98).abs() < 0.01);
+        assert_eq!(result.silence_duration_secs, Some(2.3));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::dsp::Biquad;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// 取り込んだ再生音 (mono) を受け取るコールバック
+pub type SampleSink = Box<dyn FnMut(Vec<f32>) + Send>;
+
+/// システムの再生音 (ループバック) の取り込み
+/// drop すると止まり、コールバックも drop される
+pub struct SystemCapture {
+    sample_rate: u32,
+    #[cfg(windows)]
+    _stream: cpal::Stream,
+    #[cfg(target_os = "linux")]
+    child: std::process::Child,
+    #[cfg(target_os = "linux")]
+    stopped: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl SystemCapture {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+/// 既定の出力デバイスで再生している音の取り込みを始める
+/// `make_sink` には実際のサンプルレートが渡される (`preferred_rate` になるとは限らない)
+/// 取り込みが途中で止まったら `stream_error` に理由を入れる
+#[cfg(windows)]
+pub fn start(
+    _preferred_rate: u32,
+    make_sink: impl FnOnce(u32) -> SampleSink,
+    stream_error: Arc<Mutex<Option<String>>>,
+) -> Result<SystemCapture, String> {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    // WASAPI では出力デバイスで入力ストリームを開くとループバックになる
+    // (共有モードなのでミックスの形式のまま受け取る)
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or("No output device available")?;
+    let config = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get default output config: {}", e))?;
+    eprintln!(
+        "Capturing system audio from: {}",
+        device.name().unwrap_or_default()
+    );
+
+    let sample_rate = config.sample_rate().0;
+    let sink = make_sink(sample_rate);
+    let stream = crate::audio::build_stream(
+        &device,
+        &config.config(),
+        config.sample_format(),
+        &[],
+        sink,
+        stream_error,
+    )?;
+    stream
+        .play()
+        .map_err(|e| format!("Failed to play loopback stream: {}", e))?;
+    Ok(SystemCapture {
+        sample_rate,
+        _stream: stream,
+    })
+}
+
+/// PulseAudio / PipeWire (pipewire-pulse) の既定の出力のモニターソースを parec で読む
+#[cfg(target_os = "linux")]
+pub fn start(
+    preferred_rate: u32,
+    make_sink: impl FnOnce(u32) -> SampleSink,
+    stream_error: Arc<Mutex<Option<String>>>,
+) -> Result<SystemCapture, String> {
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let sample_rate = if preferred_rate > 0 {
+        preferred_rate
+    } else {
+        48000
+    };
+    let mut child = Command::new("parec")
+        .args(parec_args(MONITOR_SOURCE, sample_rate))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| {
+            format!(
+                "Failed to start parec (install pulseaudio-utils to capture system audio): {}",
+                e
+            )
+        })?;
+    let mut stdout = child.stdout.take().ok_or("Failed to read from parec")?;
+    eprintln!("Capturing system audio from: {}", MONITOR_SOURCE);
+
+    let mut sink = make_sink(sample_rate);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stopped_by_us = Arc::clone(&stopped);
+    std::thread::spawn(move || {
+        // 20ms 分ずつ読む
+        let mut buffer = vec![0u8; (sample_rate as usize / 50).max(1) * 4];
+        let mut pending = Vec::new();
+        loop {
+            match stdout.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    pending.extend_from_slice(&buffer[..n]);
+                    let samples = decode_f32le(&mut pending);
+                    if !samples.is_empty() {
+                        sink(samples);
+                    }
+                }
+            }
+        }
+        if !stopped_by_us.load(Ordering::SeqCst) {
+            eprintln!("parec exited while recording");
+            stream_error
+                .lock()
+                .unwrap()
+                .get_or_insert("System audio capture stopped".to_string());
+        }
+    });
+
+    Ok(SystemCapture {
+        sample_rate,
+        child,
+        stopped,
+    })
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn start(
+    _preferred_rate: u32,
+    _make_sink: impl FnOnce(u32) -> SampleSink,
+    _stream_error: Arc<Mutex<Option<String>>>,
+) -> Result<SystemCapture, String> {
+    Err("System audio capture is not supported on this platform".to_string())
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for SystemCapture {
+    fn drop(&mut self) {
+        self.stopped
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// 既定の出力のモニター (PulseAudio と pipewire-pulse の特別な名前)
+#[cfg(target_os = "linux")]
+const MONITOR_SOURCE: &str = "@DEFAULT_MONITOR@";
+
+#[cfg(target_os = "linux")]
+fn parec_args(device: &str, sample_rate: u32) -> Vec<String> {
+    vec![
+        format!("--device={}", device),
+        "--format=float32le".to_string(),
+        format!("--rate={}", sample_rate),
+        "--channels=1".to_string(),
+        "--latency-msec=20".to_string(),
+    ]
+}
+
+/// 読めた分のバイト列をサンプルにして、4 バイトに満たない残りは次に回す
+#[cfg(target_os = "linux")]
+fn decode_f32le(pending: &mut Vec<u8>) -> Vec<f32> {
+    let whole = pending.len() / 4 * 4;
+    let samples = pending[..whole]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    pending.drain(..whole);
+    samples
+}
+
+/// 再生音をマイクのサンプルレートにする
+/// 取り込みのブロックをまたいで位置と直前のサンプルを引き継ぐ (ブロックの境目で音が飛ばないように)
+/// 下げるときは先に低域通過フィルタをかけて、新しいナイキスト周波数より上の音が折り返さないようにする
+pub struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output, in input samples where 0 is `previous`
+    position: f64,
+    previous: f32,
+    low_pass: Vec<Biquad>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        let low_pass = if to_rate < from_rate {
+            // 4 次 (2 段) で、新しいナイキスト周波数の 8 割から落とす
+            let cutoff = to_rate as f32 * 0.4;
+            vec![
+                Biquad::low_pass(cutoff, from_rate),
+                Biquad::low_pass(cutoff, from_rate),
+            ]
+        } else {
+            Vec::new()
+        };
+        Self {
+            step: from_rate as f64 / to_rate.max(1) as f64,
+            position: 1.0,
+            previous: 0.0,
+            low_pass,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        if self.step == 1.0 {
+            return samples.to_vec();
+        }
+        let mut input = samples.to_vec();
+        for filter in &mut self.low_pass {
+            filter.process(&mut input);
+        }
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.position < input.len() as f64 {
+            let index = self.position.floor() as usize;
+            let frac = (self.position - index as f64) as f32;
+            let a = match index {
+                0 => self.previous,
+                i => input[i - 1],
+            };
+            let b = input[index];
+            output.push(a + (b - a) * frac);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        if let Some(&last) = input.last() {
+            self.previous = last;
+        }
+        output
+    }
+}
+
+/// マイクと再生音を 1 本に混ぜる
+/// マイクのコールバックに合わせて、届いている再生音を同じ長さだけ足す
+/// (再生音が無い間は WASAPI から何も届かないので、足りない分は無音とみなす)
+#[derive(Default)]
+pub struct LoopbackMixer {
+    pending: VecDeque<f32>,
+}
+
+impl LoopbackMixer {
+    /// Queue system audio (already at the microphone's sample rate)
+    /// 1 秒を超えて溜まった分は古い方から捨てる (クロックのずれで遅れていかないように)
+    pub fn push(&mut self, samples: &[f32], sample_rate: u32) {
+        self.pending.extend(samples.iter().copied());
+        let excess = self.pending.len().saturating_sub(sample_rate as usize);
+        self.pending.drain(..excess);
+    }
+
+    /// Add the queued system audio to a block of microphone samples
+    pub fn mix(&mut self, mic: &mut [f32]) {
+        for sample in mic.iter_mut() {
+            let system = self.pending.pop_front().unwrap_or(0.0);
+            *sample = (*sample + system).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixer_adds_queued_audio_and_pads_with_silence() {
+        let mut mixer = LoopbackMixer::default();
+        mixer.push(&[0.1, 0.2, 0.3], 4);
+        let mut mic = vec![0.5, 0.5];
+        mixer.mix(&mut mic);
+        assert_eq!(mic, vec![0.6, 0.7]);
+
+        let mut mic = vec![0.9, 0.5];
+        mixer.mix(&mut mic);
+        assert_eq!(mic, vec![1.0, 0.5]);
+
+        // 溜まりすぎたら古い方を捨てる
+        mixer.push(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 4);
+        let mut mic = vec![0.0; 5];
+        mixer.mix(&mut mic);
+        assert_eq!(mic, vec![0.3, 0.4, 0.5, 0.6, 0.0]);
+    }
+
+    fn tone(hz: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * hz * i as f32 / sample_rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_resampler_is_continuous_across_blocks() {
+        let input = tone(440.0, 44100, 4410);
+        let whole = Resampler::new(44100, 16000).process(&input);
+
+        // 半端な長さのブロックに分けても同じ結果になる
+        let mut resampler = Resampler::new(44100, 16000);
+        let blocks: Vec<f32> = input
+            .chunks(441)
+            .flat_map(|block| resampler.process(block))
+            .collect();
+        assert_eq!(blocks.len(), whole.len());
+        assert!((blocks.len() as i64 - 1600).abs() <= 1);
+        for (a, b) in blocks.iter().zip(&whole) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_resampler_filters_before_downsampling() {
+        let mut voice = Resampler::new(48000, 16000);
+        let kept = voice.process(&tone(1000.0, 48000, 9600));
+        assert!(rms(&kept[800..]) > 0.3);
+
+        // 12 kHz は 16 kHz にすると 4 kHz に折り返すので、先に落とす
+        let mut high = Resampler::new(48000, 16000);
+        let aliased = high.process(&tone(12000.0, 48000, 9600));
+        assert!(rms(&aliased[800..]) < 0.05, "rms {}", rms(&aliased[800..]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parec_output_decoding() {
+        assert_eq!(
+            parec_args(MONITOR_SOURCE, 16000),
+            vec![
+                "--device=@DEFAULT_MONITOR@",
+                "--format=float32le",
+                "--rate=16000",
+                "--channels=1",
+                "--latency-msec=20"
+            ]
+        );
+
+        let mut pending: Vec<u8> = [0.5f32, -0.25]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        pending.extend_from_slice(&1.0f32.to_le_bytes()[..2]);
+        assert_eq!(decode_f32le(&mut pending), vec![0.5, -0.25]);
+        assert_eq!(pending.len(), 2);
+        pending.extend_from_slice(&1.0f32.to_le_bytes()[2..]);
+        assert_eq!(decode_f32le(&mut pending), vec![1.0]);
+        assert!(pending.is_empty());
+    }
+}
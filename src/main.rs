@@ -1,26 +1,67 @@
+mod assistant;
 mod audio;
+mod audio_monitor;
 mod auto_input;
+mod chat_completion;
+mod cli;
 mod config;
+mod config_watcher;
+mod conversation;
+mod device_watcher;
+mod dictation;
+mod dsp;
 mod eliza;
+mod foreground;
+mod level_meter;
+mod loopback;
+mod migration;
+mod osc_trigger;
+mod oscquery;
+mod profile;
+mod rewrite;
+mod secrets;
 mod speech_to_text;
+mod tools;
+mod translation;
+mod tts;
 mod vrchat;
 
-use audio::AudioRecorder;
+use assistant::AssistantEvent;
+use audio::{AudioRecorder, InputSource};
+use audio_monitor::AudioMonitor;
 use config::Config;
+use conversation::{ChatMessage, Conversation, ToolCall};
 use eframe::egui;
 use global_hotkey::{
     hotkey::{Code, HotKey, Modifiers},
-    GlobalHotKeyEvent, GlobalHotKeyManager,
+    GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState,
 };
+use level_meter::{Calibration, LevelSnapshot};
+use osc_trigger::{OscAction, OscTriggerEvent};
+use rewrite::{RewritePreview, RewriteTemplate};
 use speech_to_text::SpeechToTextClient;
-use std::sync::mpsc::{channel, Receiver};
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::UnboundedReceiver;
+use translation::TranslationLanguages;
 
 fn main() -> eframe::Result<()> {
     // Load config and apply command line arguments
-    let args: Vec<String> = std::env::args().collect();
-    let mut config = Config::load();
-    config.apply_args(&args);
+    let cli = cli::Cli::parse_args(std::env::args().collect());
+    let (mut config, config_warning) = match &cli.config {
+        Some(path) => Config::load_path(path),
+        None => Config::load(),
+    };
+    if let Err(e) = cli.apply_to(&mut config) {
+        eprintln!("Error: {}", e);
+        std::process::exit(2);
+    }
+
+    // サブコマンドがあれば GUI を起動せずに実行する
+    if let Some(command) = cli.command {
+        std::process::exit(cli::run(command, config));
+    }
 
     // Load application icon
     let icon_data = load_icon();
@@ -62,21 +103,79 @@ fn main() -> eframe::Result<()> {
 
             cc.egui_ctx.set_fonts(fonts);
 
-            Ok(Box::new(WinhApp::new(config)))
+            Ok(Box::new(WinhApp::new(config, config_warning)))
         }),
     )
 }
 
 const USER_PREFIX: &str = "> ";
 const ELIZA_PREFIX: &str = "AI> ";
+/// 1回の発言に対してツール呼び出しを繰り返せる最大回数
+const MAX_TOOL_ROUNDS: usize = 4;
+const AVATAR_ERROR_HOLD: std::time::Duration = std::time::Duration::from_secs(3);
 
 pub enum TranscriptionMessage {
     InProgress,
     Partial(String),
+    /// 連続ディクテーションで確定した区切り (録音は続いている)
+    Segment(String),
     Success(String),
     Error(String),
 }
 
+/// 雑音・話し声を測る時間
+const CALIBRATION_NOISE_SECS: f32 = 3.0;
+const CALIBRATION_SPEECH_SECS: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CalibrationStep {
+    Intro,
+    Noise,
+    SpeechIntro,
+    Speech,
+    Result,
+}
+
+/// マイクのキャリブレーション (設定画面から開く)
+struct CalibrationWizard {
+    step: CalibrationStep,
+    step_start: std::time::Instant,
+    recorder: Option<AudioRecorder>,
+    data: Calibration,
+    error: Option<String>,
+}
+
+impl CalibrationWizard {
+    fn new() -> Self {
+        Self {
+            step: CalibrationStep::Intro,
+            step_start: std::time::Instant::now(),
+            recorder: None,
+            data: Calibration::default(),
+            error: None,
+        }
+    }
+
+    fn set_step(&mut self, step: CalibrationStep) {
+        self.step = step;
+        self.step_start = std::time::Instant::now();
+    }
+}
+
+/// DSP の聞き比べで録る秒数
+const DSP_AB_SECS: f32 = 4.0;
+
+/// DSP の聞き比べ (設定画面で録音し、処理前と処理後を再生する)
+struct DspAbTest {
+    recorder: Option<AudioRecorder>,
+    receiver: UnboundedReceiver<Vec<f32>>,
+    started: std::time::Instant,
+    raw: Vec<f32>,
+    sample_rate: u32,
+    // 再生中の音を止めるための世代番号
+    playback: Arc<std::sync::atomic::AtomicU64>,
+}
+
 struct WinhApp {
     is_recording: bool,
     is_preparing: bool,
@@ -85,6 +184,12 @@ struct WinhApp {
     audio_recorder: Option<AudioRecorder>,
     status_message: String,
     recording_info: String,
+    // 連続ディクテーション中のセッションと、区切りの合図を STT に送るチャンネル
+    continuous_session: Option<dictation::ContinuousSession>,
+    segment_sender: Option<tokio::sync::mpsc::UnboundedSender<()>>,
+    // 出力待ちの区切り (テキスト, 翻訳言語, 書き直しテンプレート名)
+    // 翻訳・書き直しは 1 つずつなので、前の区切りが終わるまで待たせる
+    pending_segments: dictation::SegmentQueue<(String, Option<TranslationLanguages>, String)>,
     // Config
     config: Config,
 
@@ -98,14 +203,72 @@ struct WinhApp {
     settings_xai_api_key: String,
     settings_silence_duration: f32,
     settings_silence_threshold: f32,
+    settings_dsp: dsp::DspSettings,
+    settings_continuous: dictation::ContinuousSettings,
+    dsp_ab: Option<DspAbTest>,
+    dsp_ab_error: Option<String>,
     settings_input_device: Option<String>,
     settings_hotkey: String,
+    settings_stt_language: String,
+    settings_remove_punctuation: bool,
+    settings_profile_switch_hotkey: String,
+    settings_new_profile_name: String,
+    settings_auto_profile_switching: bool,
+    settings_osc_send_addr: String,
+    settings_osc_listen_addr: String,
+    settings_oscquery_enabled: bool,
+    settings_osc_double_toggle: f32,
+    // OSC トリガールール (JSON で編集する)
+    settings_osc_trigger_rules: String,
+    settings_avatar_parameters_enabled: bool,
+    settings_chatbox_page_dwell: f32,
+    settings_chatbox_page_marker: bool,
     settings_eliza_url: String,
     settings_eliza_gesture: i32,
+    settings_eliza_max_turns: usize,
+    settings_eliza_max_tokens: usize,
+    settings_eliza_persist_conversation: bool,
+    settings_eliza_reset_hotkey: String,
+    settings_assistant_backend: assistant::AssistantBackend,
+    settings_rewrite_template: String,
+    settings_rewrite_url: String,
+    settings_rewrite_model: String,
+    settings_rewrite_api_key: String,
+    settings_rewrite_preview: bool,
+    settings_translation_enabled: bool,
+    settings_translation_backend: translation::TranslationBackend,
+    settings_translation_url: String,
+    settings_translation_model: String,
+    settings_translation_api_key: String,
+    settings_translation_source_lang: String,
+    settings_translation_target_lang: String,
+    settings_translation_output: translation::TranslationOutput,
+    settings_assistant_streaming: bool,
+    settings_assistant_tools_enabled: bool,
+    settings_chat_completion_base_url: String,
+    settings_chat_completion_api_key: String,
+    settings_chat_completion_model: String,
+    settings_chat_completion_system_prompt: String,
+    settings_chat_completion_temperature: f32,
+    settings_tts_enabled: bool,
+    settings_tts_engine: tts::TtsEngine,
+    settings_tts_command_args: String,
+    settings_tts_output_device: Option<String>,
+    available_output_devices: Vec<String>,
 
     // Device management
     available_devices: Vec<String>,
     selected_device_index: usize,
+    // 今つながっている入力デバイス (抜き差しを監視して更新する)
+    connected_devices: Vec<String>,
+    device_watcher: device_watcher::DeviceWatcher,
+    settings_input_device_fallbacks: String,
+    settings_input_device_fallback_to_default: bool,
+    settings_input_source: InputSource,
+    // チャンネルはカンマ区切りで編集する
+    settings_input_channels: String,
+    settings_input_sample_rate: Option<u32>,
+    settings_input_buffer_size: Option<u32>,
 
     // Error tracking
     last_error: Option<String>,
@@ -113,25 +276,84 @@ struct WinhApp {
     // Global hotkey management
     hotkey_manager: GlobalHotKeyManager,
     current_hotkey: HotKey,
-
-    // VRChat mute trigger (i32 = GestureRight value at trigger time)
-    mute_trigger_receiver: Receiver<i32>,
+    reset_conversation_hotkey: Option<HotKey>,
+    // プロファイル切り替えのホットキーと、そのプロファイル名
+    profile_hotkeys: Vec<(HotKey, String)>,
+    // 翻訳・書き直しを指定して録音するホットキー
+    recording_hotkeys: Vec<(HotKey, RecordingHotkey)>,
+    // 今回の録音で使う翻訳言語 (None なら設定の既定に従う)
+    recording_translation: Option<TranslationLanguages>,
+    // Background translation result receiver と翻訳中の原文
+    translation_receiver: Option<Receiver<Result<String, String>>>,
+    translation_original: String,
+    // 今回の録音で使う書き直しテンプレート名
+    recording_rewrite: Option<String>,
+    // 今回の文字起こしに適用する書き直しテンプレート
+    transcript_rewrite: Option<RewriteTemplate>,
+    // Background rewrite result receiver (書き直し前のテキストは rewrite_preview に持つ)
+    rewrite_receiver: Option<Receiver<Result<String, String>>>,
+    // 採用待ちの書き直し結果と、取り消し用の直前の書き直し (原文, 書き直し)
+    rewrite_preview: Option<RewritePreview>,
+    last_rewrite: Option<(String, String)>,
+
+    // VRChat OSC client (chatbox send queue)
+    vrchat_client: vrchat::VRChatClient,
+    // アバターパラメータに反映している状態
+    avatar_state: vrchat::AvatarState,
+    avatar_state_since: std::time::Instant,
+
+    // VRChat OSC trigger (avatar parameter rules)
+    osc_trigger_sender: Sender<OscTriggerEvent>,
+    osc_trigger_receiver: Receiver<OscTriggerEvent>,
+    osc_listener: Option<vrchat::OscListener>,
+    oscquery_service: Option<oscquery::OscQueryService>,
+    // OSC の bind 失敗などのエラー (UI に表示する)
+    osc_error: Option<String>,
+    // 設定ファイルを読み込めなかった・新しすぎるなどの警告 (閉じるまで表示する)
+    config_warning: Option<String>,
+    // 変更された config.json を反映できなかったときの警告 (次に反映できたら消す)
+    reload_warning: Option<String>,
+    // config.json の変更監視 (外部で編集されたら読み直す)
+    config_watcher: Option<config_watcher::ConfigWatcher>,
+    // マイクのキャリブレーション中なら Some
+    calibration: Option<CalibrationWizard>,
+    // 直近の録音の波形・スペクトログラム (別ウィンドウで表示する)
+    audio_monitor: Arc<Mutex<AudioMonitor>>,
+    // 画面側の複製 (スペクトログラムの計算と描画はロックの外で行う)
+    audio_monitor_view: AudioMonitor,
+    show_audio_monitor: bool,
+    spectrogram_texture: Option<egui::TextureHandle>,
     // eliza モードで録音開始したかどうか
     eliza_mode: bool,
-    // Background eliza response receiver
-    eliza_response_receiver: Option<Receiver<Result<String, String>>>,
+    // Background eliza response events (streamed)
+    eliza_response_receiver: Option<Receiver<AssistantEvent>>,
+    // ストリーミング中 (または直前) の返答テキストと、chatbox に途中経過を送った時刻・内容
+    assistant_reply: String,
+    assistant_preview_sent: Option<(std::time::Instant, String)>,
+    // 実行中の問い合わせのメッセージ列 (ツールの結果を付け足して再送する)
+    assistant_request: Vec<ChatMessage>,
+    // 未実行のツール呼び出しと、ユーザーの確認待ちのツール呼び出し
+    pending_tool_calls: VecDeque<ToolCall>,
+    tool_confirmation: Option<ToolCall>,
+    // 実行中のツール呼び出しと結果の受け取り口
+    running_tool: Option<(ToolCall, Receiver<Result<String, String>>)>,
+    tool_rounds: usize,
+    // アシスタントの返答の読み上げ
+    tts_player: tts::TtsPlayer,
+    // Eliza との会話履歴と、返答待ちのユーザー発言
+    conversation: Conversation,
+    eliza_pending_text: Option<String>,
 }
 
 impl WinhApp {
-    fn new(config: Config) -> Self {
+    fn new(config: Config, config_warning: Option<String>) -> Self {
         // Get available input devices
-        let mut available_devices = audio::get_input_devices().unwrap_or_else(|e| {
+        let connected_devices = audio::get_input_devices().unwrap_or_else(|e| {
             eprintln!("Failed to get input devices: {}", e);
             vec![]
         });
-
-        // Add "Windows既定" as first option
-        available_devices.insert(0, "Windows既定".to_string());
+        let available_devices =
+            device_options(&connected_devices, &[config.input_device_name.as_deref()]);
 
         // Find the index of the configured device
         let selected_device_index = if let Some(ref device_name) = config.input_device_name {
@@ -159,11 +381,45 @@ impl WinhApp {
             println!("Global hotkey registered: {}", config.hotkey);
         }
 
-        // Setup VRChat mute trigger channel and start listener
-        let (mute_trigger_sender, mute_trigger_receiver) = channel::<i32>();
-        vrchat::start_mute_listener(mute_trigger_sender.clone());
+        let mut vrchat_client = vrchat::VRChatClient::with_target(config.osc_send_addr.clone());
+        vrchat_client.page_dwell = config.chatbox_page_dwell();
+        vrchat_client.page_marker = config.chatbox_page_marker;
+
+        // Register optional "reset conversation" hotkey
+        let reset_conversation_hotkey = register_optional_hotkey(
+            &hotkey_manager,
+            &config.eliza_reset_hotkey,
+            "Reset conversation",
+        );
+
+        // Register profile switch hotkeys
+        let profile_hotkeys = register_profile_hotkeys(&hotkey_manager, &config);
+
+        // Register translation and rewrite template hotkeys (each starts recording with its own options)
+        let recording_hotkeys = register_recording_hotkeys(&hotkey_manager, &config);
+
+        // Watch config.json so hand edits are applied without restarting
+        let config_watcher = config
+            .path
+            .clone()
+            .map(Ok)
+            .unwrap_or_else(Config::config_path)
+            .and_then(|path| config_watcher::ConfigWatcher::new(&path))
+            .map_err(|e| eprintln!("{}", e))
+            .ok();
+
+        // Restore conversation history if persistence is enabled
+        let mut conversation = Conversation::new(config.eliza_max_turns, config.eliza_max_tokens);
+        if config.eliza_persist_conversation {
+            if let Err(e) = Conversation::default_path().and_then(|p| conversation.load(&p)) {
+                eprintln!("Failed to load conversation: {}", e);
+            }
+        }
+
+        // Setup VRChat OSC trigger channel (listener is started below)
+        let (osc_trigger_sender, osc_trigger_receiver) = channel::<OscTriggerEvent>();
 
-        Self {
+        let mut app = Self {
             is_recording: false,
             is_preparing: false,
             prepare_start_time: None,
@@ -171,15 +427,85 @@ impl WinhApp {
             audio_recorder: None,
             status_message: String::new(),
             recording_info: String::new(),
+            continuous_session: None,
+            segment_sender: None,
+            pending_segments: dictation::SegmentQueue::default(),
             settings_xai_api_key: config.xai_api_key.clone(),
             settings_silence_duration: config.silence_duration_secs,
             settings_silence_threshold: config.silence_threshold,
+            settings_dsp: config.dsp.clone(),
+            settings_continuous: config.continuous.clone(),
+            dsp_ab: None,
+            dsp_ab_error: None,
             settings_input_device: config.input_device_name.clone(),
             settings_hotkey: config.hotkey.clone(),
+            settings_stt_language: config.stt_language.clone(),
+            settings_remove_punctuation: config.remove_punctuation,
+            settings_profile_switch_hotkey: config
+                .profiles
+                .iter()
+                .find(|p| p.name == config.active_profile)
+                .map(|p| p.switch_hotkey.clone())
+                .unwrap_or_default(),
+            settings_new_profile_name: String::new(),
+            settings_auto_profile_switching: config.auto_profile_switching,
+            settings_osc_send_addr: config.osc_send_addr.clone(),
+            settings_osc_listen_addr: config.osc_listen_addr.clone(),
+            settings_oscquery_enabled: config.oscquery_enabled,
+            settings_osc_double_toggle: config.osc_double_toggle_secs,
+            settings_osc_trigger_rules: osc_rules_text(&config.osc_trigger_rules),
+            settings_avatar_parameters_enabled: config.avatar_parameters_enabled,
+            settings_chatbox_page_dwell: config.chatbox_page_dwell_secs,
+            settings_chatbox_page_marker: config.chatbox_page_marker,
             settings_eliza_url: config.eliza_url.clone(),
             settings_eliza_gesture: config.eliza_gesture,
+            settings_eliza_max_turns: config.eliza_max_turns,
+            settings_eliza_max_tokens: config.eliza_max_tokens,
+            settings_eliza_persist_conversation: config.eliza_persist_conversation,
+            settings_eliza_reset_hotkey: config.eliza_reset_hotkey.clone(),
+            settings_assistant_backend: config.assistant_backend,
+            settings_rewrite_template: config.rewrite_template.clone(),
+            settings_rewrite_url: config.rewrite_url.clone(),
+            settings_rewrite_model: config.rewrite_model.clone(),
+            settings_rewrite_api_key: config.rewrite_api_key.clone(),
+            settings_rewrite_preview: config.rewrite_preview,
+            settings_translation_enabled: config.translation_enabled,
+            settings_translation_backend: config.translation_backend,
+            settings_translation_url: config.translation_url.clone(),
+            settings_translation_model: config.translation_model.clone(),
+            settings_translation_api_key: config.translation_api_key.clone(),
+            settings_translation_source_lang: config.translation_source_lang.clone(),
+            settings_translation_target_lang: config.translation_target_lang.clone(),
+            settings_translation_output: config.translation_output,
+            settings_assistant_streaming: config.assistant_streaming,
+            settings_assistant_tools_enabled: config.assistant_tools_enabled,
+            settings_chat_completion_base_url: config.chat_completion_base_url.clone(),
+            settings_chat_completion_api_key: config.chat_completion_api_key.clone(),
+            settings_chat_completion_model: config.chat_completion_model.clone(),
+            settings_chat_completion_system_prompt: config.chat_completion_system_prompt.clone(),
+            settings_chat_completion_temperature: config.chat_completion_temperature,
+            settings_tts_enabled: config.tts_enabled,
+            settings_tts_engine: config.tts_engine.clone(),
+            settings_tts_command_args: tts_command_args(&config.tts_engine),
+            settings_tts_output_device: config.tts_output_device.clone(),
+            available_output_devices: tts::get_output_devices().unwrap_or_else(|e| {
+                eprintln!("Failed to get output devices: {}", e);
+                vec![]
+            }),
+            tts_player: tts::TtsPlayer::new(
+                config.tts_engine.clone(),
+                config.tts_output_device.clone(),
+            ),
             available_devices,
             selected_device_index,
+            connected_devices,
+            device_watcher: device_watcher::DeviceWatcher::new(),
+            settings_input_device_fallbacks: config.input_device_fallbacks.join("\n"),
+            settings_input_device_fallback_to_default: config.input_device_fallback_to_default,
+            settings_input_source: config.input_source,
+            settings_input_channels: channel_list_text(&config.input_format.channels),
+            settings_input_sample_rate: config.input_format.sample_rate,
+            settings_input_buffer_size: config.input_format.buffer_size,
             config,
             transcription_receiver: None,
             is_transcribing: false,
@@ -188,58 +514,176 @@ impl WinhApp {
             last_error: None,
             hotkey_manager,
             current_hotkey,
-            mute_trigger_receiver,
+            reset_conversation_hotkey,
+            profile_hotkeys,
+            recording_hotkeys,
+            recording_translation: None,
+            translation_receiver: None,
+            translation_original: String::new(),
+            recording_rewrite: None,
+            transcript_rewrite: None,
+            rewrite_receiver: None,
+            rewrite_preview: None,
+            last_rewrite: None,
+            vrchat_client,
+            avatar_state: vrchat::AvatarState::Idle,
+            avatar_state_since: std::time::Instant::now(),
+            osc_trigger_sender,
+            osc_trigger_receiver,
+            osc_listener: None,
+            oscquery_service: None,
+            osc_error: None,
+            config_warning,
+            reload_warning: None,
+            config_watcher,
+            calibration: None,
+            audio_monitor: Arc::new(Mutex::new(AudioMonitor::default())),
+            audio_monitor_view: AudioMonitor::default(),
+            show_audio_monitor: false,
+            spectrogram_texture: None,
             eliza_mode: false,
             eliza_response_receiver: None,
-        }
+            assistant_reply: String::new(),
+            assistant_preview_sent: None,
+            assistant_request: Vec::new(),
+            pending_tool_calls: VecDeque::new(),
+            tool_confirmation: None,
+            running_tool: None,
+            tool_rounds: 0,
+            conversation,
+            eliza_pending_text: None,
+        };
+        app.restart_osc_listener();
+        // 前回の終了状態が残っていても idle から始める
+        app.send_avatar_state();
+        app
     }
-}
 
-impl eframe::App for WinhApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Check for eliza response and send to VRChat chatbox
-        if let Some(ref receiver) = self.eliza_response_receiver {
-            if let Ok(result) = receiver.try_recv() {
-                match result {
-                    Ok(response) => {
-                        println!("[Eliza] Response received → send to VRChat: {}", response);
-                        let client = vrchat::VRChatClient::new();
-                        if let Err(e) = client.send_message(&format!("{}{}", ELIZA_PREFIX, response)) {
-                            eprintln!("[Eliza] Failed to send response to VRChat: {}", e);
-                        }
-                    }
+    /// VRChat からの OSC 受信を (再) 開始し、必要なら OSCQuery で広告する
+    /// OSCQuery 有効時に設定ポートが使用中なら空きポートで受信する
+    fn restart_osc_listener(&mut self) {
+        // 先に古い listener を止めてポートを解放する
+        self.oscquery_service = None;
+        self.osc_listener = None;
+        self.osc_error = None;
+
+        let engine = || {
+            osc_trigger::TriggerEngine::new(
+                self.config.osc_trigger_rules.clone(),
+                self.config.osc_double_toggle_secs,
+            )
+        };
+        let listener = match vrchat::start_osc_listener(
+            &self.config.osc_listen_addr,
+            engine(),
+            self.osc_trigger_sender.clone(),
+        ) {
+            Ok(listener) => listener,
+            Err(e) if self.config.oscquery_enabled => {
+                eprintln!("{} (falling back to a dynamic port)", e);
+                match vrchat::start_osc_listener(
+                    "127.0.0.1:0",
+                    engine(),
+                    self.osc_trigger_sender.clone(),
+                ) {
+                    Ok(listener) => listener,
                     Err(e) => {
-                        eprintln!("[Eliza] Error: {}", e);
+                        self.osc_error = Some(e.to_string());
+                        return;
                     }
                 }
-                self.eliza_response_receiver = None;
             }
-        }
+            Err(e) => {
+                eprintln!("[VRChat OSC Listener] {}", e);
+                self.osc_error = Some(e.to_string());
+                return;
+            }
+        };
 
-        // Check for VRChat mute trigger
-        if let Ok(gesture_right) = self.mute_trigger_receiver.try_recv() {
-            if !self.is_recording && !self.is_transcribing && !self.is_preparing {
-                let eliza_mode =
-                    self.config.eliza_enabled && gesture_right == self.config.eliza_gesture;
-                println!(
-                    "VRChat mute trigger received → start recording (gesture_right={}, eliza_mode={})",
-                    gesture_right, eliza_mode
-                );
-                self.eliza_mode = eliza_mode;
-                self.is_recording = true;
-                self.on_actually_start_recording();
+        if self.config.oscquery_enabled {
+            let endpoints = oscquery::advertised_endpoints(&self.config.osc_trigger_rules);
+            match oscquery::OscQueryService::start(listener.local_addr.port(), &endpoints) {
+                Ok(service) => self.oscquery_service = Some(service),
+                Err(e) => {
+                    eprintln!("[OSCQuery] {}", e);
+                    self.osc_error = Some(e);
+                }
             }
         }
+        self.osc_listener = Some(listener);
+    }
+}
+
+impl eframe::App for WinhApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Check for streamed eliza response and send to VRChat chatbox
+        self.poll_assistant_events();
+        self.poll_tool_call();
+
+        // Check for finished translation / rewrite and send to outputs
+        self.poll_translation();
+        self.poll_rewrite();
+        self.poll_segments();
+
+        // Apply hand edits of config.json
+        if self
+            .config_watcher
+            .as_mut()
+            .is_some_and(|watcher| watcher.poll())
+        {
+            self.reload_config();
+        }
+
+        // エラー表示のアバターパラメータは一定時間で idle に戻す
+        if self.avatar_state == vrchat::AvatarState::Error
+            && self.avatar_state_since.elapsed() >= AVATAR_ERROR_HOLD
+        {
+            self.set_avatar_state(vrchat::AvatarState::Idle);
+        }
+
+        // Check for VRChat OSC triggers
+        while let Ok(event) = self.osc_trigger_receiver.try_recv() {
+            self.on_osc_trigger(event);
+        }
 
         // Check for global hotkey events
         if let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
-            if event.id == self.current_hotkey.id() {
+            if Some(event.id) == self.reset_conversation_hotkey.map(|h| h.id())
+                && event.state == HotKeyState::Pressed
+            {
+                println!("Reset conversation hotkey triggered");
+                self.reset_conversation();
+            } else if event.id == self.current_hotkey.id() {
                 println!("Global hotkey triggered: {}", self.config.hotkey);
-                if !self.is_recording && !self.is_transcribing && !self.is_preparing {
-                    self.is_recording = true;
-                    self.is_preparing = false;
-                    self.is_transcribing = false;
-                    self.on_actually_start_recording();
+                // 連続ディクテーション中はもう一度押すと終わる
+                if self.is_recording
+                    && self.continuous_session.is_some()
+                    && event.state == HotKeyState::Pressed
+                {
+                    println!("Stopping continuous dictation");
+                    self.is_recording = false;
+                    self.on_stop_recording();
+                } else if !self.is_recording && !self.is_transcribing && !self.is_preparing {
+                    self.start_recording();
+                }
+            } else if let Some((_, profile)) = self
+                .profile_hotkeys
+                .iter()
+                .find(|(hotkey, _)| hotkey.id() == event.id)
+                .cloned()
+            {
+                if event.state == HotKeyState::Pressed {
+                    println!("Profile hotkey triggered: {}", profile);
+                    self.switch_profile(&profile);
+                }
+            } else if let Some((_, options)) = self
+                .recording_hotkeys
+                .iter()
+                .find(|(hotkey, _)| hotkey.id() == event.id)
+                .cloned()
+            {
+                if event.state == HotKeyState::Pressed {
+                    self.on_recording_hotkey(options);
                 }
             }
         }
@@ -250,8 +694,7 @@ impl eframe::App for WinhApp {
                 if start_time.elapsed() >= std::time::Duration::from_millis(500) {
                     self.is_preparing = false;
                     self.prepare_start_time = None;
-                    self.is_recording = true;
-                    self.on_actually_start_recording();
+                    self.start_recording();
                 }
             }
             ctx.request_repaint();
@@ -268,107 +711,42 @@ impl eframe::App for WinhApp {
                     TranscriptionMessage::Partial(text) => {
                         self.transcribed_text = text;
                     }
+                    TranscriptionMessage::Segment(text) => {
+                        println!("Dictation segment: {}", text);
+                        let languages = self.recording_translation.clone().or_else(|| {
+                            self.config
+                                .translation_enabled
+                                .then(|| self.config.translation_languages())
+                        });
+                        let rewrite_name = self
+                            .recording_rewrite
+                            .clone()
+                            .unwrap_or_else(|| self.config.rewrite_template.clone());
+                        self.pending_segments.push((text, languages, rewrite_name));
+                    }
                     TranscriptionMessage::Success(text) => {
-                        self.transcribed_text = text.clone();
                         self.last_error = None;
 
-                        let mut status_parts = Vec::new();
-
-                        // Conditional clipboard copy
-                        if self.config.clipboard_enabled {
-                            match arboard::Clipboard::new() {
-                                Ok(mut clipboard) => match clipboard.set_text(&text) {
-                                    Ok(_) => {
-                                        status_parts.push("copied to clipboard");
-                                        println!("Text copied to clipboard: {}", text);
-                                    }
-                                    Err(e) => {
-                                        status_parts.push("clipboard failed");
-                                        eprintln!("Clipboard error: {}", e);
-                                    }
-                                },
-                                Err(e) => {
-                                    status_parts.push("clipboard init failed");
-                                    eprintln!("Clipboard init error: {}", e);
-                                }
-                            }
-                        }
-
-                        // Conditional VRChat OSC send
-                        if self.config.vrchat_enabled && !text.is_empty() {
-                            let client = vrchat::VRChatClient::new();
-                            match client.send_message(&format!("{}{}", USER_PREFIX, text)) {
-                                Ok(_) => {
-                                    status_parts.push("sent to VRChat");
-                                    println!("Text sent to VRChat via OSC: {}", text);
-                                }
-                                Err(e) => {
-                                    status_parts.push("VRChat send failed");
-                                    eprintln!("VRChat OSC error: {}", e);
-                                }
-                            }
-                        }
-
-                        // Conditional eliza-agent-server send (background)
-                        if self.eliza_mode && !text.is_empty() {
-                            let eliza_url = self.config.eliza_url.clone();
-                            let eliza_text = text.clone();
-                            let (eliza_sender, eliza_receiver) =
-                                channel::<Result<String, String>>();
-                            std::thread::spawn(move || {
-                                let client = eliza::ElizaClient::new(eliza_url);
-                                let _ = eliza_sender.send(client.send_chat(&eliza_text));
-                            });
-                            self.eliza_response_receiver = Some(eliza_receiver);
-                            status_parts.push("sent to Eliza");
-                        }
-                        self.eliza_mode = false;
-
-                        // Conditional auto-input
-                        if self.config.auto_input_enabled {
-                            // If clipboard is enabled, use Ctrl+V to paste
-                            // Otherwise, type the text character-by-character
-                            // If send_enter is enabled, use the _with_enter variants
-                            let result = match (
-                                self.config.clipboard_enabled,
-                                self.config.auto_input_send_enter,
-                            ) {
-                                (true, true) => {
-                                    status_parts.push("auto-input (Ctrl+V + Enter) started");
-                                    println!("Auto-input (Ctrl+V + Enter) started");
-                                    auto_input::send_ctrl_v_with_enter()
-                                }
-                                (true, false) => {
-                                    status_parts.push("auto-input (Ctrl+V) started");
-                                    println!("Auto-input (Ctrl+V) started");
-                                    auto_input::send_ctrl_v()
-                                }
-                                (false, true) => {
-                                    status_parts.push("auto-input (typing + Enter) started");
-                                    println!("Auto-input (typing + Enter) started");
-                                    auto_input::type_text_with_enter(&text)
-                                }
-                                (false, false) => {
-                                    status_parts.push("auto-input (typing) started");
-                                    println!("Auto-input (typing) started");
-                                    auto_input::type_text(&text)
-                                }
-                            };
-
-                            if let Err(e) = result {
-                                status_parts.pop(); // Remove the "started" message
-                                status_parts.push("auto-input failed");
-                                eprintln!("Auto-input error: {}", e);
+                        let languages = self.recording_translation.take().or_else(|| {
+                            self.config
+                                .translation_enabled
+                                .then(|| self.config.translation_languages())
+                        });
+                        let rewrite_name = self
+                            .recording_rewrite
+                            .take()
+                            .unwrap_or_else(|| self.config.rewrite_template.clone());
+                        if self.continuous_session.is_some() {
+                            // 各区切りはもう出力に回しているので、セッションを終えるだけ
+                            self.end_continuous_session();
+                            if self.pending_segments.is_empty() {
+                                self.transcribed_text = text;
+                                self.status_message = "Dictation finished".to_string();
                             }
-                        }
-
-                        // Build status message
-                        let base = "Transcription completed";
-                        self.status_message = if status_parts.is_empty() {
-                            format!("{}!", base)
                         } else {
-                            format!("{} ({})", base, status_parts.join(", "))
-                        };
+                            self.transcribed_text = text.clone();
+                            self.process_transcript(text, languages, &rewrite_name);
+                        }
 
                         self.is_transcribing = false;
                         self.transcription_receiver = None;
@@ -377,9 +755,12 @@ impl eframe::App for WinhApp {
                         }
                     }
                     TranscriptionMessage::Error(error) => {
+                        self.end_continuous_session();
                         self.status_message = format!("❌ Transcription failed: {}", error);
                         self.last_error = Some(error.clone());
                         self.is_transcribing = false;
+                        self.set_vrchat_typing(false);
+                        self.set_avatar_state(vrchat::AvatarState::Error);
                         self.transcription_receiver = None;
                         if let Some(rt) = self.tokio_runtime.take() {
                             rt.shutdown_background();
@@ -390,6 +771,55 @@ impl eframe::App for WinhApp {
             }
         }
 
+        // Tool call confirmation dialog
+        if let Some(call) = &self.tool_confirmation {
+            let description = match self.find_tool(&call.function.name) {
+                Some(tool) => tool.describe_call(&call.function.arguments),
+                None => call.function.name.clone(),
+            };
+            let mut answer = None;
+            egui::Window::new("Allow tool call?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("The assistant wants to run:");
+                    ui.monospace(&description);
+                    ui.horizontal(|ui| {
+                        if ui.button("Allow").clicked() {
+                            answer = Some(true);
+                        }
+                        if ui.button("Deny").clicked() {
+                            answer = Some(false);
+                        }
+                    });
+                });
+            if let Some(allowed) = answer {
+                self.answer_tool_confirmation(allowed);
+            }
+        }
+
+        // Waveform / spectrogram window
+        if self.show_audio_monitor {
+            self.show_audio_monitor_window(ctx);
+        }
+
+        // Input device hot-plug (録音中は調べない)
+        self.device_watcher
+            .set_paused(self.is_recording || self.is_preparing);
+        if let Some(devices) = self.device_watcher.poll() {
+            self.on_input_devices_changed(devices);
+        }
+
+        // DSP A/B sample recording
+        if self.dsp_ab.is_some() {
+            self.poll_dsp_ab(ctx);
+        }
+
+        // Microphone calibration wizard
+        if self.calibration.is_some() {
+            self.show_calibration_window(ctx);
+        }
+
         // Settings modal window
         if self.show_settings {
             egui::Window::new("Settings")
@@ -405,6 +835,7 @@ impl eframe::App for WinhApp {
                                     .password(true)
                                     .desired_width(f32::INFINITY),
                             );
+                            ui.small("Stored in the OS credential store, not in config.json");
                             ui.add_space(10.0);
 
                             ui.label("Silence Duration (seconds):");
@@ -422,9 +853,72 @@ impl eframe::App for WinhApp {
                                 )
                                 .logarithmic(true),
                             );
-                            ui.label(format!("Current: {:.4}", self.settings_silence_threshold));
+                            ui.label(format!(
+                                "Current: {:.4} ({:.1} dBFS)",
+                                self.settings_silence_threshold,
+                                level_meter::amplitude_to_dbfs(self.settings_silence_threshold)
+                            ));
+                            if ui
+                                .add_enabled(
+                                    !self.is_recording
+                                        && !self.is_preparing
+                                        && self.calibration.is_none(),
+                                    egui::Button::new("🎚 Calibrate..."),
+                                )
+                                .on_hover_text("Measure room noise and propose a threshold")
+                                .clicked()
+                            {
+                                self.calibration = Some(CalibrationWizard::new());
+                            }
+                            ui.add_space(10.0);
+
+                            ui.checkbox(
+                                &mut self.settings_continuous.enabled,
+                                "Continuous dictation (split at pauses, stop with the hotkey)",
+                            );
+                            if self.settings_continuous.enabled {
+                                let continuous = &mut self.settings_continuous;
+                                ui.horizontal(|ui| {
+                                    ui.label("Max session:");
+                                    ui.add(
+                                        egui::DragValue::new(&mut continuous.max_session_secs)
+                                            .range(0.0..=14400.0)
+                                            .speed(10.0)
+                                            .suffix(" s"),
+                                    );
+                                    ui.label("Idle timeout:");
+                                    ui.add(
+                                        egui::DragValue::new(&mut continuous.idle_timeout_secs)
+                                            .range(0.0..=3600.0)
+                                            .speed(1.0)
+                                            .suffix(" s"),
+                                    );
+                                });
+                                ui.label("0 = no limit. Pauses longer than Silence Duration end a segment.");
+                            }
                             ui.add_space(10.0);
 
+                            ui.label("Input Source:");
+                            egui::ComboBox::from_id_salt("input_source_combo")
+                                .selected_text(self.settings_input_source.label())
+                                .show_ui(ui, |ui| {
+                                    for source in InputSource::ALL {
+                                        ui.selectable_value(
+                                            &mut self.settings_input_source,
+                                            source,
+                                            source.label(),
+                                        );
+                                    }
+                                });
+                            if self.settings_input_source != InputSource::Microphone {
+                                ui.label(if cfg!(windows) {
+                                    "System audio is captured from the default output device."
+                                } else {
+                                    "System audio is captured from the default output's monitor (needs parec)."
+                                });
+                            }
+                            ui.add_space(5.0);
+
                             ui.label("Input Device:");
                             egui::ComboBox::from_id_salt("input_device_combo")
                                 .selected_text(
@@ -443,6 +937,85 @@ impl eframe::App for WinhApp {
                                         );
                                     }
                                 });
+                            ui.label("Fallback devices (one per line, tried in order):");
+                            ui.add(
+                                egui::TextEdit::multiline(
+                                    &mut self.settings_input_device_fallbacks,
+                                )
+                                .desired_rows(2)
+                                .desired_width(f32::INFINITY),
+                            );
+                            ui.checkbox(
+                                &mut self.settings_input_device_fallback_to_default,
+                                "Use the default device if none is connected",
+                            );
+                            ui.horizontal(|ui| {
+                                ui.label("Channels:");
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.settings_input_channels)
+                                        .hint_text("all (average)")
+                                        .desired_width(80.0),
+                                )
+                                .on_hover_text(
+                                    "1-based channel numbers, e.g. \"2\" or \"1, 2\" (averaged)",
+                                );
+                            });
+                            if let Err(e) = audio::parse_channel_list(&self.settings_input_channels)
+                            {
+                                ui.colored_label(egui::Color32::from_rgb(255, 100, 100), e);
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("Sample rate:");
+                                format_option_combo(
+                                    ui,
+                                    "input_sample_rate_combo",
+                                    &mut self.settings_input_sample_rate,
+                                    &INPUT_SAMPLE_RATES,
+                                    " Hz",
+                                );
+                                ui.label("Buffer:");
+                                format_option_combo(
+                                    ui,
+                                    "input_buffer_size_combo",
+                                    &mut self.settings_input_buffer_size,
+                                    &INPUT_BUFFER_SIZES,
+                                    " frames",
+                                );
+                            });
+                            ui.add_space(10.0);
+
+                            ui.label("Audio Processing:");
+                            let dsp = &mut self.settings_dsp;
+                            ui.checkbox(&mut dsp.high_pass_enabled, "High-pass filter");
+                            if dsp.high_pass_enabled {
+                                ui.add(
+                                    egui::Slider::new(&mut dsp.high_pass_hz, 40.0..=300.0)
+                                        .suffix(" Hz"),
+                                );
+                            }
+                            ui.checkbox(&mut dsp.noise_suppression_enabled, "Noise suppression")
+                                .on_hover_text("Spectral gating: lowers steady background noise such as fans and hiss");
+                            if dsp.noise_suppression_enabled {
+                                ui.add(
+                                    egui::Slider::new(&mut dsp.noise_reduction_db, 6.0..=40.0)
+                                        .text("max reduction")
+                                        .suffix(" dB"),
+                                );
+                            }
+                            ui.checkbox(&mut dsp.agc_enabled, "Automatic gain control");
+                            if dsp.agc_enabled {
+                                ui.add(
+                                    egui::Slider::new(&mut dsp.agc_target_dbfs, -40.0..=-6.0)
+                                        .text("target")
+                                        .suffix(" dBFS"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut dsp.agc_max_gain_db, 0.0..=40.0)
+                                        .text("max gain")
+                                        .suffix(" dB"),
+                                );
+                            }
+                            self.show_dsp_ab_controls(ui);
                             ui.add_space(10.0);
 
                             ui.label("Hotkey:");
@@ -451,13 +1024,326 @@ impl eframe::App for WinhApp {
                             ui.text_edit_singleline(&mut self.settings_hotkey);
                             ui.add_space(10.0);
 
-                            ui.label("Eliza Agent URL:");
+                            ui.label("Speech Language (e.g. ja, en):");
+                            ui.text_edit_singleline(&mut self.settings_stt_language);
+                            ui.checkbox(
+                                &mut self.settings_remove_punctuation,
+                                "Remove punctuation",
+                            );
+                            ui.add_space(10.0);
+
+                            ui.label(format!("Profile: {}", self.config.active_profile));
+                            ui.label("Switch hotkey for this profile (empty = none):");
+                            ui.text_edit_singleline(&mut self.settings_profile_switch_hotkey);
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut self.settings_new_profile_name);
+                                if ui.button("New profile").clicked() {
+                                    self.create_profile();
+                                }
+                            });
+                            if ui.button("Delete this profile").clicked() {
+                                self.delete_active_profile();
+                            }
+                            ui.checkbox(
+                                &mut self.settings_auto_profile_switching,
+                                "Switch profile by foreground app (profile_rules)",
+                            );
+                            ui.add_space(10.0);
+
+                            ui.label("VRChat OSC Send Address:");
+                            ui.text_edit_singleline(&mut self.settings_osc_send_addr);
+                            ui.label("VRChat OSC Listen Address:");
+                            ui.text_edit_singleline(&mut self.settings_osc_listen_addr);
+                            ui.checkbox(
+                                &mut self.settings_oscquery_enabled,
+                                "Advertise via OSCQuery (dynamic port)",
+                            );
+                            ui.label("Mute Double-Toggle Window (seconds):");
+                            ui.add(egui::Slider::new(
+                                &mut self.settings_osc_double_toggle,
+                                0.3..=3.0,
+                            ));
+                            ui.label("OSC Trigger Rules (JSON):");
+                            ui.add(
+                                egui::TextEdit::multiline(&mut self.settings_osc_trigger_rules)
+                                    .code_editor()
+                                    .desired_rows(4)
+                                    .desired_width(f32::INFINITY),
+                            );
+                            if let Err(e) = serde_json::from_str::<Vec<osc_trigger::OscTriggerRule>>(
+                                &self.settings_osc_trigger_rules,
+                            ) {
+                                ui.colored_label(
+                                    egui::Color32::RED,
+                                    format!("Invalid rules (not saved): {}", e),
+                                );
+                            }
+                            ui.checkbox(
+                                &mut self.settings_avatar_parameters_enabled,
+                                "Reflect winh state in avatar parameters",
+                            );
+                            ui.add_space(10.0);
+
+                            ui.label("VRChat Chatbox Page Dwell (seconds):");
+                            ui.add(egui::Slider::new(
+                                &mut self.settings_chatbox_page_dwell,
+                                1.5..=10.0,
+                            ));
+                            ui.checkbox(
+                                &mut self.settings_chatbox_page_marker,
+                                "Show page number (1/3) on long messages",
+                            );
+                            ui.add_space(10.0);
+
+                            ui.checkbox(
+                                &mut self.settings_translation_enabled,
+                                "Translate transcripts before sending",
+                            );
+                            ui.horizontal(|ui| {
+                                ui.label("From:");
+                                ui.add(
+                                    egui::TextEdit::singleline(
+                                        &mut self.settings_translation_source_lang,
+                                    )
+                                    .desired_width(40.0),
+                                );
+                                ui.label("To:");
+                                ui.add(
+                                    egui::TextEdit::singleline(
+                                        &mut self.settings_translation_target_lang,
+                                    )
+                                    .desired_width(40.0),
+                                );
+                            });
+                            ui.label("Translation Output:");
+                            egui::ComboBox::from_id_salt("translation_output_combo")
+                                .selected_text(self.settings_translation_output.label())
+                                .show_ui(ui, |ui| {
+                                    for output in [
+                                        translation::TranslationOutput::TranslationOnly,
+                                        translation::TranslationOutput::Both,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut self.settings_translation_output,
+                                            output,
+                                            output.label(),
+                                        );
+                                    }
+                                });
+                            ui.label("Translation Service:");
+                            egui::ComboBox::from_id_salt("translation_backend_combo")
+                                .selected_text(self.settings_translation_backend.label())
+                                .show_ui(ui, |ui| {
+                                    for backend in [
+                                        translation::TranslationBackend::ChatCompletion,
+                                        translation::TranslationBackend::LibreTranslate,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut self.settings_translation_backend,
+                                            backend,
+                                            backend.label(),
+                                        );
+                                    }
+                                });
+                            ui.label("Translation URL:");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.settings_translation_url)
+                                    .desired_width(f32::INFINITY),
+                            );
+                            if self.settings_translation_backend
+                                == translation::TranslationBackend::ChatCompletion
+                            {
+                                ui.label("Translation Model:");
+                                ui.text_edit_singleline(&mut self.settings_translation_model);
+                            }
+                            ui.label("Translation API Key (optional):");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.settings_translation_api_key)
+                                    .password(true)
+                                    .desired_width(f32::INFINITY),
+                            );
+                            ui.add_space(10.0);
+
+                            ui.label("Rewrite Template (LLM cleanup before clipboard/auto-input):");
+                            egui::ComboBox::from_id_salt("rewrite_template_combo")
+                                .selected_text(if self.settings_rewrite_template.is_empty() {
+                                    "(none)"
+                                } else {
+                                    self.settings_rewrite_template.as_str()
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(
+                                        &mut self.settings_rewrite_template,
+                                        String::new(),
+                                        "(none)",
+                                    );
+                                    for template in &self.config.rewrite_templates {
+                                        ui.selectable_value(
+                                            &mut self.settings_rewrite_template,
+                                            template.name.clone(),
+                                            &template.name,
+                                        );
+                                    }
+                                });
+                            ui.label("Rewrite URL (OpenAI-compatible):");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.settings_rewrite_url)
+                                    .desired_width(f32::INFINITY),
+                            );
+                            ui.label("Rewrite Model:");
+                            ui.text_edit_singleline(&mut self.settings_rewrite_model);
+                            ui.label("Rewrite API Key (optional):");
                             ui.add(
-                                egui::TextEdit::singleline(&mut self.settings_eliza_url)
+                                egui::TextEdit::singleline(&mut self.settings_rewrite_api_key)
+                                    .password(true)
                                     .desired_width(f32::INFINITY),
                             );
+                            ui.checkbox(
+                                &mut self.settings_rewrite_preview,
+                                "Preview rewrite before output",
+                            );
+                            ui.add_space(10.0);
+
+                            ui.label("Assistant Backend:");
+                            egui::ComboBox::from_id_salt("assistant_backend_combo")
+                                .selected_text(self.settings_assistant_backend.label())
+                                .show_ui(ui, |ui| {
+                                    for backend in [
+                                        assistant::AssistantBackend::Eliza,
+                                        assistant::AssistantBackend::ChatCompletion,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut self.settings_assistant_backend,
+                                            backend,
+                                            backend.label(),
+                                        );
+                                    }
+                                });
+                            ui.checkbox(
+                                &mut self.settings_assistant_streaming,
+                                "Stream replies (show tokens as they arrive)",
+                            );
+                            match self.settings_assistant_backend {
+                                assistant::AssistantBackend::Eliza => {
+                                    ui.label("Eliza Agent URL:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut self.settings_eliza_url)
+                                            .desired_width(f32::INFINITY),
+                                    );
+                                }
+                                assistant::AssistantBackend::ChatCompletion => {
+                                    ui.label("Chat Completion Base URL (e.g. http://localhost:11434/v1):");
+                                    ui.add(
+                                        egui::TextEdit::singleline(
+                                            &mut self.settings_chat_completion_base_url,
+                                        )
+                                        .desired_width(f32::INFINITY),
+                                    );
+                                    ui.label("Chat Completion API Key (optional):");
+                                    ui.add(
+                                        egui::TextEdit::singleline(
+                                            &mut self.settings_chat_completion_api_key,
+                                        )
+                                        .password(true)
+                                        .desired_width(f32::INFINITY),
+                                    );
+                                    ui.label("Model:");
+                                    ui.text_edit_singleline(&mut self.settings_chat_completion_model);
+                                    ui.label("System Prompt:");
+                                    ui.add(
+                                        egui::TextEdit::multiline(
+                                            &mut self.settings_chat_completion_system_prompt,
+                                        )
+                                        .desired_rows(3)
+                                        .desired_width(f32::INFINITY),
+                                    );
+                                    ui.label("Temperature:");
+                                    ui.add(egui::Slider::new(
+                                        &mut self.settings_chat_completion_temperature,
+                                        0.0..=2.0,
+                                    ));
+                                    ui.checkbox(
+                                        &mut self.settings_assistant_tools_enabled,
+                                        "Allow tool calls (assistant_tools in config.json)",
+                                    );
+                                }
+                            }
                             ui.label("Eliza Gesture (GestureRight value to trigger Eliza mode):");
                             ui.add(egui::Slider::new(&mut self.settings_eliza_gesture, 0..=7));
+                            ui.label("Eliza Conversation Max Turns:");
+                            ui.add(egui::Slider::new(
+                                &mut self.settings_eliza_max_turns,
+                                1..=50,
+                            ));
+                            ui.label("Eliza Conversation Token Budget:");
+                            ui.add(
+                                egui::Slider::new(&mut self.settings_eliza_max_tokens, 100..=32000)
+                                    .logarithmic(true),
+                            );
+                            ui.checkbox(
+                                &mut self.settings_eliza_persist_conversation,
+                                "Keep conversation across restarts",
+                            );
+                            ui.label("Reset Conversation Hotkey (empty to disable):");
+                            ui.text_edit_singleline(&mut self.settings_eliza_reset_hotkey);
+                            ui.add_space(10.0);
+
+                            ui.checkbox(&mut self.settings_tts_enabled, "Speak assistant replies (TTS)");
+                            ui.label("TTS Output Device:");
+                            egui::ComboBox::from_id_salt("tts_output_device_combo")
+                                .selected_text(
+                                    self.settings_tts_output_device
+                                        .as_deref()
+                                        .unwrap_or(audio::DEFAULT_DEVICE_LABEL),
+                                )
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(
+                                        &mut self.settings_tts_output_device,
+                                        None,
+                                        audio::DEFAULT_DEVICE_LABEL,
+                                    );
+                                    for device_name in &self.available_output_devices {
+                                        ui.selectable_value(
+                                            &mut self.settings_tts_output_device,
+                                            Some(device_name.clone()),
+                                            device_name,
+                                        );
+                                    }
+                                });
+                            ui.label("TTS Engine:");
+                            egui::ComboBox::from_id_salt("tts_engine_combo")
+                                .selected_text(self.settings_tts_engine.label())
+                                .show_ui(ui, |ui| {
+                                    let voicevox = tts::default_engine();
+                                    let command = tts::TtsEngine::Command {
+                                        program: "piper".to_string(),
+                                        args: Vec::new(),
+                                    };
+                                    for engine in [voicevox, command] {
+                                        let selected = std::mem::discriminant(&engine)
+                                            == std::mem::discriminant(&self.settings_tts_engine);
+                                        let label = engine.label();
+                                        if ui.selectable_label(selected, label).clicked()
+                                            && !selected
+                                        {
+                                            self.settings_tts_engine = engine;
+                                        }
+                                    }
+                                });
+                            match &mut self.settings_tts_engine {
+                                tts::TtsEngine::Voicevox { url, speaker } => {
+                                    ui.label("VOICEVOX URL:");
+                                    ui.text_edit_singleline(url);
+                                    ui.label("Speaker ID:");
+                                    ui.add(egui::DragValue::new(speaker));
+                                }
+                                tts::TtsEngine::Command { program, .. } => {
+                                    ui.label("Command:");
+                                    ui.text_edit_singleline(program);
+                                    ui.label("Arguments ({output} = WAV file, otherwise stdout):");
+                                    ui.text_edit_singleline(&mut self.settings_tts_command_args);
+                                }
+                            }
                         });
 
                     ui.add_space(10.0);
@@ -466,50 +1352,140 @@ impl eframe::App for WinhApp {
                             self.config.xai_api_key = self.settings_xai_api_key.trim().to_string();
                             self.config.silence_duration_secs = self.settings_silence_duration;
                             self.config.silence_threshold = self.settings_silence_threshold;
+                            self.config.dsp = self.settings_dsp.clone();
+                            self.config.continuous = self.settings_continuous.clone();
+                            self.config.input_device_fallbacks = self
+                                .settings_input_device_fallbacks
+                                .lines()
+                                .map(|line| line.trim().to_string())
+                                .filter(|line| !line.is_empty())
+                                .collect();
+                            self.config.input_device_fallback_to_default =
+                                self.settings_input_device_fallback_to_default;
+                            self.config.input_source = self.settings_input_source;
+                            // 不正なチャンネル指定は赤字で出ているので、前の形式のままにする
+                            if let Ok(input_format) = self.settings_input_format() {
+                                self.config.input_format = input_format;
+                            }
                             self.config.input_device_name = self
                                 .available_devices
                                 .get(self.selected_device_index)
                                 .cloned();
-                            self.config.eliza_url = self.settings_eliza_url.trim().to_string();
-                            self.config.eliza_gesture = self.settings_eliza_gesture;
-
+                            // Handle OSC address changes
+                            let osc_send_addr = self.settings_osc_send_addr.trim().to_string();
+                            if osc_send_addr != self.config.osc_send_addr {
+                                self.config.osc_send_addr = osc_send_addr;
+                                self.vrchat_client = vrchat::VRChatClient::with_target(
+                                    self.config.osc_send_addr.clone(),
+                                );
+                            }
+                            self.config.avatar_parameters_enabled =
+                                self.settings_avatar_parameters_enabled;
+                            self.send_avatar_state();
+                            let osc_listen_addr = self.settings_osc_listen_addr.trim().to_string();
+                            // 書式が正しくないルールは保存せず、今のルールのままにする
+                            let osc_trigger_rules = serde_json::from_str(
+                                &self.settings_osc_trigger_rules,
+                            )
+                            .unwrap_or_else(|_| self.config.osc_trigger_rules.clone());
+                            if osc_listen_addr != self.config.osc_listen_addr
+                                || self.settings_oscquery_enabled != self.config.oscquery_enabled
+                                || self.settings_osc_double_toggle
+                                    != self.config.osc_double_toggle_secs
+                                || osc_trigger_rules != self.config.osc_trigger_rules
+                            {
+                                self.config.osc_listen_addr = osc_listen_addr;
+                                self.config.oscquery_enabled = self.settings_oscquery_enabled;
+                                self.config.osc_double_toggle_secs =
+                                    self.settings_osc_double_toggle;
+                                self.config.osc_trigger_rules = osc_trigger_rules;
+                                self.restart_osc_listener();
+                            }
+                            self.config.chatbox_page_dwell_secs = self.settings_chatbox_page_dwell;
+                            self.config.chatbox_page_marker = self.settings_chatbox_page_marker;
+                            self.vrchat_client.page_dwell = self.config.chatbox_page_dwell();
+                            self.vrchat_client.page_marker = self.config.chatbox_page_marker;
+                            self.config.eliza_url = self.settings_eliza_url.trim().to_string();
+                            self.config.assistant_backend = self.settings_assistant_backend;
+                            self.config.rewrite_template = self.settings_rewrite_template.trim().to_string();
+                            self.config.rewrite_url = self.settings_rewrite_url.trim().to_string();
+                            self.config.rewrite_model = self.settings_rewrite_model.trim().to_string();
+                            self.config.rewrite_api_key = self.settings_rewrite_api_key.trim().to_string();
+                            self.config.rewrite_preview = self.settings_rewrite_preview;
+                            self.config.translation_enabled = self.settings_translation_enabled;
+                            self.config.translation_backend = self.settings_translation_backend;
+                            self.config.translation_url = self.settings_translation_url.trim().to_string();
+                            self.config.translation_model = self.settings_translation_model.trim().to_string();
+                            self.config.translation_api_key = self.settings_translation_api_key.trim().to_string();
+                            self.config.translation_source_lang = self.settings_translation_source_lang.trim().to_string();
+                            self.config.translation_target_lang = self.settings_translation_target_lang.trim().to_string();
+                            self.config.translation_output = self.settings_translation_output;
+                            self.config.tts_enabled = self.settings_tts_enabled;
+                            if let tts::TtsEngine::Command { args, .. } =
+                                &mut self.settings_tts_engine
+                            {
+                                *args = tts::split_args(&self.settings_tts_command_args);
+                            }
+                            self.config.tts_engine = self.settings_tts_engine.clone();
+                            self.config.tts_output_device = self.settings_tts_output_device.clone();
+                            self.tts_player.configure(
+                                self.config.tts_engine.clone(),
+                                self.config.tts_output_device.clone(),
+                            );
+                            self.config.assistant_streaming = self.settings_assistant_streaming;
+                            self.config.assistant_tools_enabled =
+                                self.settings_assistant_tools_enabled;
+                            self.config.chat_completion_base_url =
+                                self.settings_chat_completion_base_url.trim().to_string();
+                            self.config.chat_completion_api_key =
+                                self.settings_chat_completion_api_key.trim().to_string();
+                            self.config.chat_completion_model =
+                                self.settings_chat_completion_model.trim().to_string();
+                            self.config.chat_completion_system_prompt =
+                                self.settings_chat_completion_system_prompt.clone();
+                            self.config.chat_completion_temperature =
+                                self.settings_chat_completion_temperature;
+                            self.config.eliza_gesture = self.settings_eliza_gesture;
+                            self.config.eliza_max_turns = self.settings_eliza_max_turns;
+                            self.config.eliza_max_tokens = self.settings_eliza_max_tokens;
+                            self.config.eliza_persist_conversation =
+                                self.settings_eliza_persist_conversation;
+                            self.conversation.max_turns = self.config.eliza_max_turns;
+                            self.conversation.max_tokens = self.config.eliza_max_tokens;
+                            self.save_conversation();
+
+                            let reset_hotkey = self.settings_eliza_reset_hotkey.trim().to_string();
+                            if reset_hotkey != self.config.eliza_reset_hotkey {
+                                self.config.eliza_reset_hotkey = reset_hotkey;
+                                if let Some(old) = self.reset_conversation_hotkey.take() {
+                                    if let Err(e) = self.hotkey_manager.unregister(old) {
+                                        eprintln!("Failed to unregister old hotkey: {}", e);
+                                    }
+                                }
+                                self.reset_conversation_hotkey = register_optional_hotkey(
+                                    &self.hotkey_manager,
+                                    &self.config.eliza_reset_hotkey,
+                                    "Reset conversation",
+                                );
+                            }
+
                             // Handle hotkey change
                             let new_hotkey_str = self.settings_hotkey.trim().to_string();
-                            if new_hotkey_str != self.config.hotkey {
-                                self.config.hotkey = new_hotkey_str;
-
-                                // Try to parse and register new hotkey
-                                match self.config.parse_hotkey() {
-                                    Ok(new_hotkey) => {
-                                        // Unregister old hotkey
-                                        if let Err(e) =
-                                            self.hotkey_manager.unregister(self.current_hotkey)
-                                        {
-                                            eprintln!("Failed to unregister old hotkey: {}", e);
-                                        }
-
-                                        // Register new hotkey
-                                        match self.hotkey_manager.register(new_hotkey) {
-                                            Ok(_) => {
-                                                self.current_hotkey = new_hotkey;
-                                                println!(
-                                                    "Hotkey changed to: {}",
-                                                    self.config.hotkey
-                                                );
-                                            }
-                                            Err(e) => {
-                                                self.status_message =
-                                                    format!("Failed to register new hotkey: {}", e);
-                                                eprintln!("Failed to register new hotkey: {}", e);
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        self.status_message =
-                                            format!("Invalid hotkey format: {}", e);
-                                        eprintln!("Failed to parse hotkey: {}", e);
-                                    }
+                            self.apply_record_hotkey(new_hotkey_str);
+                            self.config.stt_language =
+                                self.settings_stt_language.trim().to_string();
+                            self.config.remove_punctuation = self.settings_remove_punctuation;
+                            self.config.auto_profile_switching =
+                                self.settings_auto_profile_switching;
+                            let switch_hotkey = self.settings_profile_switch_hotkey.trim().to_string();
+                            if switch_hotkey != self.active_profile_switch_hotkey() {
+                                let active = self.config.active_profile.clone();
+                                if let Some(profile) =
+                                    self.config.profiles.iter_mut().find(|p| p.name == active)
+                                {
+                                    profile.switch_hotkey = switch_hotkey;
                                 }
+                                self.register_profile_hotkeys();
                             }
 
                             match self.config.save() {
@@ -528,21 +1504,7 @@ impl eframe::App for WinhApp {
 
                         if ui.button("Cancel").clicked() {
                             // Revert to current config
-                            self.settings_xai_api_key = self.config.xai_api_key.clone();
-                            self.settings_silence_duration = self.config.silence_duration_secs;
-                            self.settings_silence_threshold = self.config.silence_threshold;
-                            self.settings_input_device = self.config.input_device_name.clone();
-                            self.settings_hotkey = self.config.hotkey.clone();
-                            // Restore device index
-                            self.selected_device_index =
-                                if let Some(ref device_name) = self.config.input_device_name {
-                                    self.available_devices
-                                        .iter()
-                                        .position(|d| d == device_name)
-                                        .unwrap_or(0)
-                                } else {
-                                    0
-                                };
+                            self.load_settings_from_config();
                             self.show_settings = false;
                         }
                     });
@@ -563,15 +1525,67 @@ impl eframe::App for WinhApp {
                         if ui.button("⚙ Settings").clicked() {
                             self.show_settings = true;
                         }
+                        if ui
+                            .selectable_label(self.show_audio_monitor, "📈")
+                            .on_hover_text("Waveform and spectrogram of the recording")
+                            .clicked()
+                        {
+                            self.show_audio_monitor = !self.show_audio_monitor;
+                        }
+                        let mut selected = self.config.active_profile.clone();
+                        egui::ComboBox::from_id_salt("profile_combo")
+                            .selected_text(&selected)
+                            .show_ui(ui, |ui| {
+                                for name in self.config.profile_names() {
+                                    ui.selectable_value(&mut selected, name.clone(), name);
+                                }
+                            });
+                        ui.label("Profile:");
+                        if selected != self.config.active_profile {
+                            self.switch_profile(&selected);
+                        }
                     });
                 });
                 ui.add_space(ui_space);
 
+                // Input device status (fallback when the configured one is unplugged)
+                let (device_status, device_warning) = self.input_device_status();
+                if device_warning {
+                    ui.colored_label(egui::Color32::from_rgb(255, 160, 0), device_status);
+                } else {
+                    ui.weak(device_status);
+                }
+
                 // Error display area
                 if let Some(error) = &self.last_error {
                     ui.colored_label(egui::Color32::RED, format!("❌ Error: {}", error));
                 }
 
+                // Config file warning (parse failure, newer version)
+                if let Some(warning) = self.config_warning.clone() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(255, 160, 0),
+                            format!("⚠ Config: {}", warning),
+                        );
+                        if ui.small_button("✕").clicked() {
+                            self.config_warning = None;
+                        }
+                    });
+                }
+
+                if let Some(warning) = &self.reload_warning {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 160, 0),
+                        format!("⚠ Config: {}", warning),
+                    );
+                }
+
+                // OSC listener error (e.g. port already in use)
+                if let Some(error) = &self.osc_error {
+                    ui.colored_label(egui::Color32::RED, format!("⚠ VRChat OSC: {}", error));
+                }
+
                 // Warning if API key is not set
                 if self.config.xai_api_key.is_empty() {
                     ui.colored_label(
@@ -694,61 +1708,10 @@ impl eframe::App for WinhApp {
 
                 ui.add_space(ui_space);
 
-                // Volume indicator bar
+                // Input level meter
                 if self.is_recording {
                     if let Some(recorder) = &self.audio_recorder {
-                        let max_amplitude = recorder.get_max_amplitude();
-                        let bar_width = ui_width;
-                        let bar_height = 10.0;
-
-                        // Clip to 1.2
-                        let clipped_amplitude = max_amplitude.min(1.2);
-                        let bar_fill_width = (clipped_amplitude / 1.2) * bar_width;
-
-                        // Allocate space for the bar
-                        let (bar_rect, _) = ui.allocate_exact_size(
-                            egui::vec2(bar_width, bar_height),
-                            egui::Sense::hover(),
-                        );
-
-                        // Draw background (dark gray)
-                        ui.painter().rect_filled(
-                            bar_rect,
-                            2.0,
-                            egui::Color32::from_rgb(50, 50, 50),
-                        );
-
-                        // Draw filled portion with color coding
-                        if bar_fill_width > 0.0 {
-                            let fill_rect = egui::Rect::from_min_size(
-                                bar_rect.min,
-                                egui::vec2(bar_fill_width, bar_height),
-                            );
-
-                            // Color coding based on amplitude
-                            let color = if max_amplitude < self.config.silence_threshold {
-                                // Gray: below threshold
-                                egui::Color32::from_rgb(150, 150, 150)
-                            } else if max_amplitude < 1.0 {
-                                // Green: normal range
-                                egui::Color32::from_rgb(0, 200, 0)
-                            } else {
-                                // Red: clipping range
-                                egui::Color32::from_rgb(255, 0, 0)
-                            };
-
-                            ui.painter().rect_filled(fill_rect, 2.0, color);
-                        }
-
-                        // Draw border
-                        ui.painter().rect_stroke(
-                            bar_rect,
-                            2.0,
-                            egui::Stroke::new(1.0, egui::Color32::from_rgb(100, 100, 100)),
-                        );
-
-                        // Display amplitude value
-                        ui.label(format!("Level: {:.3}", max_amplitude));
+                        draw_level_meter(ui, recorder.level(), self.config.silence_threshold);
                     }
                 } else {
                     ui.add_space(34.0);
@@ -787,6 +1750,44 @@ impl eframe::App for WinhApp {
                     }
                 }
 
+                // Rewrite preview / undo
+                if let Some(preview) = &self.rewrite_preview {
+                    if self.rewrite_receiver.is_some() {
+                        ui.label(format!("✏ Rewriting with '{}'...", preview.template));
+                    } else {
+                        let mut choice = None;
+                        ui.horizontal(|ui| {
+                            ui.label(format!("✏ {}:", preview.template));
+                            if ui.small_button("✔ Use rewrite").clicked() {
+                                choice = Some(true);
+                            }
+                            if ui.small_button("↩ Use original").clicked() {
+                                choice = Some(false);
+                            }
+                        });
+                        if let Some(use_rewrite) = choice {
+                            if let Some(preview) = self.rewrite_preview.take() {
+                                self.apply_rewrite(preview, use_rewrite);
+                            }
+                        }
+                    }
+                } else if self.last_rewrite.is_some() && ui.small_button("↩ Undo rewrite").clicked()
+                {
+                    self.undo_rewrite();
+                }
+
+                // Assistant reply (streamed)
+                if !self.assistant_reply.is_empty() {
+                    egui::ScrollArea::vertical()
+                        .id_salt("assistant_reply")
+                        .max_height(40.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            ui.set_width(ui_width);
+                            ui.label(format!("{}{}", ELIZA_PREFIX, self.assistant_reply));
+                        });
+                }
+
                 ui.add_space(ui_space);
 
                 // Output Options
@@ -831,15 +1832,38 @@ impl eframe::App for WinhApp {
                                 .checkbox(&mut self.config.eliza_enabled, "Send to Eliza")
                                 .changed();
 
-                            // call QvPen button
-                            if ui
-                                .add(egui::Button::new("📝 call QvPen").small())
-                                .clicked()
-                            {
-                                if let Err(e) = auto_input::call_qvpen() {
-                                    eprintln!("call_qvpen error: {}", e);
+                            ui.horizontal(|ui| {
+                                // call QvPen button
+                                if ui.add(egui::Button::new("📝 call QvPen").small()).clicked() {
+                                    if let Err(e) = auto_input::call_qvpen() {
+                                        eprintln!("call_qvpen error: {}", e);
+                                    }
                                 }
-                            }
+
+                                // Reset Eliza conversation button
+                                if self.config.eliza_enabled
+                                    && ui
+                                        .add(
+                                            egui::Button::new(format!(
+                                                "🗑 Reset conversation ({})",
+                                                self.conversation.turn_count()
+                                            ))
+                                            .small(),
+                                        )
+                                        .clicked()
+                                {
+                                    self.reset_conversation();
+                                }
+
+                                // Interrupt TTS playback
+                                if self.tts_player.is_speaking()
+                                    && ui
+                                        .add(egui::Button::new("⏹ Stop speaking").small())
+                                        .clicked()
+                                {
+                                    self.tts_player.stop();
+                                }
+                            });
 
                             // Save config if any checkbox changed
                             if clipboard_changed
@@ -863,6 +1887,19 @@ impl eframe::App for WinhApp {
         // Update recording info during recording and check for silence
         if self.is_recording {
             if let Some(recorder) = &self.audio_recorder {
+                // デバイスが外れたなどで止まったら、そこまでの音声で文字起こしする
+                if let Some(error) = recorder.stream_error() {
+                    eprintln!("Recording interrupted: {}", error);
+                    self.is_recording = false;
+                    self.on_stop_recording();
+                    self.status_message = if self.is_transcribing {
+                        format!("{} - transcribing what was captured", error)
+                    } else {
+                        format!("Recording stopped: {}", error)
+                    };
+                    ctx.request_repaint();
+                    return;
+                }
                 let duration_secs = recorder.get_recording_duration();
                 let silence_elapsed = recorder.get_silence_duration().as_secs_f32();
 
@@ -871,12 +1908,34 @@ impl eframe::App for WinhApp {
                     duration_secs, silence_elapsed, self.config.silence_duration_secs
                 );
 
-                // Auto-stop if silence duration exceeded
-                if recorder.is_silent(self.config.silence_duration_secs) {
+                if let Some(session) = &mut self.continuous_session {
+                    match session.check(duration_secs, silence_elapsed) {
+                        dictation::DictationAction::Continue => {}
+                        dictation::DictationAction::EndSegment => {
+                            if let Some(sender) = &self.segment_sender {
+                                let _ = sender.send(());
+                            }
+                        }
+                        dictation::DictationAction::Stop(reason) => {
+                            println!("Continuous dictation stopped: {}", reason);
+                            self.is_recording = false;
+                            self.on_stop_recording();
+                        }
+                    }
+                    if let Some(session) = &self.continuous_session {
+                        self.recording_info = format!(
+                            "Dictating: {:.1}s | {} segment(s) | press {} to stop",
+                            duration_secs,
+                            session.segments(),
+                            self.config.hotkey
+                        );
+                    }
+                } else if recorder.is_silent(self.config.silence_duration_secs) {
                     println!(
                         "Silence detected for {:.1}s - auto-stopping",
                         self.config.silence_duration_secs
                     );
+                    self.audio_monitor.lock().unwrap().mark_silence_timeout();
                     self.is_recording = false;
                     self.on_stop_recording();
                 }
@@ -896,110 +1955,1964 @@ impl eframe::App for WinhApp {
 }
 
 impl WinhApp {
-    fn on_prepare_recording(&mut self) {
-        println!("Preparing to record...");
-        self.is_preparing = true;
-        self.prepare_start_time = Some(std::time::Instant::now());
-        self.status_message = "Preparing to record...".to_string();
+    /// 録音開始のホットキーを変更する (登録し直す)
+    fn apply_record_hotkey(&mut self, new_hotkey_str: String) {
+        if new_hotkey_str == self.config.hotkey {
+            return;
+        }
+        self.config.hotkey = new_hotkey_str;
+        self.register_record_hotkey();
     }
 
-    fn on_actually_start_recording(&mut self) {
-        println!("Recording started");
-        self.status_message = "Starting recording...".to_string();
-        self.recording_info.clear();
-
-        match AudioRecorder::new(self.config.silence_threshold) {
-            Ok(mut recorder) => {
-                // Use configured device if set, otherwise use default
-                // If "Windows既定" is selected, use None to get default device
-                let device_name = self
-                    .config
-                    .input_device_name
-                    .as_ref()
-                    .filter(|name| name.as_str() != "Windows既定")
-                    .map(|s| s.as_str());
-
-                let (chunk_tx, chunk_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+    /// 設定の録音ホットキーが登録中のものと違えば登録し直す
+    /// (録音中にプロファイルを自動で切り替えた場合は、録音が終わってから呼ぶ)
+    fn register_record_hotkey(&mut self) {
+        // Try to parse and register new hotkey
+        match self.config.parse_hotkey() {
+            Ok(new_hotkey) if new_hotkey.id() == self.current_hotkey.id() => {}
+            Ok(new_hotkey) => {
+                // Unregister old hotkey
+                if let Err(e) = self.hotkey_manager.unregister(self.current_hotkey) {
+                    eprintln!("Failed to unregister old hotkey: {}", e);
+                }
 
-                match recorder.start_recording_with_device(device_name, Some(chunk_tx)) {
+                // Register new hotkey
+                match self.hotkey_manager.register(new_hotkey) {
                     Ok(_) => {
-                        let sample_rate = recorder.get_sample_rate();
-                        self.audio_recorder = Some(recorder);
-
-                        if self.config.xai_api_key.is_empty() {
-                            self.status_message =
-                                "Recording... (Set xAI API key to enable transcription)"
-                                    .to_string();
-                        } else {
-                            self.status_message = "Recording... Speak now!".to_string();
-                            self.start_streaming_transcription(sample_rate, chunk_rx);
-                        }
+                        self.current_hotkey = new_hotkey;
+                        println!("Hotkey changed to: {}", self.config.hotkey);
                     }
                     Err(e) => {
-                        self.status_message = format!("Error: {}", e);
-                        self.is_recording = false;
-                        eprintln!("Failed to start recording: {}", e);
+                        self.status_message = format!("Failed to register new hotkey: {}", e);
+                        eprintln!("Failed to register new hotkey: {}", e);
                     }
                 }
             }
             Err(e) => {
-                self.status_message = format!("Error: {}", e);
-                self.is_recording = false;
-                eprintln!("Failed to create audio recorder: {}", e);
+                self.status_message = format!("Invalid hotkey format: {}", e);
+                eprintln!("Failed to parse hotkey: {}", e);
             }
         }
     }
 
-    fn on_stop_recording(&mut self) {
-        println!("Recording stopped");
+    /// 設定画面で編集中の入力形式 (チャンネルが不正ならエラー)
+    fn settings_input_format(&self) -> Result<audio::InputFormat, String> {
+        Ok(audio::InputFormat {
+            channels: audio::parse_channel_list(&self.settings_input_channels)?,
+            sample_rate: self.settings_input_sample_rate,
+            buffer_size: self.settings_input_buffer_size,
+        })
+    }
 
-        if let Some(mut recorder) = self.audio_recorder.take() {
-            recorder.stop_recording();
-            // stream drop → コールバッククロージャdrop → chunk_senderがdrop
-            // → UnboundedReceiver側がdisconnectを検知 → WebSocketタスクがaudio.doneを送信
+    /// 設定画面の入力欄を現在の設定で埋め直す
+    fn load_settings_from_config(&mut self) {
+        self.settings_xai_api_key = self.config.xai_api_key.clone();
+        self.settings_silence_duration = self.config.silence_duration_secs;
+        self.settings_silence_threshold = self.config.silence_threshold;
+        self.settings_dsp = self.config.dsp.clone();
+        self.settings_continuous = self.config.continuous.clone();
+        self.settings_input_device_fallbacks = self.config.input_device_fallbacks.join("\n");
+        self.settings_input_device_fallback_to_default =
+            self.config.input_device_fallback_to_default;
+        self.settings_input_source = self.config.input_source;
+        self.settings_input_channels = channel_list_text(&self.config.input_format.channels);
+        self.settings_input_sample_rate = self.config.input_format.sample_rate;
+        self.settings_input_buffer_size = self.config.input_format.buffer_size;
+        self.settings_input_device = self.config.input_device_name.clone();
+        self.settings_hotkey = self.config.hotkey.clone();
+        self.settings_stt_language = self.config.stt_language.clone();
+        self.settings_remove_punctuation = self.config.remove_punctuation;
+        self.settings_profile_switch_hotkey = self.active_profile_switch_hotkey();
+        self.settings_auto_profile_switching = self.config.auto_profile_switching;
+        self.settings_eliza_url = self.config.eliza_url.clone();
+        self.settings_eliza_gesture = self.config.eliza_gesture;
+        self.settings_eliza_max_turns = self.config.eliza_max_turns;
+        self.settings_eliza_max_tokens = self.config.eliza_max_tokens;
+        self.settings_eliza_persist_conversation = self.config.eliza_persist_conversation;
+        self.settings_eliza_reset_hotkey = self.config.eliza_reset_hotkey.clone();
+        self.settings_assistant_backend = self.config.assistant_backend;
+        self.settings_rewrite_template = self.config.rewrite_template.clone();
+        self.settings_rewrite_url = self.config.rewrite_url.clone();
+        self.settings_rewrite_model = self.config.rewrite_model.clone();
+        self.settings_rewrite_api_key = self.config.rewrite_api_key.clone();
+        self.settings_rewrite_preview = self.config.rewrite_preview;
+        self.settings_translation_enabled = self.config.translation_enabled;
+        self.settings_translation_backend = self.config.translation_backend;
+        self.settings_translation_url = self.config.translation_url.clone();
+        self.settings_translation_model = self.config.translation_model.clone();
+        self.settings_translation_api_key = self.config.translation_api_key.clone();
+        self.settings_translation_source_lang = self.config.translation_source_lang.clone();
+        self.settings_translation_target_lang = self.config.translation_target_lang.clone();
+        self.settings_translation_output = self.config.translation_output;
+        self.settings_tts_enabled = self.config.tts_enabled;
+        self.settings_tts_engine = self.config.tts_engine.clone();
+        self.settings_tts_command_args = tts_command_args(&self.config.tts_engine);
+        self.settings_tts_output_device = self.config.tts_output_device.clone();
+        self.settings_assistant_streaming = self.config.assistant_streaming;
+        self.settings_assistant_tools_enabled = self.config.assistant_tools_enabled;
+        self.settings_chat_completion_base_url = self.config.chat_completion_base_url.clone();
+        self.settings_chat_completion_api_key = self.config.chat_completion_api_key.clone();
+        self.settings_chat_completion_model = self.config.chat_completion_model.clone();
+        self.settings_chat_completion_system_prompt =
+            self.config.chat_completion_system_prompt.clone();
+        self.settings_chat_completion_temperature = self.config.chat_completion_temperature;
+        self.settings_osc_send_addr = self.config.osc_send_addr.clone();
+        self.settings_osc_listen_addr = self.config.osc_listen_addr.clone();
+        self.settings_oscquery_enabled = self.config.oscquery_enabled;
+        self.settings_osc_double_toggle = self.config.osc_double_toggle_secs;
+        self.settings_osc_trigger_rules = osc_rules_text(&self.config.osc_trigger_rules);
+        self.settings_avatar_parameters_enabled = self.config.avatar_parameters_enabled;
+        self.settings_chatbox_page_dwell = self.config.chatbox_page_dwell_secs;
+        self.settings_chatbox_page_marker = self.config.chatbox_page_marker;
+        // Restore device index
+        self.available_devices = device_options(
+            &self.connected_devices,
+            &[self.config.input_device_name.as_deref()],
+        );
+        self.selected_device_index = if let Some(ref device_name) = self.config.input_device_name {
+            self.available_devices
+                .iter()
+                .position(|d| d == device_name)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+    }
+
+    /// 設定の入力デバイス (None = 既定のデバイス)
+    fn configured_input_device(&self) -> Option<&str> {
+        self.config
+            .input_device_name
+            .as_deref()
+            .filter(|name| *name != audio::DEFAULT_DEVICE_LABEL)
+    }
+
+    /// 入力デバイスがつながった・外れたら一覧を作り直す (選択中のデバイスはそのまま)
+    fn on_input_devices_changed(&mut self, devices: Vec<String>) {
+        let (added, removed) = device_watcher::diff(&self.connected_devices, &devices);
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+        for name in &added {
+            println!("Input device connected: {}", name);
+        }
+        for name in &removed {
+            println!("Input device disconnected: {}", name);
+        }
+        if !self.is_recording && !self.is_transcribing {
+            self.status_message = match added.first() {
+                Some(name) => format!("Input device connected: {}", name),
+                None => format!("Input device disconnected: {}", removed.join(", ")),
+            };
         }
 
-        self.recording_info.clear();
+        let selected = self
+            .available_devices
+            .get(self.selected_device_index)
+            .cloned();
+        self.connected_devices = devices;
+        self.available_devices = device_options(
+            &self.connected_devices,
+            &[
+                self.config.input_device_name.as_deref(),
+                selected.as_deref(),
+            ],
+        );
+        self.selected_device_index = selected
+            .and_then(|name| self.available_devices.iter().position(|d| *d == name))
+            .unwrap_or(0);
+    }
 
-        if self.is_transcribing {
-            self.status_message = "Transcribing...".to_string();
-        } else {
-            self.status_message =
-                "Recording stopped. Set xAI API key in Settings to enable transcription."
-                    .to_string();
+    /// 次の録音で使う入力デバイスの表示 (外れていて予備を使うときは警告)
+    fn input_device_status(&self) -> (String, bool) {
+        let source = self.config.input_source;
+        if !source.uses_microphone() {
+            return ("🔊 System audio".to_string(), false);
+        }
+        let (status, warning) = match audio::choose_input_device(
+            self.configured_input_device(),
+            &self.config.input_device_fallbacks,
+            &self.connected_devices,
+            self.config.input_device_fallback_to_default,
+        ) {
+            Ok(audio::DeviceChoice {
+                name,
+                fallback_from: None,
+            }) => (
+                format!("🎤 {}", name.as_deref().unwrap_or("Default device")),
+                false,
+            ),
+            Ok(audio::DeviceChoice {
+                name,
+                fallback_from: Some(missing),
+            }) => (
+                format!(
+                    "⚠ '{}' is not connected, using {}",
+                    missing,
+                    name.as_deref().unwrap_or("the default device")
+                ),
+                true,
+            ),
+            Err(_) => (
+                format!(
+                    "⚠ '{}' is not connected",
+                    self.configured_input_device().unwrap_or_default()
+                ),
+                true,
+            ),
+        };
+        match source {
+            InputSource::Separate => (format!("{} (me) + 🔊 system audio (them)", status), warning),
+            InputSource::Mixed => (format!("{} + 🔊 system audio", status), warning),
+            _ => (status, warning),
         }
     }
 
-    fn start_streaming_transcription(
-        &mut self,
-        sample_rate: u32,
-        chunk_rx: tokio::sync::mpsc::UnboundedReceiver<Vec<f32>>,
-    ) {
-        let (msg_tx, msg_rx) = tokio::sync::mpsc::unbounded_channel::<TranscriptionMessage>();
-        self.transcription_receiver = Some(msg_rx);
-        self.is_transcribing = true;
-        let _ = msg_tx.send(TranscriptionMessage::InProgress);
+    fn active_profile_switch_hotkey(&self) -> String {
+        self.config
+            .profiles
+            .iter()
+            .find(|p| p.name == self.config.active_profile)
+            .map(|p| p.switch_hotkey.clone())
+            .unwrap_or_default()
+    }
 
-        let api_key = self.config.xai_api_key.clone();
-        let tx = msg_tx.clone();
+    /// プロファイル切り替えのホットキーを登録し直す
+    fn register_profile_hotkeys(&mut self) {
+        for (hotkey, _) in self.profile_hotkeys.drain(..) {
+            if let Err(e) = self.hotkey_manager.unregister(hotkey) {
+                eprintln!("Failed to unregister profile hotkey: {}", e);
+            }
+        }
+        self.profile_hotkeys = register_profile_hotkeys(&self.hotkey_manager, &self.config);
+    }
 
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(2)
-            .enable_all()
-            .build()
-            .expect("tokio runtime");
+    /// 録音の波形とスペクトログラムを別ウィンドウで表示する
+    fn show_audio_monitor_window(&mut self, ctx: &egui::Context) {
+        // 録音コールバックを待たせないよう、ロックは新しいサンプルを写す間だけ
+        let mut view = std::mem::take(&mut self.audio_monitor_view);
+        view.copy_new_from(&self.audio_monitor.lock().unwrap());
+        view.update_spectrogram();
+        let mut texture = self.spectrogram_texture.take();
+        let mut open = true;
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("audio_monitor"),
+            egui::ViewportBuilder::default()
+                .with_title("winh - Audio Monitor")
+                .with_inner_size([640.0, 360.0]),
+            |ctx, class| {
+                let draw = |ui: &mut egui::Ui| draw_audio_monitor(ui, &view, &mut texture);
+                if class == egui::ViewportClass::Embedded {
+                    // 複数ウィンドウに対応していない環境ではメイン画面の中に出す
+                    egui::Window::new("Audio Monitor")
+                        .open(&mut open)
+                        .show(ctx, draw);
+                } else {
+                    egui::CentralPanel::default().show(ctx, draw);
+                    if ctx.input(|i| i.viewport().close_requested()) {
+                        open = false;
+                    }
+                }
+            },
+        );
+        self.spectrogram_texture = texture;
+        self.audio_monitor_view = view;
+        self.show_audio_monitor = open;
+    }
 
-        rt.spawn(async move {
-            let client = SpeechToTextClient::new(api_key);
-            if let Err(e) = client
-                .stream_transcribe(sample_rate, chunk_rx, tx.clone())
-                .await
+    /// 設定画面の A/B 聞き比べのボタン
+    fn show_dsp_ab_controls(&mut self, ui: &mut egui::Ui) {
+        let recording = self
+            .dsp_ab
+            .as_ref()
+            .filter(|ab| ab.recorder.is_some())
+            .map(|ab| ab.started.elapsed().as_secs_f32());
+        ui.horizontal(|ui| {
+            if let Some(elapsed) = recording {
+                ui.label(format!(
+                    "Recording sample... {:.1}s / {:.0}s",
+                    elapsed, DSP_AB_SECS
+                ));
+                return;
+            }
+            if ui
+                .add_enabled(
+                    !self.is_recording && !self.is_preparing,
+                    egui::Button::new("🎙 Record A/B sample"),
+                )
+                .on_hover_text("Record a few seconds to compare the original and processed audio")
+                .clicked()
             {
-                let _ = tx.send(TranscriptionMessage::Error(e.to_string()));
+                self.start_dsp_ab();
+            }
+            if self.dsp_ab.is_some() {
+                if ui.button("▶ A: original").clicked() {
+                    self.play_dsp_ab(false);
+                }
+                if ui.button("▶ B: processed").clicked() {
+                    self.play_dsp_ab(true);
+                }
             }
         });
+        if let Some(error) = &self.dsp_ab_error {
+            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), error);
+        }
+    }
 
-        self.tokio_runtime = Some(rt);
+    /// 処理前の音を録る (処理後の音は再生するときに今の設定で作る)
+    fn start_dsp_ab(&mut self) {
+        let device_name = self
+            .available_devices
+            .get(self.selected_device_index)
+            .filter(|name| name.as_str() != audio::DEFAULT_DEVICE_LABEL)
+            .cloned();
+        let (chunk_tx, chunk_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+        let result = self.settings_input_format().and_then(|input_format| {
+            let mut recorder = AudioRecorder::new(self.settings_silence_threshold)?;
+            recorder.set_input_format(input_format);
+            recorder
+                .start_recording_with_device(device_name.as_deref(), Some(chunk_tx))
+                .map(|_| recorder)
+        });
+        match result {
+            Ok(recorder) => {
+                if let Some(previous) = &self.dsp_ab {
+                    previous
+                        .playback
+                        .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+                self.dsp_ab = Some(DspAbTest {
+                    sample_rate: recorder.get_sample_rate(),
+                    recorder: Some(recorder),
+                    receiver: chunk_rx,
+                    started: std::time::Instant::now(),
+                    raw: Vec::new(),
+                    playback: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+                });
+                self.dsp_ab_error = None;
+            }
+            Err(e) => self.dsp_ab_error = Some(e),
+        }
+    }
+
+    fn poll_dsp_ab(&mut self, ctx: &egui::Context) {
+        let Some(ab) = self.dsp_ab.as_mut() else {
+            return;
+        };
+        while let Ok(chunk) = ab.receiver.try_recv() {
+            ab.raw.extend(chunk);
+        }
+        if ab.recorder.is_some() {
+            if ab.started.elapsed().as_secs_f32() >= DSP_AB_SECS {
+                ab.recorder = None;
+            } else {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            }
+        }
+    }
+
+    fn play_dsp_ab(&mut self, processed: bool) {
+        let Some(ab) = &self.dsp_ab else {
+            return;
+        };
+        let mut samples = ab.raw.clone();
+        if processed {
+            dsp::DspChain::new(&self.settings_dsp, ab.sample_rate).process(&mut samples);
+        }
+        let pcm = tts::Pcm {
+            samples,
+            sample_rate: ab.sample_rate,
+        };
+        // 再生中の音は止めてから鳴らす
+        // (tts::play は出力デバイスのサンプル形式に変換するので、f32 以外のデバイスでも鳴る)
+        let playback = Arc::clone(&ab.playback);
+        let generation = playback.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        std::thread::spawn(move || {
+            let cancelled = || playback.load(std::sync::atomic::Ordering::SeqCst) != generation;
+            if let Err(e) = tts::play(&pcm, None, &cancelled) {
+                eprintln!("A/B playback failed: {}", e);
+            }
+        });
+    }
+
+    /// マイクのキャリブレーションの画面
+    /// 雑音 → (任意で) 話し声を測り、無音判定のしきい値と無音時間を提案する
+    fn show_calibration_window(&mut self, ctx: &egui::Context) {
+        let Some(wizard) = self.calibration.as_mut() else {
+            return;
+        };
+        let elapsed = wizard.step_start.elapsed().as_secs_f32();
+
+        // 測定が終わったら、録音コールバックで貯めたブロックごとのピークを受け取る
+        let finished = match wizard.step {
+            CalibrationStep::Noise if elapsed >= CALIBRATION_NOISE_SECS => {
+                Some(CalibrationStep::SpeechIntro)
+            }
+            CalibrationStep::Speech if elapsed >= CALIBRATION_SPEECH_SECS => {
+                Some(CalibrationStep::Result)
+            }
+            _ => None,
+        };
+        if let Some(next) = finished {
+            let peaks = wizard
+                .recorder
+                .as_ref()
+                .map(AudioRecorder::peaks)
+                .unwrap_or_default();
+            if wizard.step == CalibrationStep::Noise {
+                wizard.data.noise = peaks;
+            } else {
+                wizard.data.speech = peaks;
+            }
+            wizard.set_step(next);
+        }
+        if matches!(
+            wizard.step,
+            CalibrationStep::SpeechIntro | CalibrationStep::Result
+        ) {
+            wizard.recorder = None;
+        }
+        if wizard.recorder.is_some() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(30));
+        }
+
+        let device_name = self
+            .available_devices
+            .get(self.selected_device_index)
+            .filter(|name| name.as_str() != audio::DEFAULT_DEVICE_LABEL)
+            .cloned();
+        let threshold = self.settings_silence_threshold;
+        let dsp_settings = self.settings_dsp.clone();
+        // wizard を借りているので settings_input_format() は呼べない
+        let input_format =
+            audio::parse_channel_list(&self.settings_input_channels).map(|channels| {
+                audio::InputFormat {
+                    channels,
+                    sample_rate: self.settings_input_sample_rate,
+                    buffer_size: self.settings_input_buffer_size,
+                }
+            });
+        let mut start_measuring = None;
+        let mut apply = None;
+        let mut close = false;
+
+        egui::Window::new("Calibrate Microphone")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Device: {}",
+                    device_name.as_deref().unwrap_or("Default")
+                ));
+                ui.add_space(5.0);
+                match wizard.step {
+                    CalibrationStep::Intro => {
+                        ui.label(format!(
+                            "Stay quiet for {} seconds to measure the room noise.",
+                            CALIBRATION_NOISE_SECS
+                        ));
+                        if ui.button("Start").clicked() {
+                            start_measuring = Some(CalibrationStep::Noise);
+                        }
+                    }
+                    CalibrationStep::SpeechIntro => {
+                        ui.label(format!(
+                            "Now speak normally for {} seconds, with the pauses you usually make.",
+                            CALIBRATION_SPEECH_SECS
+                        ));
+                        ui.horizontal(|ui| {
+                            if ui.button("Start").clicked() {
+                                start_measuring = Some(CalibrationStep::Speech);
+                            }
+                            if ui.button("Skip").clicked() {
+                                wizard.set_step(CalibrationStep::Result);
+                            }
+                        });
+                    }
+                    CalibrationStep::Noise | CalibrationStep::Speech => {
+                        let (label, total) = if wizard.step == CalibrationStep::Noise {
+                            ("Measuring room noise... stay quiet", CALIBRATION_NOISE_SECS)
+                        } else {
+                            (
+                                "Measuring your voice... keep talking",
+                                CALIBRATION_SPEECH_SECS,
+                            )
+                        };
+                        ui.label(label);
+                        ui.add(egui::ProgressBar::new((elapsed / total).min(1.0)));
+                        if let Some(recorder) = &wizard.recorder {
+                            draw_level_meter(ui, recorder.level(), threshold);
+                        }
+                    }
+                    CalibrationStep::Result => match wizard.data.result() {
+                        Some(result) => {
+                            ui.label(format!("Noise floor: {:.1} dBFS", result.noise_floor_db));
+                            if let Some(speech_db) = result.speech_db {
+                                ui.label(format!("Speech: {:.1} dBFS", speech_db));
+                            }
+                            ui.label(format!(
+                                "Proposed silence threshold: {:.4} ({:.1} dBFS)",
+                                result.silence_threshold,
+                                level_meter::amplitude_to_dbfs(result.silence_threshold)
+                            ));
+                            if let Some(duration) = result.silence_duration_secs {
+                                ui.label(format!("Proposed silence duration: {:.1}s", duration));
+                            }
+                            if ui.button("Apply to settings").clicked() {
+                                apply = Some(result);
+                            }
+                        }
+                        None => {
+                            ui.label("Nothing was measured.");
+                        }
+                    },
+                }
+                if let Some(error) = &wizard.error {
+                    ui.colored_label(egui::Color32::from_rgb(255, 100, 100), error);
+                }
+                ui.add_space(5.0);
+                if ui.button("Close").clicked() {
+                    close = true;
+                }
+            });
+
+        if let Some(step) = start_measuring {
+            match input_format.and_then(|input_format| {
+                let mut recorder = AudioRecorder::new(threshold)?;
+                // 無音判定と同じく DSP を通した音で測る
+                recorder.set_dsp(dsp_settings);
+                recorder.set_input_format(input_format);
+                recorder.record_peaks();
+                recorder
+                    .start_recording_with_device(device_name.as_deref(), None)
+                    .map(|_| recorder)
+            }) {
+                Ok(recorder) => {
+                    wizard.recorder = Some(recorder);
+                    wizard.error = None;
+                    wizard.set_step(step);
+                }
+                Err(e) => wizard.error = Some(e),
+            }
+        }
+        if let Some(result) = apply {
+            // 設定画面の値だけ変える (Save で保存)
+            self.settings_silence_threshold = result.silence_threshold;
+            if let Some(duration) = result.silence_duration_secs {
+                self.settings_silence_duration = duration;
+            }
+            close = true;
+        }
+        if close {
+            self.calibration = None;
+        }
+    }
+
+    /// 録音ホットキー以外のホットキーを設定から登録し直す
+    fn reregister_hotkeys(&mut self) {
+        let registered: Vec<HotKey> = self
+            .reset_conversation_hotkey
+            .take()
+            .into_iter()
+            .chain(self.recording_hotkeys.drain(..).map(|(hotkey, _)| hotkey))
+            .collect();
+        for hotkey in registered {
+            if let Err(e) = self.hotkey_manager.unregister(hotkey) {
+                eprintln!("Failed to unregister hotkey: {}", e);
+            }
+        }
+        self.reset_conversation_hotkey = register_optional_hotkey(
+            &self.hotkey_manager,
+            &self.config.eliza_reset_hotkey,
+            "Reset conversation",
+        );
+        self.recording_hotkeys = register_recording_hotkeys(&self.hotkey_manager, &self.config);
+        self.register_profile_hotkeys();
+    }
+
+    /// 設定ファイルが外で書き換えられたら読み直して反映する
+    /// 読めない内容なら警告を出して、今の設定のまま続ける
+    fn reload_config(&mut self) {
+        let path = match self
+            .config
+            .path
+            .clone()
+            .map(Ok)
+            .unwrap_or_else(Config::config_path)
+        {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Failed to read config file: {}", e);
+                return;
+            }
+        };
+        let mut new_config = match Config::parse(&content) {
+            Ok((config, _)) => config,
+            Err(e) => {
+                let warning = format!(
+                    "config.json was changed but could not be applied ({}); keeping the previous settings",
+                    e
+                );
+                eprintln!("{}", warning);
+                self.reload_warning = Some(warning);
+                return;
+            }
+        };
+        new_config.carry_over(&self.config);
+        // 読み込めたので、前回読み込めなかったときの警告は消す
+        self.reload_warning = None;
+        // 自分で保存したときの通知なら何もしない
+        if new_config.same_settings(&self.config) {
+            return;
+        }
+
+        eprintln!("Config file changed, applying: {:?}", path);
+        let old = std::mem::replace(&mut self.config, new_config);
+        let hotkey = std::mem::replace(&mut self.config.hotkey, old.hotkey.clone());
+        self.apply_record_hotkey(hotkey);
+        self.reregister_hotkeys();
+
+        if self.config.osc_send_addr != old.osc_send_addr {
+            self.vrchat_client =
+                vrchat::VRChatClient::with_target(self.config.osc_send_addr.clone());
+        }
+        self.vrchat_client.page_dwell = self.config.chatbox_page_dwell();
+        self.vrchat_client.page_marker = self.config.chatbox_page_marker;
+        if self.config.osc_listen_addr != old.osc_listen_addr
+            || self.config.oscquery_enabled != old.oscquery_enabled
+            || self.config.osc_double_toggle_secs != old.osc_double_toggle_secs
+            || self.config.osc_trigger_rules != old.osc_trigger_rules
+        {
+            self.restart_osc_listener();
+        }
+        self.send_avatar_state();
+        self.tts_player.configure(
+            self.config.tts_engine.clone(),
+            self.config.tts_output_device.clone(),
+        );
+        self.conversation.max_turns = self.config.eliza_max_turns;
+        self.conversation.max_tokens = self.config.eliza_max_tokens;
+
+        self.load_settings_from_config();
+        self.status_message = "Config reloaded".to_string();
+    }
+
+    /// プロファイルを切り替えて保存する
+    fn switch_profile(&mut self, name: &str) {
+        if name == self.config.active_profile {
+            return;
+        }
+        if self.is_recording || self.is_preparing || self.is_transcribing {
+            self.status_message = "Cannot switch profile while recording".to_string();
+            return;
+        }
+        let hotkey = self.config.hotkey.clone();
+        if let Err(e) = self.config.select_profile(name) {
+            self.status_message = e;
+            return;
+        }
+        self.on_profile_changed(hotkey);
+    }
+
+    /// 最前面のアプリに合うルールがあれば、録音開始前にそのプロファイルへ切り替える
+    /// 録音ホットキーの登録は、録音を始めたホットキーで止められるよう録音が終わるまで変えない
+    fn auto_switch_profile(&mut self) {
+        if !self.config.auto_profile_switching
+            || self.is_recording
+            || self.is_preparing
+            || self.is_transcribing
+        {
+            return;
+        }
+        let Some((name, window)) = foreground::profile_for_foreground(
+            &foreground::SystemForeground,
+            &self.config.profile_rules,
+        )
+        .map(|(name, window)| (name.to_string(), window)) else {
+            return;
+        };
+        if name == self.config.active_profile {
+            return;
+        }
+        println!(
+            "Foreground app {} ({}) matched profile: {}",
+            window.process, window.title, name
+        );
+        match self.config.select_profile(&name) {
+            Ok(()) => {
+                self.status_message.clear();
+                self.finish_profile_change();
+            }
+            Err(e) => eprintln!("Auto profile switch failed: {}", e),
+        }
+    }
+
+    /// Copy the current settings into a new profile and switch to it
+    fn create_profile(&mut self) {
+        let name = self.settings_new_profile_name.trim().to_string();
+        match self.config.create_profile(&name) {
+            Ok(()) => {
+                self.settings_new_profile_name.clear();
+                self.on_profile_changed(self.config.hotkey.clone());
+            }
+            Err(e) => self.status_message = e,
+        }
+    }
+
+    fn delete_active_profile(&mut self) {
+        let hotkey = self.config.hotkey.clone();
+        match self.config.delete_active_profile() {
+            Ok(()) => {
+                self.register_profile_hotkeys();
+                self.on_profile_changed(hotkey);
+            }
+            Err(e) => self.status_message = e,
+        }
+    }
+
+    /// プロファイルが変わった後の後始末 (ホットキー・設定画面・保存)
+    fn on_profile_changed(&mut self, previous_hotkey: String) {
+        // 録音ホットキーは登録し直す必要があるので、一旦元に戻してから適用する
+        let new_hotkey = std::mem::replace(&mut self.config.hotkey, previous_hotkey);
+        self.status_message.clear();
+        self.apply_record_hotkey(new_hotkey);
+        self.finish_profile_change();
+    }
+
+    /// 切り替えたプロファイルを設定画面に反映して保存する
+    fn finish_profile_change(&mut self) {
+        self.load_settings_from_config();
+        if let Err(e) = self.config.save() {
+            eprintln!("Failed to save config: {}", e);
+        }
+        if self.status_message.is_empty() {
+            self.status_message = format!("Profile: {}", self.config.active_profile);
+        }
+        println!("Switched to profile: {}", self.config.active_profile);
+    }
+
+    fn on_prepare_recording(&mut self) {
+        println!("Preparing to record...");
+        self.is_preparing = true;
+        self.prepare_start_time = Some(std::time::Instant::now());
+        self.status_message = "Preparing to record...".to_string();
+    }
+
+    /// 録音を始める
+    /// 最前面のアプリに合うプロファイルがあれば、録音中になる前に切り替えておく
+    fn start_recording(&mut self) {
+        self.auto_switch_profile();
+        self.is_recording = true;
+        self.on_actually_start_recording();
+    }
+
+    fn on_actually_start_recording(&mut self) {
+        println!("Recording started");
+        self.recording_translation = None;
+        self.recording_rewrite = None;
+        self.status_message = "Starting recording...".to_string();
+        self.recording_info.clear();
+
+        match AudioRecorder::new(self.config.silence_threshold) {
+            Ok(mut recorder) => {
+                recorder.set_monitor(
+                    Arc::clone(&self.audio_monitor),
+                    self.config.monitor_window_secs,
+                );
+                recorder.set_dsp(self.config.dsp.clone());
+                // Use configured device if set, otherwise use default
+                // If the default entry is selected, use None to get default device
+                // 外れていれば予備のデバイスを使う
+                // 連続ディクテーションは 1 本の書き起こしを区切っていくので、
+                // "me" / "them" に分けずに混ぜて聞く
+                let continuous = self.config.continuous.enabled;
+                let source = match self.config.input_source {
+                    InputSource::Separate if continuous => InputSource::Mixed,
+                    source => source,
+                };
+                let device = if source.uses_microphone() {
+                    audio::select_input_device(
+                        self.configured_input_device(),
+                        &self.config.input_device_fallbacks,
+                        self.config.input_device_fallback_to_default,
+                    )
+                } else {
+                    Ok(audio::DeviceChoice {
+                        name: None,
+                        fallback_from: None,
+                    })
+                };
+
+                let (chunk_tx, chunk_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+                // "me" / "them" に分けるときは再生音を別のチャンネルで受け取る
+                let (system_tx, system_rx) = if source == InputSource::Separate {
+                    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f32>>();
+                    (Some(tx), Some(rx))
+                } else {
+                    (None, None)
+                };
+
+                match device.and_then(|device| {
+                    recorder.set_input_format(device.input_format(&self.config.input_format));
+                    recorder
+                        .start_recording_from_source(
+                            source,
+                            device.name.as_deref(),
+                            Some(chunk_tx),
+                            system_tx,
+                        )
+                        .map(|_| device)
+                }) {
+                    Ok(device) => {
+                        if let Some(missing) = &device.fallback_from {
+                            println!(
+                                "Input device '{}' not found, using {}",
+                                missing,
+                                device.name.as_deref().unwrap_or("the default device")
+                            );
+                        }
+                        let sample_rate = recorder.get_sample_rate();
+                        self.audio_recorder = Some(recorder);
+                        self.set_vrchat_typing(true);
+                        self.set_avatar_state(vrchat::AvatarState::Recording);
+
+                        if self.config.xai_api_key.is_empty() {
+                            self.status_message =
+                                "Recording... (Set xAI API key to enable transcription)"
+                                    .to_string();
+                        } else {
+                            self.status_message = "Recording... Speak now!".to_string();
+                            let segment_rx = if continuous {
+                                let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+                                self.segment_sender = Some(tx);
+                                self.continuous_session = Some(dictation::ContinuousSession::new(
+                                    self.config.continuous.clone(),
+                                    self.config.silence_duration_secs,
+                                ));
+                                self.status_message =
+                                    format!("Dictating... Press {} to stop", self.config.hotkey);
+                                Some(rx)
+                            } else {
+                                None
+                            };
+                            self.start_streaming_transcription(
+                                sample_rate,
+                                chunk_rx,
+                                system_rx,
+                                segment_rx,
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        self.status_message = format!("Error: {}", e);
+                        self.is_recording = false;
+                        self.set_avatar_state(vrchat::AvatarState::Error);
+                        eprintln!("Failed to start recording: {}", e);
+                    }
+                }
+            }
+            Err(e) => {
+                self.status_message = format!("Error: {}", e);
+                self.is_recording = false;
+                self.set_avatar_state(vrchat::AvatarState::Error);
+                eprintln!("Failed to create audio recorder: {}", e);
+            }
+        }
+    }
+
+    fn on_stop_recording(&mut self) {
+        println!("Recording stopped");
+
+        if let Some(mut recorder) = self.audio_recorder.take() {
+            recorder.stop_recording();
+            // stream drop → コールバッククロージャdrop → chunk_senderがdrop
+            // → UnboundedReceiver側がdisconnectを検知 → WebSocketタスクがaudio.doneを送信
+        }
+        // 最後の区切りは STT 側で audio.done の後に確定する
+        self.segment_sender = None;
+        // 録音開始時にプロファイルが自動で切り替わっていれば、ここで録音ホットキーを切り替える
+        self.register_record_hotkey();
+
+        self.recording_info.clear();
+
+        if self.is_transcribing {
+            self.status_message = "Transcribing...".to_string();
+            self.set_avatar_state(vrchat::AvatarState::Transcribing);
+        } else {
+            self.status_message =
+                "Recording stopped. Set xAI API key in Settings to enable transcription."
+                    .to_string();
+            self.set_vrchat_typing(false);
+            self.set_avatar_state(vrchat::AvatarState::Idle);
+        }
+    }
+
+    /// 翻訳・書き直しのホットキー: 待機中なら録音を始め、録音中なら止める
+    fn on_recording_hotkey(&mut self, options: RecordingHotkey) {
+        if self.is_recording {
+            println!("Recording hotkey triggered → stop recording");
+            self.is_recording = false;
+            self.on_stop_recording();
+            return;
+        }
+        if self.is_transcribing || self.is_preparing {
+            return;
+        }
+        println!("Recording hotkey triggered: {:?}", options);
+        self.start_recording();
+        match options {
+            RecordingHotkey::Translation(languages) => self.recording_translation = Some(languages),
+            RecordingHotkey::Rewrite(template) => self.recording_rewrite = Some(template),
+        }
+    }
+
+    /// OSC トリガールールの動作を実行する
+    fn on_osc_trigger(&mut self, event: OscTriggerEvent) {
+        let is_idle = !self.is_recording && !self.is_transcribing && !self.is_preparing;
+        match event.action {
+            OscAction::StartRecording | OscAction::ToggleRecording if is_idle => {
+                // 録音開始時の GestureRight が設定値なら eliza モード
+                let gesture_right = event.parameter_as_int("GestureRight").unwrap_or(0);
+                let eliza_mode =
+                    self.config.eliza_enabled && gesture_right == self.config.eliza_gesture;
+                println!(
+                    "VRChat OSC trigger received → start recording (gesture_right={}, eliza_mode={})",
+                    gesture_right, eliza_mode
+                );
+                self.eliza_mode = eliza_mode;
+                self.start_recording();
+            }
+            OscAction::StopRecording | OscAction::ToggleRecording if self.is_recording => {
+                println!("VRChat OSC trigger received → stop recording");
+                self.is_recording = false;
+                self.on_stop_recording();
+            }
+            OscAction::ResetConversation => {
+                println!("VRChat OSC trigger received → reset conversation");
+                self.reset_conversation();
+            }
+            OscAction::SelectProfile { profile } => {
+                match profile.resolve(&self.config.profile_names()) {
+                    Some(name) => {
+                        println!("VRChat OSC trigger received → select profile {}", name);
+                        // 録音中は switch_profile が断る
+                        self.switch_profile(&name);
+                    }
+                    None => {
+                        eprintln!("[OSC Trigger] No profile matches {:?}", profile);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 書き起こし結果を翻訳・書き直しを経て各出力先へ送る
+    fn process_transcript(
+        &mut self,
+        text: String,
+        languages: Option<TranslationLanguages>,
+        rewrite_name: &str,
+    ) {
+        self.transcript_rewrite =
+            rewrite::find_template(&self.config.rewrite_templates, rewrite_name).cloned();
+        match languages {
+            Some(languages) if !text.is_empty() => {
+                self.start_translation(text, languages);
+            }
+            _ => self.route_transcript(&text, &text, Vec::new()),
+        }
+    }
+
+    /// 連続ディクテーションの区切りを 1 つずつ出力する
+    /// 前の区切りの翻訳・書き直し (プレビュー含む)・アシスタントの返答が終わるまでは待つ
+    fn poll_segments(&mut self) {
+        let busy = self.translation_receiver.is_some()
+            || self.rewrite_receiver.is_some()
+            || self.rewrite_preview.is_some()
+            || self.assistant_busy();
+        if let Some((text, languages, rewrite_name)) = self.pending_segments.next(busy) {
+            self.transcribed_text = text.clone();
+            self.process_transcript(text, languages, &rewrite_name);
+        }
+    }
+
+    /// 連続ディクテーションを終える (まだ録音中なら止める)
+    fn end_continuous_session(&mut self) {
+        if self.continuous_session.take().is_none() {
+            return;
+        }
+        self.segment_sender = None;
+        if self.is_recording {
+            self.is_recording = false;
+            self.on_stop_recording();
+        }
+        // 出力待ちの区切りが残っていれば、最後の区切りを出力したときに待機状態へ戻す
+        let delivering = !self.pending_segments.is_empty()
+            || self.translation_receiver.is_some()
+            || self.rewrite_receiver.is_some()
+            || self.rewrite_preview.is_some();
+        if !delivering {
+            self.eliza_mode = false;
+            if !self.assistant_busy() {
+                self.set_vrchat_typing(false);
+                self.set_avatar_state(vrchat::AvatarState::Idle);
+            }
+        }
+    }
+
+    /// アシスタントの返答待ち・ツールの実行中・ツールの確認待ちのどれか
+    fn assistant_busy(&self) -> bool {
+        self.eliza_response_receiver.is_some()
+            || self.running_tool.is_some()
+            || self.tool_confirmation.is_some()
+    }
+
+    /// 書き直しテンプレートが有効なら書き直しへ、そうでなければ各出力先へ送る
+    fn route_transcript(&mut self, original: &str, output: &str, status_parts: Vec<&'static str>) {
+        match self.transcript_rewrite.take() {
+            Some(template) if !output.is_empty() => {
+                self.start_rewrite(original, output.to_string(), template, status_parts);
+            }
+            _ => self.deliver_transcript(original, output, status_parts),
+        }
+    }
+
+    /// 書き直しをバックグラウンドで始める (結果は poll_rewrite で受け取る)
+    /// 書き直した結果 (または原文) は、書き直さない場合と同じく全ての出力先に送る
+    fn start_rewrite(
+        &mut self,
+        assistant_text: &str,
+        text: String,
+        template: RewriteTemplate,
+        status_parts: Vec<&'static str>,
+    ) {
+        self.status_message = format!("Rewriting ({})...", template.name);
+        self.rewrite_preview = None;
+        self.last_rewrite = None;
+
+        let config = self.config.clone();
+        let source = text.clone();
+        let template_name = template.name.clone();
+        self.rewrite_receiver = Some(spawn_job(move || {
+            rewrite::rewrite(&config, &template, &source)
+        }));
+        self.rewrite_preview = Some(RewritePreview {
+            template: template_name,
+            original: text,
+            rewritten: String::new(),
+            assistant_text: assistant_text.to_string(),
+            status_parts,
+        });
+    }
+
+    /// 書き直しが終わっていればプレビューに出す (プレビューが無効ならそのまま出力する)
+    fn poll_rewrite(&mut self) {
+        let Some(result) = take_job_result(&mut self.rewrite_receiver, "Rewrite") else {
+            return;
+        };
+        let Some(mut preview) = self.rewrite_preview.take() else {
+            return;
+        };
+        match result {
+            Ok(rewritten) => {
+                preview.rewritten = rewritten;
+                if self.config.rewrite_preview {
+                    self.transcribed_text = preview.rewritten.clone();
+                    self.status_message = format!(
+                        "Rewrite ready ({}): use it or keep the original",
+                        preview.template
+                    );
+                    self.rewrite_preview = Some(preview);
+                } else {
+                    self.apply_rewrite(preview, true);
+                }
+            }
+            Err(e) => {
+                eprintln!("[Rewrite] {}", e);
+                let mut status_parts = preview.status_parts;
+                status_parts.push("rewrite failed");
+                self.deliver_transcript(&preview.assistant_text, &preview.original, status_parts);
+            }
+        }
+    }
+
+    /// 書き直し結果 (use_rewrite が false なら原文) を各出力先に送る
+    fn apply_rewrite(&mut self, preview: RewritePreview, use_rewrite: bool) {
+        let output = if use_rewrite {
+            &preview.rewritten
+        } else {
+            &preview.original
+        };
+        self.transcribed_text = output.clone();
+        let mut status_parts = preview.status_parts.clone();
+        status_parts.push(if use_rewrite {
+            "rewritten"
+        } else {
+            "kept original"
+        });
+        self.deliver_transcript(&preview.assistant_text, output, status_parts);
+        if use_rewrite {
+            self.last_rewrite = Some((preview.original, preview.rewritten));
+        }
+    }
+
+    /// 直前の書き直しを取り消し、原文を表示・クリップボードに戻す
+    /// (自動入力で打ち込んだ文字は取り消せない)
+    fn undo_rewrite(&mut self) {
+        let Some((original, _)) = self.last_rewrite.take() else {
+            return;
+        };
+        self.transcribed_text = original.clone();
+        let mut status_parts = Vec::new();
+        self.copy_output_to_clipboard(&original, &mut status_parts);
+        self.status_message = if status_parts.is_empty() {
+            "Rewrite undone".to_string()
+        } else {
+            format!("Rewrite undone ({})", status_parts.join(", "))
+        };
+    }
+
+    /// 確定した文字起こしを各出力先 (クリップボード・VRChat・Eliza・自動入力) に送る
+    /// original はアシスタントに送る原文、output は翻訳を反映した出力用テキスト
+    fn deliver_transcript(
+        &mut self,
+        original: &str,
+        output: &str,
+        mut status_parts: Vec<&'static str>,
+    ) {
+        // Conditional clipboard copy
+        self.copy_output_to_clipboard(output, &mut status_parts);
+
+        // Conditional VRChat OSC send
+        if self.config.vrchat_enabled && !output.is_empty() {
+            match self
+                .vrchat_client
+                .send_message(&format!("{}{}", USER_PREFIX, output))
+            {
+                Ok(_) => {
+                    status_parts.push("sent to VRChat");
+                    println!("Text sent to VRChat via OSC: {}", output);
+                }
+                Err(e) => {
+                    status_parts.push("VRChat send failed");
+                    eprintln!("VRChat OSC error: {}", e);
+                }
+            }
+        }
+
+        // 連続ディクテーション中は録音を続けているので、状態の表示は end_continuous_session で戻す
+        let dictating = self.continuous_session.is_some();
+
+        // Conditional eliza-agent-server send (background)
+        if self.eliza_mode && !original.is_empty() {
+            let client = assistant::from_config(&self.config);
+            let messages = self.conversation.request_messages(original);
+            self.eliza_pending_text = Some(original.to_string());
+            self.assistant_reply.clear();
+            self.assistant_preview_sent = None;
+            self.assistant_request = messages.clone();
+            self.tool_rounds = 0;
+            self.eliza_response_receiver = Some(assistant::spawn_chat(client, messages));
+            status_parts.push("sent to Eliza");
+            if !dictating {
+                // Eliza の返答待ちの間も typing を表示する
+                self.set_vrchat_typing(true);
+                self.set_avatar_state(vrchat::AvatarState::AwaitingReply);
+            }
+        } else if !dictating {
+            if output.is_empty() {
+                self.set_vrchat_typing(false);
+            }
+            self.set_avatar_state(vrchat::AvatarState::Idle);
+        }
+
+        // Conditional auto-input
+        self.auto_input_output(output, &mut status_parts);
+
+        if dictating {
+            println!("Dictation segment delivered ({})", status_parts.join(", "));
+            return;
+        }
+        self.eliza_mode = false;
+
+        // Build status message
+        let base = "Transcription completed";
+        self.status_message = if status_parts.is_empty() {
+            format!("{}!", base)
+        } else {
+            format!("{} ({})", base, status_parts.join(", "))
+        };
+    }
+
+    fn copy_output_to_clipboard(&self, output: &str, status_parts: &mut Vec<&'static str>) {
+        if self.config.clipboard_enabled {
+            match arboard::Clipboard::new() {
+                Ok(mut clipboard) => match clipboard.set_text(output) {
+                    Ok(_) => {
+                        status_parts.push("copied to clipboard");
+                        println!("Text copied to clipboard: {}", output);
+                    }
+                    Err(e) => {
+                        status_parts.push("clipboard failed");
+                        eprintln!("Clipboard error: {}", e);
+                    }
+                },
+                Err(e) => {
+                    status_parts.push("clipboard init failed");
+                    eprintln!("Clipboard init error: {}", e);
+                }
+            }
+        }
+    }
+
+    fn auto_input_output(&self, output: &str, status_parts: &mut Vec<&'static str>) {
+        if self.config.auto_input_enabled {
+            // If clipboard is enabled, use Ctrl+V to paste
+            // Otherwise, type the text character-by-character
+            // If send_enter is enabled, use the _with_enter variants
+            let result = match (
+                self.config.clipboard_enabled,
+                self.config.auto_input_send_enter,
+            ) {
+                (true, true) => {
+                    status_parts.push("auto-input (Ctrl+V + Enter) started");
+                    println!("Auto-input (Ctrl+V + Enter) started");
+                    auto_input::send_ctrl_v_with_enter()
+                }
+                (true, false) => {
+                    status_parts.push("auto-input (Ctrl+V) started");
+                    println!("Auto-input (Ctrl+V) started");
+                    auto_input::send_ctrl_v()
+                }
+                (false, true) => {
+                    status_parts.push("auto-input (typing + Enter) started");
+                    println!("Auto-input (typing + Enter) started");
+                    auto_input::type_text_with_enter(output)
+                }
+                (false, false) => {
+                    status_parts.push("auto-input (typing) started");
+                    println!("Auto-input (typing) started");
+                    auto_input::type_text(output)
+                }
+            };
+
+            if let Err(e) = result {
+                status_parts.pop(); // Remove the "started" message
+                status_parts.push("auto-input failed");
+                eprintln!("Auto-input error: {}", e);
+            }
+        }
+    }
+
+    /// 文字起こしの翻訳をバックグラウンドで始める (結果は poll_translation で受け取る)
+    fn start_translation(&mut self, text: String, languages: TranslationLanguages) {
+        self.status_message = format!(
+            "Translating ({} → {})...",
+            languages.source_lang, languages.target_lang
+        );
+        let translator = translation::Translator::from_config(&self.config);
+        let original = text.clone();
+        self.translation_receiver = Some(spawn_job(move || {
+            translator.translate(&original, &languages)
+        }));
+        self.translation_original = text;
+    }
+
+    /// 翻訳が終わっていれば原文と訳文を組み立てて出力する
+    /// 翻訳に失敗した場合は原文をそのまま出力する
+    fn poll_translation(&mut self) {
+        let Some(result) = take_job_result(&mut self.translation_receiver, "Translation") else {
+            return;
+        };
+        let original = std::mem::take(&mut self.translation_original);
+        match result {
+            Ok(translation) => {
+                let output = translation::format_output(
+                    &original,
+                    &translation,
+                    self.config.translation_output,
+                );
+                self.transcribed_text = output.clone();
+                self.route_transcript(&original, &output, vec!["translated"]);
+            }
+            Err(e) => {
+                eprintln!("[Translator] {}", e);
+                self.route_transcript(&original, &original, vec!["translation failed"]);
+            }
+        }
+    }
+
+    /// アシスタントの返答イベントを処理する
+    /// 断片は画面に逐次表示し、chatbox には送信間隔を空けて途中経過を送る
+    fn poll_assistant_events(&mut self) {
+        let Some(receiver) = self.eliza_response_receiver.take() else {
+            return;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(AssistantEvent::Delta(delta)) => {
+                    self.assistant_reply.push_str(&delta);
+                }
+                Ok(AssistantEvent::Done(response)) => {
+                    println!("[Eliza] Response received → send to VRChat: {}", response);
+                    self.assistant_reply = response.clone();
+                    if let Some(user_text) = self.eliza_pending_text.take() {
+                        self.conversation.record_turn(&user_text, &response);
+                        self.save_conversation();
+                    }
+                    if let Err(e) = self
+                        .vrchat_client
+                        .send_message(&format!("{}{}", ELIZA_PREFIX, response))
+                    {
+                        eprintln!("[Eliza] Failed to send response to VRChat: {}", e);
+                    }
+                    if self.config.tts_enabled {
+                        self.tts_player.speak(&response);
+                    }
+                    // 連続ディクテーション中はまだ録音している
+                    if self.continuous_session.is_none() {
+                        self.set_avatar_state(vrchat::AvatarState::Idle);
+                    }
+                    return;
+                }
+                Ok(AssistantEvent::ToolCalls { content, calls }) => {
+                    if self.tool_rounds >= MAX_TOOL_ROUNDS {
+                        self.on_assistant_error("Too many tool calls");
+                        return;
+                    }
+                    self.tool_rounds += 1;
+                    self.assistant_reply.clear();
+                    self.assistant_request
+                        .push(ChatMessage::assistant_tool_calls(&content, calls.clone()));
+                    self.pending_tool_calls = calls.into();
+                    self.run_pending_tool_calls();
+                    return;
+                }
+                Ok(AssistantEvent::Error(e)) => {
+                    self.on_assistant_error(&e);
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.on_assistant_error("Assistant thread exited unexpectedly");
+                    return;
+                }
+            }
+        }
+
+        // 新しい断片が届いていなければ送り直さない (送信枠と通知音を無駄にしない)
+        let preview_due = match &self.assistant_preview_sent {
+            Some((sent_at, text)) => {
+                sent_at.elapsed() >= vrchat::CHATBOX_MIN_INTERVAL && *text != self.assistant_reply
+            }
+            None => true,
+        };
+        if preview_due && !self.assistant_reply.is_empty() {
+            if let Err(e) = self
+                .vrchat_client
+                .send_preview(&format!("{}{}", ELIZA_PREFIX, self.assistant_reply))
+            {
+                eprintln!("[Eliza] Failed to send preview to VRChat: {}", e);
+            }
+            self.assistant_preview_sent =
+                Some((std::time::Instant::now(), self.assistant_reply.clone()));
+        }
+        self.eliza_response_receiver = Some(receiver);
+    }
+
+    /// 要求されたツールを順に実行し、全部終わったら結果を添えて再度問い合わせる
+    /// 確認が必要なツールがあればそこで止め、確認ダイアログの応答を待つ
+    /// ツールはワーカースレッドで動くので、実行中は poll_tool_call で結果を待つ
+    fn run_pending_tool_calls(&mut self) {
+        while let Some(call) = self.pending_tool_calls.pop_front() {
+            let tool = self.find_tool(&call.function.name).cloned();
+            match tool {
+                Some(tool) if tool.needs_confirmation() => {
+                    self.status_message = format!("🔧 Waiting for confirmation: {}", tool.name);
+                    self.tool_confirmation = Some(call);
+                    return;
+                }
+                Some(tool) => {
+                    self.start_tool_call(&tool, call);
+                    return;
+                }
+                None => {
+                    let result = Err(format!("Unknown tool: {}", call.function.name));
+                    self.finish_tool_call(&call, result);
+                }
+            }
+        }
+
+        let client = assistant::from_config(&self.config);
+        self.eliza_response_receiver = Some(assistant::spawn_chat(
+            client,
+            self.assistant_request.clone(),
+        ));
+    }
+
+    fn start_tool_call(&mut self, tool: &tools::ToolDefinition, call: ToolCall) {
+        self.status_message = format!("🔧 Running: {}", tool.name);
+        let receiver = tools::spawn_execute(tool, &call.function.arguments, &self.vrchat_client);
+        self.running_tool = Some((call, receiver));
+    }
+
+    /// 実行中のツールが終わっていれば結果を記録し、残りのツール呼び出しを続ける
+    fn poll_tool_call(&mut self) {
+        let Some((_, receiver)) = &self.running_tool else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("Tool thread exited unexpectedly".to_string()),
+        };
+        let Some((call, _)) = self.running_tool.take() else {
+            return;
+        };
+        self.finish_tool_call(&call, result);
+        self.run_pending_tool_calls();
+    }
+
+    fn find_tool(&self, name: &str) -> Option<&tools::ToolDefinition> {
+        if !self.config.assistant_tools_enabled {
+            return None;
+        }
+        self.config.assistant_tools.iter().find(|t| t.name == name)
+    }
+
+    /// ツールの実行結果を履歴に記録し、モデルに返すメッセージに加える
+    fn finish_tool_call(&mut self, call: &ToolCall, result: Result<String, String>) {
+        let invocation =
+            tools::ToolInvocation::new(&call.function.name, &call.function.arguments, &result);
+        println!("[Tool] {:?}", invocation);
+        if let Err(e) = tools::log_invocation(&invocation) {
+            eprintln!("[Tool] Failed to log invocation: {}", e);
+        }
+        let content = match result {
+            Ok(output) => {
+                self.status_message = format!("🔧 {}: ok", call.function.name);
+                output
+            }
+            Err(e) => {
+                self.status_message = format!("🔧 {}: {}", call.function.name, e);
+                format!("error: {}", e)
+            }
+        };
+        self.assistant_request
+            .push(ChatMessage::tool_result(&call.id, &content));
+    }
+
+    /// 確認ダイアログの応答を反映して残りのツール呼び出しを続ける
+    fn answer_tool_confirmation(&mut self, allowed: bool) {
+        let Some(call) = self.tool_confirmation.take() else {
+            return;
+        };
+        let result = match self.find_tool(&call.function.name).cloned() {
+            Some(tool) if allowed => {
+                self.start_tool_call(&tool, call);
+                return;
+            }
+            Some(_) => Err("denied by user".to_string()),
+            None => Err(format!("Unknown tool: {}", call.function.name)),
+        };
+        self.finish_tool_call(&call, result);
+        self.run_pending_tool_calls();
+    }
+
+    fn on_assistant_error(&mut self, error: &str) {
+        eprintln!("[Eliza] Error: {}", error);
+        self.eliza_pending_text = None;
+        self.set_vrchat_typing(false);
+        self.set_avatar_state(vrchat::AvatarState::Error);
+        self.status_message = format!("❌ Assistant error: {}", error);
+    }
+
+    /// Eliza との会話履歴を消去する
+    fn reset_conversation(&mut self) {
+        self.conversation.reset();
+        self.eliza_pending_text = None;
+        self.assistant_reply.clear();
+        // 問い合わせやツールの実行の途中なら、その結果は捨てる
+        if self.eliza_response_receiver.take().is_some() || self.running_tool.is_some() {
+            self.set_vrchat_typing(false);
+            self.set_avatar_state(vrchat::AvatarState::Idle);
+        }
+        self.running_tool = None;
+        self.assistant_request.clear();
+        self.pending_tool_calls.clear();
+        self.tool_confirmation = None;
+        self.tts_player.stop();
+        self.save_conversation();
+        self.status_message = "Conversation reset".to_string();
+    }
+
+    /// 会話履歴の永続化が有効なら保存する
+    fn save_conversation(&self) {
+        if !self.config.eliza_persist_conversation {
+            return;
+        }
+        if let Err(e) = Conversation::default_path().and_then(|p| self.conversation.save(&p)) {
+            eprintln!("Failed to save conversation: {}", e);
+        }
+    }
+
+    /// 状態が変わったときだけアバターパラメータを送る
+    fn set_avatar_state(&mut self, state: vrchat::AvatarState) {
+        if self.avatar_state == state {
+            return;
+        }
+        self.avatar_state = state;
+        self.avatar_state_since = std::time::Instant::now();
+        self.send_avatar_state();
+    }
+
+    fn send_avatar_state(&self) {
+        if !self.config.avatar_parameters_enabled {
+            return;
+        }
+        println!("[VRChat OSC] Avatar state → {:?}", self.avatar_state);
+        if let Err(e) = self
+            .vrchat_client
+            .send_state(self.avatar_state, &self.config.avatar_state_bindings)
+        {
+            eprintln!("VRChat avatar parameter error: {}", e);
+        }
+    }
+
+    /// VRChat 送信が有効なときだけ chatbox の typing インジケータを切り替える
+    fn set_vrchat_typing(&self, typing: bool) {
+        if !self.config.vrchat_enabled {
+            return;
+        }
+        if let Err(e) = self.vrchat_client.set_typing(typing) {
+            eprintln!("VRChat typing indicator error: {}", e);
+        }
+    }
+
+    fn start_streaming_transcription(
+        &mut self,
+        sample_rate: u32,
+        chunk_rx: tokio::sync::mpsc::UnboundedReceiver<Vec<f32>>,
+        system_rx: Option<tokio::sync::mpsc::UnboundedReceiver<Vec<f32>>>,
+        segment_rx: Option<tokio::sync::mpsc::UnboundedReceiver<()>>,
+    ) {
+        let (msg_tx, msg_rx) = tokio::sync::mpsc::unbounded_channel::<TranscriptionMessage>();
+        self.transcription_receiver = Some(msg_rx);
+        self.is_transcribing = true;
+        let _ = msg_tx.send(TranscriptionMessage::InProgress);
+
+        let api_key = self.config.xai_api_key.clone();
+        let language = self.config.stt_language.clone();
+        let remove_punctuation = self.config.remove_punctuation;
+        let tx = msg_tx.clone();
+
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("tokio runtime");
+
+        rt.spawn(async move {
+            let client = SpeechToTextClient::new(api_key, language, remove_punctuation);
+            let result = match segment_rx {
+                Some(segment_rx) => {
+                    client
+                        .stream_transcribe_segmented(
+                            sample_rate,
+                            chunk_rx,
+                            Some(segment_rx),
+                            tx.clone(),
+                        )
+                        .await
+                }
+                None => {
+                    client
+                        .stream_transcribe_recording(sample_rate, chunk_rx, system_rx, tx.clone())
+                        .await
+                }
+            };
+            if let Err(e) = result {
+                let _ = tx.send(TranscriptionMessage::Error(e.to_string()));
+            }
+        });
+
+        self.tokio_runtime = Some(rt);
+    }
+}
+
+/// 設定画面で編集する OSC トリガールールの JSON
+fn osc_rules_text(rules: &[osc_trigger::OscTriggerRule]) -> String {
+    serde_json::to_string_pretty(rules).unwrap_or_default()
+}
+
+/// 設定画面で選べるサンプルレートとバッファサイズ
+const INPUT_SAMPLE_RATES: [u32; 6] = [16000, 22050, 32000, 44100, 48000, 96000];
+const INPUT_BUFFER_SIZES: [u32; 6] = [128, 256, 512, 1024, 2048, 4096];
+
+fn channel_list_text(channels: &[u16]) -> String {
+    channels
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// None (デバイスの既定) か一覧の値を選ぶ ComboBox
+fn format_option_combo(
+    ui: &mut egui::Ui,
+    id: &str,
+    value: &mut Option<u32>,
+    options: &[u32],
+    suffix: &str,
+) {
+    let text = |v: Option<u32>| match v {
+        Some(v) => format!("{}{}", v, suffix),
+        None => "Device default".to_string(),
+    };
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(text(*value))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(value, None, text(None));
+            for &option in options {
+                ui.selectable_value(value, Some(option), text(Some(option)));
+            }
+        });
+}
+
+/// 設定画面の入力デバイスの選択肢
+/// 先頭は既定のデバイスで、設定されているのに外れているデバイスも選べるように残す
+fn device_options(connected: &[String], keep: &[Option<&str>]) -> Vec<String> {
+    let mut options = vec![audio::DEFAULT_DEVICE_LABEL.to_string()];
+    options.extend(connected.iter().cloned());
+    for name in keep.iter().flatten() {
+        if !options.iter().any(|d| d == name) {
+            options.push(name.to_string());
+        }
+    }
+    options
+}
+
+/// 波形 (無音・発声の区間と無音で止めた位置を重ねる) とスペクトログラム
+fn draw_audio_monitor(
+    ui: &mut egui::Ui,
+    monitor: &AudioMonitor,
+    texture: &mut Option<egui::TextureHandle>,
+) {
+    let sample_rate = monitor.sample_rate();
+    if sample_rate == 0 {
+        ui.label("No recording yet.");
+        return;
+    }
+    // 右端を最新にして、表示する秒数分の幅を取る
+    let end = monitor.total_samples();
+    let span = monitor.window_samples().max(1) as u64;
+    let start = end.saturating_sub(span);
+    ui.label(format!(
+        "Last {:.1}s of {:.1}s recorded  (green: voice, yellow line: silence threshold, red: silence timeout)",
+        span as f32 / sample_rate as f32,
+        end as f32 / sample_rate as f32
+    ));
+
+    let width = ui.available_width();
+    let height = ((ui.available_height() - 10.0) / 2.0).max(60.0);
+    let (wave_rect, _) = ui.allocate_exact_size(egui::vec2(width, height), egui::Sense::hover());
+    let x_at = |pos: u64| {
+        wave_rect.min.x + wave_rect.width() * (pos.saturating_sub(start)) as f32 / span as f32
+    };
+    let painter = ui.painter_at(wave_rect);
+    painter.rect_filled(wave_rect, 0.0, egui::Color32::from_rgb(30, 30, 30));
+
+    // Voice regions
+    for (from, to, voice) in monitor.regions() {
+        if voice {
+            painter.rect_filled(
+                egui::Rect::from_x_y_ranges(x_at(from)..=x_at(to), wave_rect.y_range()),
+                0.0,
+                egui::Color32::from_rgba_unmultiplied(0, 200, 0, 40),
+            );
+        }
+    }
+
+    // Silence threshold
+    let y_at =
+        |value: f32| wave_rect.center().y - value.clamp(-1.0, 1.0) * wave_rect.height() / 2.0;
+    for value in [monitor.threshold(), -monitor.threshold()] {
+        painter.hline(
+            wave_rect.x_range(),
+            y_at(value),
+            egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 200, 0)),
+        );
+    }
+
+    // Waveform (min / max per pixel)
+    let samples = monitor.samples();
+    let offset = monitor.window_start();
+    let columns = wave_rect.width().max(1.0) as usize;
+    let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgb(100, 200, 255));
+    for column in 0..columns {
+        let from = start + span * column as u64 / columns as u64;
+        let to = start + span * (column as u64 + 1) / columns as u64;
+        let range = from.max(offset)..to.max(offset);
+        if range.is_empty() {
+            continue;
+        }
+        let (min, max) = samples
+            .range(
+                (range.start - offset) as usize..((range.end - offset) as usize).min(samples.len()),
+            )
+            .fold((0.0f32, 0.0f32), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+        let x = wave_rect.min.x + column as f32 + 0.5;
+        painter.line_segment([egui::pos2(x, y_at(max)), egui::pos2(x, y_at(min))], stroke);
+    }
+
+    // Silence timer
+    for &pos in monitor.silence_timeouts() {
+        let x = x_at(pos);
+        painter.line_segment(
+            [
+                egui::pos2(x, wave_rect.min.y),
+                egui::pos2(x, wave_rect.max.y),
+            ],
+            egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 60, 60)),
+        );
+    }
+
+    ui.add_space(10.0);
+
+    // Spectrogram (low frequencies at the bottom, -90..0 dBFS)
+    let (spec_rect, _) = ui.allocate_exact_size(egui::vec2(width, height), egui::Sense::hover());
+    let bins = audio_monitor::SPECTROGRAM_BINS;
+    let image_width = columns.clamp(1, 1024);
+    let mut image = egui::ColorImage::new([image_width, bins], egui::Color32::BLACK);
+    for (frame_start, levels) in monitor.columns() {
+        let x = ((frame_start.saturating_sub(start)) as f32 / span as f32 * image_width as f32)
+            as usize;
+        if x >= image_width {
+            continue;
+        }
+        for (bin, &db) in levels.iter().enumerate() {
+            let v = ((db + 90.0) / 90.0).clamp(0.0, 1.0);
+            image[(x, bins - 1 - bin)] = spectrogram_color(v);
+        }
+    }
+    match texture {
+        Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+        None => {
+            *texture = Some(ui.ctx().load_texture(
+                "spectrogram",
+                image,
+                egui::TextureOptions::LINEAR,
+            ))
+        }
+    }
+    if let Some(texture) = texture {
+        ui.painter().image(
+            texture.id(),
+            spec_rect,
+            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            egui::Color32::WHITE,
+        );
+    }
+}
+
+/// 暗い青 → 赤 → 黄色
+fn spectrogram_color(v: f32) -> egui::Color32 {
+    let r = (v * 2.0).min(1.0);
+    let g = (v * 2.0 - 1.0).max(0.0);
+    let b = (0.4 - v).max(0.0) * 1.5;
+    egui::Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+/// dBFS の入力メーター (RMS・ピーク・無音判定のしきい値・クリップ表示)
+fn draw_level_meter(ui: &mut egui::Ui, level: LevelSnapshot, silence_threshold: f32) {
+    let floor = level_meter::METER_FLOOR_DB;
+    let fraction = |db: f32| ((db - floor) / -floor).clamp(0.0, 1.0);
+    let bar_height = 10.0;
+    let (bar_rect, _) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), bar_height),
+        egui::Sense::hover(),
+    );
+    let x_at = |db: f32| bar_rect.min.x + bar_rect.width() * fraction(db);
+
+    // Draw background (dark gray)
+    ui.painter()
+        .rect_filled(bar_rect, 2.0, egui::Color32::from_rgb(50, 50, 50));
+
+    // RMS (filled, gray below the threshold, green above, red when clipping)
+    let threshold_db = level_meter::amplitude_to_dbfs(silence_threshold);
+    let color = if level.clipped {
+        egui::Color32::from_rgb(255, 0, 0)
+    } else if level.peak_db < threshold_db {
+        egui::Color32::from_rgb(150, 150, 150)
+    } else {
+        egui::Color32::from_rgb(0, 200, 0)
+    };
+    let rms_rect =
+        egui::Rect::from_min_max(bar_rect.min, egui::pos2(x_at(level.rms_db), bar_rect.max.y));
+    ui.painter().rect_filled(rms_rect, 2.0, color);
+
+    // Peak (thin line with falloff)
+    if level.peak_db > floor {
+        let x = x_at(level.peak_db);
+        ui.painter().line_segment(
+            [egui::pos2(x, bar_rect.min.y), egui::pos2(x, bar_rect.max.y)],
+            egui::Stroke::new(2.0, color),
+        );
+    }
+
+    // Silence threshold marker
+    let x = x_at(threshold_db);
+    ui.painter().line_segment(
+        [
+            egui::pos2(x, bar_rect.min.y - 2.0),
+            egui::pos2(x, bar_rect.max.y + 2.0),
+        ],
+        egui::Stroke::new(1.5, egui::Color32::from_rgb(255, 200, 0)),
+    );
+
+    // Draw border
+    ui.painter().rect_stroke(
+        bar_rect,
+        2.0,
+        egui::Stroke::new(1.0, egui::Color32::from_rgb(100, 100, 100)),
+    );
+
+    let db_text = |db: f32| {
+        if db > floor {
+            format!("{:.1}", db)
+        } else {
+            "-inf".to_string()
+        }
+    };
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "RMS {} dBFS  Peak {} dBFS  Threshold {:.1} dBFS",
+            db_text(level.rms_db),
+            db_text(level.peak_db),
+            threshold_db
+        ));
+        if level.clipped {
+            ui.colored_label(egui::Color32::from_rgb(255, 0, 0), "CLIP");
+        }
+    });
+}
+
+/// 設定画面で編集するためにコマンドの引数を空白区切りの文字列にする
+/// (空白を含む引数は引用符で囲む)
+fn tts_command_args(engine: &tts::TtsEngine) -> String {
+    match engine {
+        tts::TtsEngine::Command { args, .. } => tts::join_args(args),
+        _ => String::new(),
+    }
+}
+
+/// 録音を始めるホットキーごとの、その録音で使う翻訳言語か書き直しテンプレート
+#[derive(Debug, Clone)]
+enum RecordingHotkey {
+    Translation(TranslationLanguages),
+    Rewrite(String),
+}
+
+/// Register translation hotkeys and rewrite template hotkeys
+fn register_recording_hotkeys(
+    hotkey_manager: &GlobalHotKeyManager,
+    config: &Config,
+) -> Vec<(HotKey, RecordingHotkey)> {
+    let translations = config.translation_hotkeys.iter().map(|t| {
+        (
+            &t.hotkey,
+            "Translation",
+            RecordingHotkey::Translation(t.languages.clone()),
+        )
+    });
+    let rewrites = config.rewrite_templates.iter().map(|t| {
+        (
+            &t.hotkey,
+            "Rewrite",
+            RecordingHotkey::Rewrite(t.name.clone()),
+        )
+    });
+    translations
+        .chain(rewrites)
+        .filter_map(|(hotkey, label, options)| {
+            register_optional_hotkey(hotkey_manager, hotkey, label).map(|hotkey| (hotkey, options))
+        })
+        .collect()
+}
+
+/// 翻訳・書き直しなどの時間のかかる処理をバックグラウンドで始める
+fn spawn_job(
+    job: impl FnOnce() -> Result<String, String> + Send + 'static,
+) -> Receiver<Result<String, String>> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(job());
+    });
+    receiver
+}
+
+/// spawn_job の結果が届いていれば取り出し、受け取り口を空にする
+fn take_job_result(
+    receiver: &mut Option<Receiver<Result<String, String>>>,
+    label: &str,
+) -> Option<Result<String, String>> {
+    let result = match receiver.as_ref()?.try_recv() {
+        Ok(result) => result,
+        Err(TryRecvError::Empty) => return None,
+        Err(TryRecvError::Disconnected) => Err(format!("{} thread exited unexpectedly", label)),
+    };
+    *receiver = None;
+    Some(result)
+}
+
+/// Register the switch hotkey of each profile that has one
+fn register_profile_hotkeys(
+    hotkey_manager: &GlobalHotKeyManager,
+    config: &Config,
+) -> Vec<(HotKey, String)> {
+    config
+        .profiles
+        .iter()
+        .filter_map(|p| {
+            register_optional_hotkey(hotkey_manager, &p.switch_hotkey, "Profile")
+                .map(|hotkey| (hotkey, p.name.clone()))
+        })
+        .collect()
+}
+
+/// 空でなければホットキーを登録する (失敗時はログを出して None)
+fn register_optional_hotkey(
+    hotkey_manager: &GlobalHotKeyManager,
+    hotkey: &str,
+    label: &str,
+) -> Option<HotKey> {
+    if hotkey.trim().is_empty() {
+        return None;
+    }
+    let parsed = match Config::parse_hotkey_str(hotkey) {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("Failed to parse {} hotkey '{}': {}", label, hotkey, e);
+            return None;
+        }
+    };
+    match hotkey_manager.register(parsed) {
+        Ok(_) => {
+            println!("{} hotkey registered: {}", label, hotkey);
+            Some(parsed)
+        }
+        Err(e) => {
+            eprintln!("Failed to register {} hotkey: {}", label, e);
+            None
+        }
     }
 }
 
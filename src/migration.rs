@@ -0,0 +1,134 @@
+use crate::profile::DEFAULT_PROFILE_NAME;
+use serde_json::{Map, Value};
+
+/// 設定ファイルの形式のバージョン
+/// 形式を変えたら上げて、`MIGRATIONS` に変換手順を足す
+pub const CONFIG_VERSION: u32 = 1;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[i]` converts a version `i` config into version `i + 1`
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1];
+
+/// `config_version` が無いファイルは version 0 (0.4.0 以前の形式)
+pub fn version_of(value: &Value) -> u32 {
+    value
+        .get("config_version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(0)
+}
+
+/// Upgrade a raw config to `CONFIG_VERSION`, returning the version it started from
+pub fn migrate(value: &mut Value) -> Result<u32, String> {
+    if !value.is_object() {
+        return Err("Config is not a JSON object".to_string());
+    }
+    let from = version_of(value);
+    for (version, step) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        step(value).map_err(|e| {
+            format!(
+                "Migration from version {} to {} failed: {}",
+                version,
+                version + 1,
+                e
+            )
+        })?;
+        value["config_version"] = Value::from(version as u32 + 1);
+        eprintln!("Config migrated to version {}", version + 1);
+    }
+    Ok(from)
+}
+
+/// v0 の平らな設定のうち、v1 でプロファイルごとの値になったもの
+/// (変換は JSON のまま行い、今の `Config` の形に依存しない)
+const V0_PROFILE_KEYS: &[&str] = &[
+    "hotkey",
+    "input_device_name",
+    "silence_duration_secs",
+    "silence_threshold",
+    "clipboard_enabled",
+    "auto_input_enabled",
+    "auto_input_send_enter",
+    "vrchat_enabled",
+    "eliza_enabled",
+];
+
+/// v0 → v1: 必須だった無音判定の値を補い、平らな設定を "default" プロファイルにまとめる
+fn migrate_v0_to_v1(value: &mut Value) -> Result<(), String> {
+    let object = value.as_object_mut().ok_or("Config is not a JSON object")?;
+    // v0 のときの既定値
+    object
+        .entry("silence_duration_secs")
+        .or_insert(Value::from(2.0));
+    object
+        .entry("silence_threshold")
+        .or_insert(Value::from(0.01));
+
+    let has_profiles = object
+        .get("profiles")
+        .and_then(Value::as_array)
+        .is_some_and(|profiles| !profiles.is_empty());
+    if !has_profiles {
+        let mut profile = Map::new();
+        profile.insert("name".to_string(), Value::from(DEFAULT_PROFILE_NAME));
+        for key in V0_PROFILE_KEYS {
+            if let Some(v) = object.get(*key) {
+                profile.insert(key.to_string(), v.clone());
+            }
+        }
+        object.insert(
+            "profiles".to_string(),
+            Value::from(vec![Value::Object(profile)]),
+        );
+        object.insert(
+            "active_profile".to_string(),
+            Value::from(DEFAULT_PROFILE_NAME),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_migrations_cover_every_version() {
+        assert_eq!(MIGRATIONS.len(), CONFIG_VERSION as usize);
+    }
+
+    #[test]
+    fn test_migrate_is_noop_for_current_version() {
+        let mut value = serde_json::to_value(Config::default()).unwrap();
+        let before = value.clone();
+        assert_eq!(migrate(&mut value).unwrap(), CONFIG_VERSION);
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn test_migrate_v0_edits_json_only() {
+        let mut value = serde_json::json!({
+            "xai_api_key": "",
+            "hotkey": "Ctrl+Alt+F1",
+            "vrchat_enabled": true,
+            "unknown_key": [1, 2]
+        });
+        assert_eq!(migrate(&mut value).unwrap(), 0);
+        // 知らないキーもそのまま残る
+        assert_eq!(value["unknown_key"], serde_json::json!([1, 2]));
+        assert_eq!(value["silence_threshold"], Value::from(0.01));
+        assert_eq!(value["active_profile"], Value::from(DEFAULT_PROFILE_NAME));
+        let profile = &value["profiles"][0];
+        assert_eq!(profile["hotkey"], Value::from("Ctrl+Alt+F1"));
+        assert_eq!(profile["vrchat_enabled"], Value::from(true));
+        assert_eq!(profile["silence_duration_secs"], Value::from(2.0));
+        assert!(profile.get("stt_language").is_none());
+    }
+
+    #[test]
+    fn test_migrate_rejects_non_object() {
+        assert!(migrate(&mut Value::from(3)).is_err());
+    }
+}
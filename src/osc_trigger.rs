@@ -0,0 +1,475 @@
+use rosc::{decoder, OscMessage, OscPacket, OscType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const AVATAR_PARAMETER_PREFIX: &str = "/avatar/parameters/";
+
+/// ダブルトグルと見なす間隔の既定値 (秒)
+pub const DEFAULT_DOUBLE_TOGGLE_SECS: f32 = 1.0;
+
+/// アバターパラメータの変化に対する発火条件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OscCondition {
+    /// bool パラメータが false → true になった
+    BecameTrue,
+    /// bool パラメータが true → false になった
+    BecameFalse,
+    /// false → true の切り替えが window_secs 以内に起きた (ミュートのダブルタップ)
+    /// window_secs を省略すると Config の osc_double_toggle_secs を使う
+    DoubleToggle {
+        #[serde(default, deserialize_with = "deserialize_window_secs")]
+        window_secs: Option<f32>,
+    },
+    /// int/float パラメータが value と等しくなった
+    Equals { value: f32 },
+    /// float パラメータが threshold を下から上に超えた
+    CrossAbove { threshold: f32 },
+    /// float パラメータが threshold を上から下に下回った
+    CrossBelow { threshold: f32 },
+}
+
+/// 秒数として扱えない window_secs (負・無限大・大きすぎる値) は読み込み時に弾く
+fn deserialize_window_secs<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let secs = Option::<f32>::deserialize(deserializer)?;
+    if let Some(secs) = secs {
+        if secs < 0.0 || Duration::try_from_secs_f32(secs).is_err() {
+            return Err(serde::de::Error::custom(format!(
+                "invalid window_secs: {}",
+                secs
+            )));
+        }
+    }
+    Ok(secs)
+}
+
+/// 秒数を Duration にする (扱えない値なら fallback)
+fn window_duration(secs: f32, fallback: Duration) -> Duration {
+    Duration::try_from_secs_f32(secs.max(0.0))
+        .ok()
+        .filter(|_| secs.is_finite())
+        .unwrap_or(fallback)
+}
+
+/// ルールが発火したときの動作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OscAction {
+    StartRecording,
+    StopRecording,
+    ToggleRecording,
+    /// Eliza との会話履歴を消去する
+    ResetConversation,
+    /// プロファイルを切り替える (名前か、プロファイル一覧での 0 始まりの番号)
+    SelectProfile {
+        profile: ProfileSelector,
+    },
+}
+
+/// 切り替え先のプロファイルの指定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ProfileSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl ProfileSelector {
+    /// プロファイル名の一覧から切り替え先の名前を決める
+    pub fn resolve(&self, names: &[String]) -> Option<String> {
+        match self {
+            ProfileSelector::Index(index) => names.get(*index).cloned(),
+            ProfileSelector::Name(name) => names.iter().find(|n| *n == name).cloned(),
+        }
+    }
+}
+
+/// アバターパラメータ名のパターン (`*` でワイルドカード) と条件・動作の組
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OscTriggerRule {
+    pub parameter: String,
+    pub condition: OscCondition,
+    pub action: OscAction,
+}
+
+/// 従来の「MuteSelf を1秒以内に OFF→ON で録音開始」に相当するルール
+pub fn default_rules() -> Vec<OscTriggerRule> {
+    vec![OscTriggerRule {
+        parameter: "MuteSelf".to_string(),
+        condition: OscCondition::DoubleToggle { window_secs: None },
+        action: OscAction::StartRecording,
+    }]
+}
+
+/// 発火したルールの動作と、その時点でのアバターパラメータの値
+#[derive(Debug, Clone)]
+pub struct OscTriggerEvent {
+    pub action: OscAction,
+    pub parameters: HashMap<String, f32>,
+}
+
+impl OscTriggerEvent {
+    pub fn parameter_as_int(&self, name: &str) -> Option<i32> {
+        self.parameters.get(name).map(|v| *v as i32)
+    }
+}
+
+/// OSC メッセージを受け取り、ルールに従って動作を決める
+pub struct TriggerEngine {
+    rules: Vec<OscTriggerRule>,
+    double_toggle_window: Duration,
+    parameters: HashMap<String, f32>,
+    // パラメータが false になった時刻 (DoubleToggle 用)
+    released_at: HashMap<String, Instant>,
+}
+
+impl TriggerEngine {
+    pub fn new(rules: Vec<OscTriggerRule>, double_toggle_secs: f32) -> Self {
+        Self {
+            rules,
+            double_toggle_window: window_duration(
+                double_toggle_secs,
+                Duration::from_secs_f32(DEFAULT_DOUBLE_TOGGLE_SECS),
+            ),
+            parameters: HashMap::new(),
+            released_at: HashMap::new(),
+        }
+    }
+
+    /// Decode a raw OSC packet and feed every message in it to the engine
+    pub fn handle_packet(&mut self, buf: &[u8], now: Instant) -> Vec<OscTriggerEvent> {
+        match decoder::decode_udp(buf) {
+            Ok((_, packet)) => self.handle_osc_packet(packet, now),
+            Err(_) => vec![],
+        }
+    }
+
+    fn handle_osc_packet(&mut self, packet: OscPacket, now: Instant) -> Vec<OscTriggerEvent> {
+        match packet {
+            OscPacket::Message(msg) => self.handle_message(&msg, now),
+            OscPacket::Bundle(bundle) => bundle
+                .content
+                .into_iter()
+                .flat_map(|p| self.handle_osc_packet(p, now))
+                .collect(),
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: &OscMessage, now: Instant) -> Vec<OscTriggerEvent> {
+        let Some(name) = msg.addr.strip_prefix(AVATAR_PARAMETER_PREFIX) else {
+            return vec![];
+        };
+        let Some(value) = msg.args.first().and_then(parameter_value) else {
+            return vec![];
+        };
+
+        let previous = self.parameters.insert(name.to_string(), value);
+        let released_at = self.released_at.get(name).copied();
+        if value == 0.0 {
+            self.released_at.insert(name.to_string(), now);
+        } else {
+            self.released_at.remove(name);
+        }
+
+        let mut actions = Vec::new();
+        for rule in &self.rules {
+            if !matches_pattern(&rule.parameter, name) {
+                continue;
+            }
+            let fired = match rule.condition {
+                OscCondition::BecameTrue => value != 0.0 && previous.unwrap_or(0.0) == 0.0,
+                OscCondition::BecameFalse => value == 0.0 && previous.is_some_and(|p| p != 0.0),
+                OscCondition::DoubleToggle { window_secs } => {
+                    let window = window_secs
+                        .map(|s| window_duration(s, self.double_toggle_window))
+                        .unwrap_or(self.double_toggle_window);
+                    value != 0.0
+                        && released_at.is_some_and(|t| now.saturating_duration_since(t) <= window)
+                }
+                OscCondition::Equals { value: target } => {
+                    value == target && previous != Some(target)
+                }
+                OscCondition::CrossAbove { threshold } => {
+                    value > threshold && previous.is_some_and(|p| p <= threshold)
+                }
+                OscCondition::CrossBelow { threshold } => {
+                    value < threshold && previous.is_some_and(|p| p >= threshold)
+                }
+            };
+            if fired {
+                println!(
+                    "[OSC Trigger] {} = {} matched {:?} → {:?}",
+                    name, value, rule.condition, rule.action
+                );
+                actions.push(rule.action.clone());
+            }
+        }
+
+        actions
+            .into_iter()
+            .map(|action| OscTriggerEvent {
+                action,
+                parameters: self.parameters.clone(),
+            })
+            .collect()
+    }
+}
+
+fn parameter_value(arg: &OscType) -> Option<f32> {
+    match arg {
+        OscType::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        OscType::Int(i) => Some(*i as f32),
+        OscType::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// `*` を任意の文字列として扱う簡易 glob
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    if !name.starts_with(first) || !name.ends_with(last) || name.len() < first.len() + last.len() {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::encoder;
+
+    fn packet(name: &str, arg: OscType) -> Vec<u8> {
+        encoder::encode(&OscPacket::Message(OscMessage {
+            addr: format!("{}{}", AVATAR_PARAMETER_PREFIX, name),
+            args: vec![arg],
+        }))
+        .unwrap()
+    }
+
+    fn actions(events: Vec<OscTriggerEvent>) -> Vec<OscAction> {
+        events.into_iter().map(|e| e.action).collect()
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("MuteSelf", "MuteSelf"));
+        assert!(!matches_pattern("MuteSelf", "MuteSelfX"));
+        assert!(matches_pattern("Gesture*", "GestureRight"));
+        assert!(matches_pattern("*Right", "GestureRight"));
+        assert!(matches_pattern("winh/*/Rec", "winh/a/b/Rec"));
+        assert!(!matches_pattern("Gesture*", "Viseme"));
+        assert!(!matches_pattern("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn test_default_rule_double_toggle() {
+        let mut engine = TriggerEngine::new(default_rules(), 1.0);
+        let t0 = Instant::now();
+
+        assert!(engine
+            .handle_packet(&packet("MuteSelf", OscType::Bool(true)), t0)
+            .is_empty());
+        assert!(engine
+            .handle_packet(&packet("MuteSelf", OscType::Bool(false)), t0)
+            .is_empty());
+        assert_eq!(
+            actions(engine.handle_packet(
+                &packet("MuteSelf", OscType::Bool(true)),
+                t0 + Duration::from_millis(500)
+            )),
+            vec![OscAction::StartRecording]
+        );
+
+        // window を過ぎた切り替えは無視する
+        engine.handle_packet(
+            &packet("MuteSelf", OscType::Bool(false)),
+            t0 + Duration::from_secs(2),
+        );
+        assert!(engine
+            .handle_packet(
+                &packet("MuteSelf", OscType::Bool(true)),
+                t0 + Duration::from_secs(4)
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn test_double_toggle_window_override() {
+        let rules = vec![OscTriggerRule {
+            parameter: "MuteSelf".to_string(),
+            condition: OscCondition::DoubleToggle {
+                window_secs: Some(3.0),
+            },
+            action: OscAction::StartRecording,
+        }];
+        let mut engine = TriggerEngine::new(rules, 1.0);
+        let t0 = Instant::now();
+        engine.handle_packet(&packet("MuteSelf", OscType::Bool(false)), t0);
+        assert_eq!(
+            actions(engine.handle_packet(
+                &packet("MuteSelf", OscType::Bool(true)),
+                t0 + Duration::from_secs(2)
+            )),
+            vec![OscAction::StartRecording]
+        );
+    }
+
+    #[test]
+    fn test_bool_int_and_float_rules() {
+        let rules = vec![
+            OscTriggerRule {
+                parameter: "winh/Rec".to_string(),
+                condition: OscCondition::BecameTrue,
+                action: OscAction::StartRecording,
+            },
+            OscTriggerRule {
+                parameter: "Gesture*".to_string(),
+                condition: OscCondition::Equals { value: 3.0 },
+                action: OscAction::ToggleRecording,
+            },
+            OscTriggerRule {
+                parameter: "winh/Stop".to_string(),
+                condition: OscCondition::CrossAbove { threshold: 0.8 },
+                action: OscAction::StopRecording,
+            },
+        ];
+        let mut engine = TriggerEngine::new(rules, 1.0);
+        let now = Instant::now();
+
+        assert_eq!(
+            actions(engine.handle_packet(&packet("winh/Rec", OscType::Bool(true)), now)),
+            vec![OscAction::StartRecording]
+        );
+        // 同じ値が続いても再発火しない
+        assert!(engine
+            .handle_packet(&packet("winh/Rec", OscType::Bool(true)), now)
+            .is_empty());
+
+        assert_eq!(
+            actions(engine.handle_packet(&packet("GestureLeft", OscType::Int(3)), now)),
+            vec![OscAction::ToggleRecording]
+        );
+        assert!(engine
+            .handle_packet(&packet("GestureLeft", OscType::Int(3)), now)
+            .is_empty());
+
+        // 初回値だけではしきい値をまたいだとみなさない
+        assert!(engine
+            .handle_packet(&packet("winh/Stop", OscType::Float(0.9)), now)
+            .is_empty());
+        engine.handle_packet(&packet("winh/Stop", OscType::Float(0.2)), now);
+        assert_eq!(
+            actions(engine.handle_packet(&packet("winh/Stop", OscType::Float(0.85)), now)),
+            vec![OscAction::StopRecording]
+        );
+    }
+
+    #[test]
+    fn test_event_carries_parameter_snapshot() {
+        let mut engine = TriggerEngine::new(default_rules(), 1.0);
+        let now = Instant::now();
+        engine.handle_packet(&packet("GestureRight", OscType::Int(7)), now);
+        engine.handle_packet(&packet("MuteSelf", OscType::Bool(false)), now);
+        let events = engine.handle_packet(&packet("MuteSelf", OscType::Bool(true)), now);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].parameter_as_int("GestureRight"), Some(7));
+    }
+
+    #[test]
+    fn test_int_parameter_selects_profile() {
+        let json = r#"[
+            {"parameter": "winh/Profile", "condition": {"type": "equals", "value": 1}, "action": {"type": "select_profile", "profile": 1}},
+            {"parameter": "winh/Profile", "condition": {"type": "equals", "value": 2}, "action": {"type": "select_profile", "profile": "Work"}}
+        ]"#;
+        let rules: Vec<OscTriggerRule> = serde_json::from_str(json).unwrap();
+        let mut engine = TriggerEngine::new(rules, 1.0);
+        let now = Instant::now();
+        let names = vec![
+            "Default".to_string(),
+            "Game".to_string(),
+            "Work".to_string(),
+        ];
+
+        let fired = actions(engine.handle_packet(&packet("winh/Profile", OscType::Int(1)), now));
+        let [OscAction::SelectProfile { profile }] = fired.as_slice() else {
+            panic!("unexpected actions: {:?}", fired);
+        };
+        assert_eq!(profile.resolve(&names).as_deref(), Some("Game"));
+
+        let fired = actions(engine.handle_packet(&packet("winh/Profile", OscType::Int(2)), now));
+        let [OscAction::SelectProfile { profile }] = fired.as_slice() else {
+            panic!("unexpected actions: {:?}", fired);
+        };
+        assert_eq!(profile.resolve(&names).as_deref(), Some("Work"));
+
+        assert_eq!(ProfileSelector::Index(5).resolve(&names), None);
+        assert_eq!(
+            ProfileSelector::Name("Home".to_string()).resolve(&names),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_double_toggle_window() {
+        let now = Instant::now();
+        let double_toggle = |engine: &mut TriggerEngine| {
+            engine.handle_packet(&packet("MuteSelf", OscType::Bool(true)), now);
+            engine.handle_packet(&packet("MuteSelf", OscType::Bool(false)), now);
+            actions(engine.handle_packet(
+                &packet("MuteSelf", OscType::Bool(true)),
+                now + Duration::from_millis(500),
+            ))
+        };
+        // 扱えない既定の間隔は 1 秒として扱う
+        for secs in [f32::INFINITY, 1e20] {
+            let mut engine = TriggerEngine::new(default_rules(), secs);
+            assert_eq!(double_toggle(&mut engine), vec![OscAction::StartRecording]);
+        }
+
+        // ルールごとの間隔は読み込み時に弾く
+        for secs in ["1e39", "1e20", "-1"] {
+            let json = format!(
+                r#"[{{"parameter": "MuteSelf", "condition": {{"type": "double_toggle", "window_secs": {}}}, "action": {{"type": "start_recording"}}}}]"#,
+                secs
+            );
+            assert!(serde_json::from_str::<Vec<OscTriggerRule>>(&json).is_err());
+        }
+        let mut rules = default_rules();
+        rules[0].condition = OscCondition::DoubleToggle {
+            window_secs: Some(f32::INFINITY),
+        };
+        let mut engine = TriggerEngine::new(rules, 1.0);
+        assert_eq!(double_toggle(&mut engine), vec![OscAction::StartRecording]);
+    }
+
+    #[test]
+    fn test_rules_roundtrip_json() {
+        let json = r#"[
+            {"parameter": "MuteSelf", "condition": {"type": "double_toggle"}, "action": {"type": "start_recording"}},
+            {"parameter": "Volume", "condition": {"type": "cross_below", "threshold": 0.1}, "action": {"type": "stop_recording"}}
+        ]"#;
+        let rules: Vec<OscTriggerRule> = serde_json::from_str(json).unwrap();
+        assert_eq!(rules[0], default_rules()[0]);
+        assert_eq!(
+            rules[1].condition,
+            OscCondition::CrossBelow { threshold: 0.1 }
+        );
+    }
+}
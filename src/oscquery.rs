@@ -0,0 +1,333 @@
+use crate::osc_trigger::{OscCondition, OscTriggerRule};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const OSCJSON_SERVICE_TYPE: &str = "_oscjson._tcp.local.";
+const OSC_SERVICE_TYPE: &str = "_osc._udp.local.";
+
+/// トリガールールに関係なく受信する OSC アドレス
+/// (GestureRight は eliza モードの判定に使う)
+const FIXED_ENDPOINTS: &[(&str, &str)] = &[
+    ("/avatar/change", "s"),
+    ("/avatar/parameters/GestureRight", "i"),
+];
+
+/// winh が受信する OSC アドレスと型 (OSCQuery で VRChat に広告する)
+/// トリガールールのパラメータから組み立てる。`*` を含むパターンは個別に広告できないので省く
+pub fn advertised_endpoints(rules: &[OscTriggerRule]) -> Vec<(String, String)> {
+    let mut endpoints: Vec<(String, String)> = FIXED_ENDPOINTS
+        .iter()
+        .map(|(address, osc_type)| (address.to_string(), osc_type.to_string()))
+        .collect();
+    for rule in rules {
+        if rule.parameter.contains('*') {
+            continue;
+        }
+        let address = format!("/avatar/parameters/{}", rule.parameter);
+        if endpoints.iter().any(|(a, _)| *a == address) {
+            continue;
+        }
+        // 値ノードの下にはアドレスを置けない (逆も同じ) ので、先に広告したほうを残す
+        if let Some((existing, _)) = endpoints.iter().find(|(a, _)| {
+            a.starts_with(&format!("{}/", address)) || address.starts_with(&format!("{}/", a))
+        }) {
+            eprintln!(
+                "[OSCQuery] Not advertising {} because it conflicts with {}",
+                address, existing
+            );
+            continue;
+        }
+        let osc_type = match rule.condition {
+            OscCondition::BecameTrue
+            | OscCondition::BecameFalse
+            | OscCondition::DoubleToggle { .. } => "T",
+            OscCondition::Equals { .. } => "i",
+            OscCondition::CrossAbove { .. } | OscCondition::CrossBelow { .. } => "f",
+        };
+        endpoints.push((address, osc_type.to_string()));
+    }
+    endpoints
+}
+
+/// OSCQuery (mDNS + HTTP) で winh の OSC 受信ポートを VRChat に知らせるサービス
+/// drop すると mDNS の登録解除と HTTP サーバーの停止を行う
+pub struct OscQueryService {
+    daemon: ServiceDaemon,
+    fullnames: Vec<String>,
+    stop: Arc<AtomicBool>,
+}
+
+impl OscQueryService {
+    /// osc_port: VRChat からの OSC を受信している UDP ポート
+    /// endpoints: 広告する OSC アドレスと型 (advertised_endpoints)
+    pub fn start(osc_port: u16, endpoints: &[(String, String)]) -> Result<Self, String> {
+        let listener = TcpListener::bind("0.0.0.0:0")
+            .map_err(|e| format!("Failed to bind OSCQuery HTTP server: {}", e))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure OSCQuery HTTP server: {}", e))?;
+        let http_port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to get OSCQuery HTTP address: {}", e))?
+            .port();
+
+        let daemon =
+            ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS daemon: {}", e))?;
+        let instance = format!("winh-{}", osc_port);
+        let host_name = format!("{}.local.", instance);
+
+        let mut fullnames = Vec::new();
+        for (service_type, port) in [
+            (OSCJSON_SERVICE_TYPE, http_port),
+            (OSC_SERVICE_TYPE, osc_port),
+        ] {
+            let registered = ServiceInfo::new(
+                service_type,
+                &instance,
+                &host_name,
+                "",
+                port,
+                &[("txtvers", "1")][..],
+            )
+            .map_err(|e| format!("Failed to create mDNS service info: {}", e))
+            .and_then(|info| {
+                let info = info.enable_addr_auto();
+                let fullname = info.get_fullname().to_string();
+                daemon
+                    .register(info)
+                    .map(|_| fullname)
+                    .map_err(|e| format!("Failed to register mDNS service: {}", e))
+            });
+            match registered {
+                Ok(fullname) => fullnames.push(fullname),
+                Err(e) => {
+                    // 途中まで登録した分を取り消して mDNS のスレッドも止める
+                    for fullname in &fullnames {
+                        let _ = daemon.unregister(fullname);
+                    }
+                    let _ = daemon.shutdown();
+                    return Err(e);
+                }
+            }
+        }
+
+        // mDNS の登録に成功してから HTTP サーバーを動かす (失敗時にスレッドを残さない)
+        let tree = address_tree(endpoints);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = Arc::clone(&stop);
+        std::thread::spawn(move || run_http_server(listener, osc_port, tree, stop_clone));
+
+        println!(
+            "[OSCQuery] Advertising {} (OSC port {}, HTTP port {})",
+            instance, osc_port, http_port
+        );
+
+        Ok(Self {
+            daemon,
+            fullnames,
+            stop,
+        })
+    }
+}
+
+impl Drop for OscQueryService {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for fullname in &self.fullnames {
+            let _ = self.daemon.unregister(fullname);
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+fn run_http_server(listener: TcpListener, osc_port: u16, tree: Value, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = handle_connection(stream, osc_port, &tree) {
+                    eprintln!("[OSCQuery] HTTP error: {}", e);
+                }
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            Err(e) => {
+                eprintln!("[OSCQuery] accept error: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, osc_port: u16, tree: &Value) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(2)))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // 残りのヘッダーは読み捨てる
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = match respond(target, osc_port, tree) {
+        Some(body) => ("200 OK", body.to_string()),
+        None => ("404 Not Found", String::new()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// リクエストパスに対する OSCQuery の JSON を返す
+fn respond(target: &str, osc_port: u16, tree: &Value) -> Option<Value> {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+    if query == Some("HOST_INFO") {
+        return Some(host_info(osc_port));
+    }
+    find_node(tree, path).cloned()
+}
+
+fn host_info(osc_port: u16) -> Value {
+    json!({
+        "NAME": "winh",
+        "EXTENSIONS": {
+            "ACCESS": true,
+            "VALUE": true,
+        },
+        "OSC_IP": "127.0.0.1",
+        "OSC_PORT": osc_port,
+        "OSC_TRANSPORT": "UDP",
+    })
+}
+
+/// 広告する OSC アドレスから OSCQuery のノードツリーを組み立てる
+fn address_tree(endpoints: &[(String, String)]) -> Value {
+    let mut root = json!({ "FULL_PATH": "/", "ACCESS": 0, "CONTENTS": {} });
+    for (address, osc_type) in endpoints {
+        let mut node = &mut root;
+        let mut full_path = String::new();
+        for part in address.trim_start_matches('/').split('/') {
+            full_path.push('/');
+            full_path.push_str(part);
+            node = node["CONTENTS"]
+                .as_object_mut()
+                .expect("container node")
+                .entry(part)
+                .or_insert_with(|| json!({ "FULL_PATH": full_path, "ACCESS": 0, "CONTENTS": {} }));
+        }
+        // 末端は書き込み専用の値ノード
+        node["ACCESS"] = json!(2);
+        node["TYPE"] = json!(osc_type);
+        if let Some(obj) = node.as_object_mut() {
+            obj.remove("CONTENTS");
+        }
+    }
+    root
+}
+
+fn find_node<'a>(root: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut node = root;
+    for part in path.split('/').filter(|p| !p.is_empty()) {
+        node = node.get("CONTENTS")?.get(part)?;
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_tree() -> Value {
+        address_tree(&advertised_endpoints(&crate::osc_trigger::default_rules()))
+    }
+
+    #[test]
+    fn test_host_info() {
+        let info = respond("/?HOST_INFO", 9123, &default_tree()).unwrap();
+        assert_eq!(info["OSC_PORT"], 9123);
+        assert_eq!(info["OSC_TRANSPORT"], "UDP");
+    }
+
+    #[test]
+    fn test_address_tree() {
+        let tree = default_tree();
+        let root = respond("/", 9001, &tree).unwrap();
+        assert_eq!(root["CONTENTS"]["avatar"]["FULL_PATH"], "/avatar");
+
+        let mute = respond("/avatar/parameters/MuteSelf", 9001, &tree).unwrap();
+        assert_eq!(mute["FULL_PATH"], "/avatar/parameters/MuteSelf");
+        assert_eq!(mute["TYPE"], "T");
+        assert_eq!(mute["ACCESS"], 2);
+
+        assert!(respond("/chatbox/input", 9001, &tree).is_none());
+    }
+
+    #[test]
+    fn test_endpoints_follow_trigger_rules() {
+        let rules = vec![
+            OscTriggerRule {
+                parameter: "Voice".to_string(),
+                condition: OscCondition::CrossAbove { threshold: 0.5 },
+                action: crate::osc_trigger::OscAction::StartRecording,
+            },
+            OscTriggerRule {
+                parameter: "Gesture*".to_string(),
+                condition: OscCondition::Equals { value: 3.0 },
+                action: crate::osc_trigger::OscAction::StopRecording,
+            },
+        ];
+        let tree = address_tree(&advertised_endpoints(&rules));
+        let voice = respond("/avatar/parameters/Voice", 9001, &tree).unwrap();
+        assert_eq!(voice["TYPE"], "f");
+        // 既定のルールが無ければ MuteSelf は広告しない
+        assert!(respond("/avatar/parameters/MuteSelf", 9001, &tree).is_none());
+        assert!(respond("/avatar/parameters/GestureRight", 9001, &tree).is_some());
+    }
+
+    #[test]
+    fn test_conflicting_endpoints_are_skipped() {
+        let rule = |parameter: &str| OscTriggerRule {
+            parameter: parameter.to_string(),
+            condition: OscCondition::BecameTrue,
+            action: crate::osc_trigger::OscAction::StartRecording,
+        };
+
+        // 固定の GestureRight (値ノード) の下には置かない
+        let tree = address_tree(&advertised_endpoints(&[rule("GestureRight/x")]));
+        let gesture = respond("/avatar/parameters/GestureRight", 9001, &tree).unwrap();
+        assert_eq!(gesture["TYPE"], "i");
+        assert!(respond("/avatar/parameters/GestureRight/x", 9001, &tree).is_none());
+
+        // 子を持つアドレスを後から値ノードにしない
+        let tree = address_tree(&advertised_endpoints(&[rule("Foo/Bar"), rule("Foo")]));
+        let bar = respond("/avatar/parameters/Foo/Bar", 9001, &tree).unwrap();
+        assert_eq!(bar["TYPE"], "T");
+        assert!(respond("/avatar/parameters/Foo", 9001, &tree).unwrap()["TYPE"].is_null());
+
+        // 値ノードを先に広告したときは子のほうを省く
+        let tree = address_tree(&advertised_endpoints(&[rule("Foo"), rule("Foo/Bar")]));
+        assert_eq!(
+            respond("/avatar/parameters/Foo", 9001, &tree).unwrap()["TYPE"],
+            "T"
+        );
+        assert!(respond("/avatar/parameters/Foo/Bar", 9001, &tree).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::audio::{InputFormat, InputSource};
+use crate::config::Config;
+use crate::dictation::ContinuousSettings;
+use crate::dsp::DspSettings;
+use crate::translation::TranslationOutput;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// 用途ごとに切り替える設定のまとまり
+/// (入力デバイス・無音判定・言語・後処理・出力先・ホットキー)
+/// 選択中のプロファイルの値は Config の同名フィールドに展開して使う
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// このプロファイルに切り替えるホットキー (空なら無し)
+    pub switch_hotkey: String,
+    /// 録音開始のホットキー
+    pub hotkey: String,
+    pub input_source: InputSource,
+    pub input_device_name: Option<String>,
+    pub input_format: InputFormat,
+    pub silence_duration_secs: f32,
+    pub silence_threshold: f32,
+    pub dsp: DspSettings,
+    pub continuous: ContinuousSettings,
+    pub stt_language: String,
+    pub remove_punctuation: bool,
+    pub translation_enabled: bool,
+    pub translation_source_lang: String,
+    pub translation_target_lang: String,
+    pub translation_output: TranslationOutput,
+    pub rewrite_template: String,
+    pub clipboard_enabled: bool,
+    pub auto_input_enabled: bool,
+    pub auto_input_send_enter: bool,
+    pub vrchat_enabled: bool,
+    pub eliza_enabled: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::from_config(DEFAULT_PROFILE_NAME, &Config::default())
+    }
+}
+
+impl Profile {
+    /// Snapshot the profile-specific fields of a config
+    pub fn from_config(name: &str, config: &Config) -> Self {
+        Self {
+            name: name.to_string(),
+            switch_hotkey: String::new(),
+            hotkey: config.hotkey.clone(),
+            input_source: config.input_source,
+            input_device_name: config.input_device_name.clone(),
+            input_format: config.input_format.clone(),
+            silence_duration_secs: config.silence_duration_secs,
+            silence_threshold: config.silence_threshold,
+            dsp: config.dsp.clone(),
+            continuous: config.continuous.clone(),
+            stt_language: config.stt_language.clone(),
+            remove_punctuation: config.remove_punctuation,
+            translation_enabled: config.translation_enabled,
+            translation_source_lang: config.translation_source_lang.clone(),
+            translation_target_lang: config.translation_target_lang.clone(),
+            translation_output: config.translation_output,
+            rewrite_template: config.rewrite_template.clone(),
+            clipboard_enabled: config.clipboard_enabled,
+            auto_input_enabled: config.auto_input_enabled,
+            auto_input_send_enter: config.auto_input_send_enter,
+            vrchat_enabled: config.vrchat_enabled,
+            eliza_enabled: config.eliza_enabled,
+        }
+    }
+
+    /// Copy the profile into the config's working fields
+    pub fn apply_to(&self, config: &mut Config) {
+        config.hotkey = self.hotkey.clone();
+        config.input_source = self.input_source;
+        config.input_device_name = self.input_device_name.clone();
+        config.input_format = self.input_format.clone();
+        config.silence_duration_secs = self.silence_duration_secs;
+        config.silence_threshold = self.silence_threshold;
+        config.dsp = self.dsp.clone();
+        config.continuous = self.continuous.clone();
+        config.stt_language = self.stt_language.clone();
+        config.remove_punctuation = self.remove_punctuation;
+        config.translation_enabled = self.translation_enabled;
+        config.translation_source_lang = self.translation_source_lang.clone();
+        config.translation_target_lang = self.translation_target_lang.clone();
+        config.translation_output = self.translation_output;
+        config.rewrite_template = self.rewrite_template.clone();
+        config.clipboard_enabled = self.clipboard_enabled;
+        config.auto_input_enabled = self.auto_input_enabled;
+        config.auto_input_send_enter = self.auto_input_send_enter;
+        config.vrchat_enabled = self.vrchat_enabled;
+        config.eliza_enabled = self.eliza_enabled;
+    }
+}
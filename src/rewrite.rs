@@ -0,0 +1,113 @@
+use crate::assistant::Assistant;
+use crate::chat_completion::ChatCompletionClient;
+use crate::config::Config;
+use crate::conversation::ChatMessage;
+use serde::{Deserialize, Serialize};
+
+/// 文字起こしを書き直すプロンプトのテンプレート
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewriteTemplate {
+    pub name: String,
+    /// LLM に渡すシステムプロンプト
+    pub prompt: String,
+    /// このテンプレートで書き直しながら録音を始めるホットキー (空なら無し)
+    #[serde(default)]
+    pub hotkey: String,
+}
+
+impl RewriteTemplate {
+    fn new(name: &str, prompt: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            prompt: prompt.to_string(),
+            hotkey: String::new(),
+        }
+    }
+}
+
+pub fn default_templates() -> Vec<RewriteTemplate> {
+    vec![
+        RewriteTemplate::new(
+            "clean up",
+            "Clean up the following speech transcript. Remove fillers (えー, あの, um, uh), \
+             fix punctuation and split it into paragraphs where appropriate. \
+             Keep the language and meaning unchanged. Reply with the cleaned text only.",
+        ),
+        RewriteTemplate::new(
+            "formal email",
+            "Rewrite the following speech transcript as a polite, formal email body \
+             in the same language. Reply with the email text only.",
+        ),
+        RewriteTemplate::new(
+            "bullet list",
+            "Summarize the following speech transcript as a concise bullet list \
+             in the same language. Reply with the list only.",
+        ),
+        RewriteTemplate::new(
+            "commit message",
+            "Turn the following speech transcript into a git commit message: \
+             a short imperative subject line in English, a blank line, then a brief body. \
+             Reply with the commit message only.",
+        ),
+    ]
+}
+
+pub fn find_template<'a>(
+    templates: &'a [RewriteTemplate],
+    name: &str,
+) -> Option<&'a RewriteTemplate> {
+    templates.iter().find(|t| t.name == name)
+}
+
+/// Rewrite a transcript with the given template using the configured LLM
+pub fn rewrite(config: &Config, template: &RewriteTemplate, text: &str) -> Result<String, String> {
+    let client = ChatCompletionClient::new(
+        config.rewrite_url.clone(),
+        config.rewrite_api_key.clone(),
+        config.rewrite_model.clone(),
+        template.prompt.clone(),
+        0.3,
+        false,
+        Vec::new(),
+    );
+    let reply = client.stream_chat(&[ChatMessage::user(text)], &mut |_| {})?;
+    let rewritten = reply.content.trim().to_string();
+    println!("[Rewrite:{}] {} → {}", template.name, text, rewritten);
+    Ok(rewritten)
+}
+
+/// 書き直し結果のプレビュー (採用するまで出力しない)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewritePreview {
+    pub template: String,
+    pub original: String,
+    pub rewritten: String,
+    /// アシスタントに送る原文 (翻訳前の文字起こし)
+    pub assistant_text: String,
+    /// 出力時のステータスに付け足す前段の結果 ("translated" など)
+    pub status_parts: Vec<&'static str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_templates() {
+        let templates = default_templates();
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["clean up", "formal email", "bullet list", "commit message"]
+        );
+        assert!(find_template(&templates, "bullet list").is_some());
+        assert!(find_template(&templates, "haiku").is_none());
+    }
+
+    #[test]
+    fn test_template_json_without_hotkey() {
+        let template: RewriteTemplate =
+            serde_json::from_str(r#"{"name": "haiku", "prompt": "Write a haiku."}"#).unwrap();
+        assert_eq!(template.hotkey, "");
+    }
+}
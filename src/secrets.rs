@@ -0,0 +1,116 @@
+/// 資格情報ストアでのサービス名
+const SERVICE: &str = "winh";
+
+/// xAI API キーのエントリ名
+pub const XAI_API_KEY: &str = "xai_api_key";
+
+/// API キーなどの秘密情報の保存先
+pub trait SecretStore {
+    fn get(&self, name: &str) -> Result<Option<String>, String>;
+    fn set(&self, name: &str, value: &str) -> Result<(), String>;
+    fn delete(&self, name: &str) -> Result<(), String>;
+}
+
+/// OS の資格情報ストア (Windows: Credential Manager, Linux: Secret Service)
+pub struct KeyringStore;
+
+impl KeyringStore {
+    fn entry(name: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(SERVICE, name).map_err(|e| format!("Credential store error: {}", e))
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        match Self::entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to read from credential store: {}", e)),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        Self::entry(name)?
+            .set_password(value)
+            .map_err(|e| format!("Failed to write to credential store: {}", e))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to delete from credential store: {}", e)),
+        }
+    }
+}
+
+/// Write a secret only when it changed (an empty value deletes the entry)
+pub fn store_secret(store: &dyn SecretStore, name: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return store.delete(name);
+    }
+    if store.get(name)?.as_deref() == Some(value) {
+        return Ok(());
+    }
+    store.set(name, value)
+}
+
+/// テスト用のメモリ上のストア
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    values: std::cell::RefCell<std::collections::HashMap<String, String>>,
+    /// true なら資格情報ストアが使えない環境として振る舞う
+    unavailable: bool,
+}
+
+#[cfg(test)]
+impl MemoryStore {
+    pub fn unavailable() -> Self {
+        Self {
+            unavailable: true,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+impl SecretStore for MemoryStore {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        if self.unavailable {
+            return Err("unavailable".to_string());
+        }
+        Ok(self.values.borrow().get(name).cloned())
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        if self.unavailable {
+            return Err("unavailable".to_string());
+        }
+        self.values
+            .borrow_mut()
+            .insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        if self.unavailable {
+            return Err("unavailable".to_string());
+        }
+        self.values.borrow_mut().remove(name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_secret() {
+        let store = MemoryStore::default();
+        store_secret(&store, XAI_API_KEY, "xai-1").unwrap();
+        assert_eq!(store.get(XAI_API_KEY).unwrap().as_deref(), Some("xai-1"));
+        store_secret(&store, XAI_API_KEY, "").unwrap();
+        assert_eq!(store.get(XAI_API_KEY).unwrap(), None);
+    }
+}
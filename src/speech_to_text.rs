@@ -1,6 +1,8 @@
+use crate::audio::{MICROPHONE_LABEL, SYSTEM_AUDIO_LABEL};
 use crate::TranscriptionMessage;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio_tungstenite::{
     connect_async, tungstenite::client::IntoClientRequest, tungstenite::Message,
@@ -8,6 +10,9 @@ use tokio_tungstenite::{
 
 const XAI_STT_WS_URL: &str = "wss://api.x.ai/v1/stt";
 
+/// 区切りの合図の後、次の途中経過を待つ上限 (サーバーの途中経過は音声より遅れて届く)
+const SEGMENT_GRACE: Duration = Duration::from_millis(800);
+
 #[derive(Debug, Deserialize)]
 struct WsEvent {
     #[serde(rename = "type")]
@@ -17,6 +22,7 @@ struct WsEvent {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum SttError {
     NetworkError(String),
     ApiError(String),
@@ -37,25 +43,78 @@ impl std::error::Error for SttError {}
 
 pub struct SpeechToTextClient {
     api_key: String,
+    language: String,
+    remove_punctuation: bool,
 }
 
 impl SpeechToTextClient {
-    pub fn new(api_key: String) -> Self {
-        Self { api_key }
+    pub fn new(api_key: String, language: String, remove_punctuation: bool) -> Self {
+        Self {
+            api_key,
+            language,
+            remove_punctuation,
+        }
+    }
+
+    /// 確定したテキストの後処理
+    fn finalize(&self, text: &str) -> String {
+        if self.remove_punctuation {
+            remove_punctuation(text)
+        } else {
+            text.trim().to_string()
+        }
+    }
+
+    /// 連続ディクテーションの全区切りを 1 行ずつにまとめる
+    fn finalize_segments(&self, segmenter: &Segmenter) -> String {
+        segmenter
+            .segments
+            .iter()
+            .map(|text| self.finalize(text))
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
     pub async fn stream_transcribe(
+        &self,
+        sample_rate: u32,
+        audio_rx: mpsc::UnboundedReceiver<Vec<f32>>,
+        result_tx: mpsc::UnboundedSender<TranscriptionMessage>,
+    ) -> Result<(), SttError> {
+        self.stream_transcribe_segmented(sample_rate, audio_rx, None, result_tx)
+            .await
+    }
+
+    /// WebSocket の接続先 (言語はプロファイルで自由に書けるのでエンコードする)
+    fn stream_url(&self, sample_rate: u32) -> Result<String, SttError> {
+        let sample_rate = sample_rate.to_string();
+        reqwest::Url::parse_with_params(
+            XAI_STT_WS_URL,
+            &[
+                ("sample_rate", sample_rate.as_str()),
+                ("encoding", "pcm"),
+                ("interim_results", "true"),
+                ("language", self.language.trim()),
+                ("endpointing", "5000"),
+            ],
+        )
+        .map(String::from)
+        .map_err(|e| SttError::NetworkError(e.to_string()))
+    }
+
+    /// `segment_rx` があれば連続ディクテーションとして、合図が来るたびにそこまでのテキストを
+    /// `TranscriptionMessage::Segment` で送る (接続は録音が終わるまで開いたまま)
+    /// 最後に全区切りをまとめたテキストを `Success` で送る
+    pub async fn stream_transcribe_segmented(
         &self,
         sample_rate: u32,
         mut audio_rx: mpsc::UnboundedReceiver<Vec<f32>>,
+        mut segment_rx: Option<mpsc::UnboundedReceiver<()>>,
         result_tx: mpsc::UnboundedSender<TranscriptionMessage>,
     ) -> Result<(), SttError> {
-        let url = format!(
-            "{}?sample_rate={}&encoding=pcm&interim_results=true&language=ja&endpointing=5000",
-            XAI_STT_WS_URL, sample_rate
-        );
+        let url = self.stream_url(sample_rate)?;
 
-        println!("Connecting to WebSocket STT: {}", url);
+        eprintln!("Connecting to WebSocket STT: {}", url);
 
         let mut request = url
             .into_client_request()
@@ -80,7 +139,7 @@ impl SpeechToTextClient {
                     let event: WsEvent = serde_json::from_str(&text)
                         .map_err(|e| SttError::ParseError(e.to_string()))?;
                     if event.event_type == "transcript.created" {
-                        println!("WebSocket STT server ready");
+                        eprintln!("WebSocket STT server ready");
                         break;
                     }
                     if event.event_type == "error" {
@@ -102,22 +161,38 @@ impl SpeechToTextClient {
 
         let mut audio_done = false;
         let mut last_seen_text = String::new();
+        let segmented = segment_rx.is_some();
+        let mut segments_open = segmented;
+        let mut segmenter = Segmenter::default();
 
         loop {
             tokio::select! {
+                segment = next_segment(&mut segment_rx), if segments_open => {
+                    match segment {
+                        // 合図の時点の途中経過にはまだ最後の言葉が無いことがあるので、次の途中経過を待つ
+                        Some(()) => segmenter.request(Instant::now()),
+                        None => segments_open = false,
+                    }
+                }
+                _ = wait_until(segmenter.deadline()) => {
+                    // 待っても途中経過が届かなければ、それまでのテキストで区切る
+                    if let Some(text) = segmenter.commit_requested(&last_seen_text) {
+                        let _ = result_tx.send(TranscriptionMessage::Segment(self.finalize(&text)));
+                    }
+                }
                 chunk = audio_rx.recv(), if !audio_done => {
                     match chunk {
                         Some(samples) => {
                             let bytes = to_pcm16_bytes(&samples);
-                            ws_sink.send(Message::Binary(bytes.into()))
+                            ws_sink.send(Message::Binary(bytes))
                                 .await
                                 .map_err(|e| SttError::NetworkError(e.to_string()))?;
                         }
                         None => {
                             // chunk_senderがdrop = 録音停止 → audio.done送信
-                            println!("Audio done, sending audio.done to WebSocket");
+                            eprintln!("Audio done, sending audio.done to WebSocket");
                             ws_sink.send(Message::Text(
-                                r#"{"type":"audio.done"}"#.to_string().into()
+                                r#"{"type":"audio.done"}"#.to_string()
                             ))
                             .await
                             .map_err(|e| SttError::NetworkError(e.to_string()))?;
@@ -141,16 +216,40 @@ impl SpeechToTextClient {
                                         if !t.is_empty() {
                                             last_seen_text = t.clone();
                                         }
-                                        let _ = result_tx.send(TranscriptionMessage::Partial(t.clone()));
+                                        if let Some(text) = segmenter.commit_requested(&last_seen_text) {
+                                            let _ = result_tx.send(TranscriptionMessage::Segment(self.finalize(&text)));
+                                        }
+                                        // 連続ディクテーションでは、まだ区切っていない部分だけ見せる
+                                        let shown = if segmented {
+                                            segmenter.pending(&last_seen_text).to_string()
+                                        } else {
+                                            t.clone()
+                                        };
+                                        let _ = result_tx.send(TranscriptionMessage::Partial(shown));
                                     }
                                 }
                                 "transcript.done" => {
                                     let text = event.text
                                         .filter(|t| !t.is_empty())
                                         .unwrap_or_else(|| last_seen_text.clone());
-                                    println!("Transcript done: {}", text);
+                                    eprintln!("Transcript done: {}", text);
+                                    if segmented {
+                                        if let Some(text) = segmenter.done(&text) {
+                                            let _ = result_tx.send(TranscriptionMessage::Segment(self.finalize(&text)));
+                                        }
+                                        // 次の途中経過は新しい発話の全文になる
+                                        last_seen_text.clear();
+                                        // 話している途中でサーバーが区切っただけなら続ける
+                                        if !audio_done {
+                                            continue;
+                                        }
+                                        let _ = result_tx.send(TranscriptionMessage::Success(
+                                            self.finalize_segments(&segmenter)
+                                        ));
+                                        return Ok(());
+                                    }
                                     let _ = result_tx.send(TranscriptionMessage::Success(
-                                        remove_punctuation(&text)
+                                        self.finalize(&text)
                                     ));
                                     return Ok(());
                                 }
@@ -164,9 +263,15 @@ impl SpeechToTextClient {
                             }
                         }
                         None | Some(Err(_)) => {
+                            if segmented {
+                                if let Some(text) = segmenter.done(&last_seen_text) {
+                                    let _ = result_tx.send(TranscriptionMessage::Segment(self.finalize(&text)));
+                                }
+                                last_seen_text = self.finalize_segments(&segmenter);
+                            }
                             if !last_seen_text.is_empty() {
                                 let _ = result_tx.send(TranscriptionMessage::Success(
-                                    remove_punctuation(&last_seen_text)
+                                    self.finalize(&last_seen_text)
                                 ));
                             } else {
                                 let _ = result_tx.send(TranscriptionMessage::Error(
@@ -183,6 +288,242 @@ impl SpeechToTextClient {
     }
 }
 
+impl SpeechToTextClient {
+    /// 録音を文字起こしする
+    /// `system_rx` があれば再生音も別に文字起こしして "me" / "them" のラベルを付ける
+    pub async fn stream_transcribe_recording(
+        &self,
+        sample_rate: u32,
+        chunk_rx: mpsc::UnboundedReceiver<Vec<f32>>,
+        system_rx: Option<mpsc::UnboundedReceiver<Vec<f32>>>,
+        result_tx: mpsc::UnboundedSender<TranscriptionMessage>,
+    ) -> Result<(), SttError> {
+        match system_rx {
+            Some(system_rx) => {
+                let streams = vec![
+                    (MICROPHONE_LABEL.to_string(), chunk_rx),
+                    (SYSTEM_AUDIO_LABEL.to_string(), system_rx),
+                ];
+                self.stream_transcribe_labelled(sample_rate, streams, result_tx)
+                    .await
+            }
+            None => {
+                self.stream_transcribe(sample_rate, chunk_rx, result_tx)
+                    .await
+            }
+        }
+    }
+
+    /// 複数の音声をそれぞれ別の接続で文字起こしし、`label: text` の行にまとめて送る
+    /// (マイクと再生音を "me" / "them" に分けるときに使う)
+    /// 行は話し始めた順で、途中で相手が話したらそこで行を分ける
+    pub async fn stream_transcribe_labelled(
+        &self,
+        sample_rate: u32,
+        streams: Vec<(String, mpsc::UnboundedReceiver<Vec<f32>>)>,
+        result_tx: mpsc::UnboundedSender<TranscriptionMessage>,
+    ) -> Result<(), SttError> {
+        let (labels, receivers): (Vec<String>, Vec<_>) = streams.into_iter().unzip();
+        let (tagged_tx, mut tagged_rx) = mpsc::unbounded_channel::<(usize, TranscriptionMessage)>();
+
+        let tasks: Vec<_> = receivers
+            .into_iter()
+            .enumerate()
+            .map(|(index, audio_rx)| {
+                let tagged_tx = tagged_tx.clone();
+                async move {
+                    let (inner_tx, mut inner_rx) = mpsc::unbounded_channel();
+                    let forward = async {
+                        while let Some(message) = inner_rx.recv().await {
+                            let _ = tagged_tx.send((index, message));
+                        }
+                    };
+                    let (result, _) = tokio::join!(
+                        self.stream_transcribe(sample_rate, audio_rx, inner_tx),
+                        forward
+                    );
+                    if let Err(e) = result {
+                        let _ = tagged_tx.send((index, TranscriptionMessage::Error(e.to_string())));
+                    }
+                }
+            })
+            .collect();
+        drop(tagged_tx);
+
+        let collect = async {
+            let mut turns = Turns::new(labels.len());
+            let mut errors = Vec::new();
+            while let Some((index, message)) = tagged_rx.recv().await {
+                match message {
+                    TranscriptionMessage::Partial(text) => {
+                        turns.update(index, &text);
+                        let _ = result_tx.send(TranscriptionMessage::Partial(
+                            turns.lines(&labels, |t| t.trim().to_string()),
+                        ));
+                    }
+                    TranscriptionMessage::Success(text) => turns.finish(index, &text),
+                    TranscriptionMessage::Error(e) => {
+                        errors.push(format!("{}: {}", labels[index], e));
+                    }
+                    TranscriptionMessage::InProgress | TranscriptionMessage::Segment(_) => {}
+                }
+            }
+            // 片方だけ失敗したら、もう片方の結果は使う
+            let combined = turns.lines(&labels, |t| self.finalize(t));
+            let message = if combined.is_empty() && !errors.is_empty() {
+                TranscriptionMessage::Error(errors.join(" / "))
+            } else {
+                TranscriptionMessage::Success(combined)
+            };
+            let _ = result_tx.send(message);
+        };
+
+        tokio::join!(futures_util::future::join_all(tasks), collect);
+        Ok(())
+    }
+}
+
+/// `deadline` まで待つ (None なら待ち続ける)
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_segment(segment_rx: &mut Option<mpsc::UnboundedReceiver<()>>) -> Option<()> {
+    match segment_rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 連続ディクテーションで、途中経過のテキスト (transcript.done までの全文) を区切りごとに切り出す
+/// どこまで区切ったかは全文の中の位置で覚え、transcript.done で次の全文の先頭に戻す
+/// (先頭の文字の一致で比べると、同じ言葉で始まる次の発話が削れたり、言い直しで送り直したりする)
+#[derive(Debug, Default)]
+struct Segmenter {
+    /// Byte offset in the current running text up to which it has been committed
+    offset: usize,
+    /// When the segment signal arrived, while waiting for the next partial
+    requested: Option<Instant>,
+    segments: Vec<String>,
+}
+
+impl Segmenter {
+    /// まだ区切っていない部分 (前回の末尾に後から付いた句読点は除く)
+    fn pending<'a>(&self, latest: &'a str) -> &'a str {
+        tail(latest, self.offset).trim_start_matches(|c: char| {
+            c.is_whitespace() || c.is_ascii_punctuation() || "、。，．！？".contains(c)
+        })
+    }
+
+    /// 区切りの合図を受けた (次の途中経過か `deadline` で区切る)
+    fn request(&mut self, now: Instant) {
+        self.requested.get_or_insert(now);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.requested.map(|at| at + SEGMENT_GRACE)
+    }
+
+    /// 合図を待っていれば、ここまでで区切る
+    fn commit_requested(&mut self, latest: &str) -> Option<String> {
+        self.requested.take()?;
+        self.commit(latest)
+    }
+
+    /// サーバーが発話を確定した: 残りを区切り、次の途中経過は新しい全文として扱う
+    fn done(&mut self, text: &str) -> Option<String> {
+        self.requested = None;
+        let segment = self.commit(text);
+        self.offset = 0;
+        segment
+    }
+
+    /// 区切る (新しいテキストが無ければ None)
+    /// 区切った部分が言い直されて短くなっても、同じ部分をもう一度送らない
+    fn commit(&mut self, latest: &str) -> Option<String> {
+        let text = self.pending(latest).trim().to_string();
+        self.offset = self.offset.max(latest.len());
+        if text.is_empty() {
+            return None;
+        }
+        self.segments.push(text.clone());
+        Some(text)
+    }
+}
+
+/// 複数の話者の発言を話し始めた順に並べる
+/// 各接続のテキストは接続を開いてからの全文なので、他の話者が割り込んだところで切って次の発言にする
+#[derive(Debug)]
+struct Turns {
+    /// `(stream, text)` in the order they started
+    turns: Vec<(usize, String)>,
+    /// Per stream: `(turn, byte offset in its running text)` where its latest turn starts
+    latest: Vec<Option<(usize, usize)>>,
+}
+
+impl Turns {
+    fn new(streams: usize) -> Self {
+        Self {
+            turns: Vec::new(),
+            latest: vec![None; streams],
+        }
+    }
+
+    /// 途中経過 (その接続の全文) で発言を更新する
+    fn update(&mut self, stream: usize, text: &str) {
+        let current = self.turns.len().checked_sub(1);
+        match self.latest[stream] {
+            Some((turn, start)) if Some(turn) == current => {
+                self.turns[turn].1 = tail(text, start).to_string();
+            }
+            latest => {
+                // 前の発言の続きから、新しい発言にする
+                let start = latest.map_or(0, |(turn, start)| start + self.turns[turn].1.len());
+                let rest = tail(text, start);
+                if rest.trim().is_empty() {
+                    return;
+                }
+                self.turns.push((stream, rest.to_string()));
+                self.latest[stream] = Some((self.turns.len() - 1, start));
+            }
+        }
+    }
+
+    /// 確定したテキスト (後処理済みで途中経過と位置が合わないことがある)
+    fn finish(&mut self, stream: usize, text: &str) {
+        match self.latest[stream] {
+            None => self.update(stream, text),
+            // 割り込まれていなければ全文が 1 つの発言
+            Some((turn, 0)) => self.turns[turn].1 = text.to_string(),
+            // 分かれた発言は途中経過のまま使う
+            Some(_) => {}
+        }
+    }
+
+    /// 空でない発言を `label: text` の行にする
+    fn lines(&self, labels: &[String], finalize: impl Fn(&str) -> String) -> String {
+        self.turns
+            .iter()
+            .map(|(stream, text)| (&labels[*stream], finalize(text)))
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(label, text)| format!("{}: {}", label, text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// `start` バイト目から後 (文字の途中なら前の境目から)
+fn tail(text: &str, start: usize) -> &str {
+    let mut start = start.min(text.len());
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    &text[start..]
+}
+
 fn to_pcm16_bytes(samples: &[f32]) -> Vec<u8> {
     samples
         .iter()
@@ -209,6 +550,99 @@ fn remove_punctuation(text: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_segmenter_splits_running_transcript() {
+        let mut segmenter = Segmenter::default();
+        assert_eq!(segmenter.pending("今日は"), "今日は");
+        assert_eq!(
+            segmenter.commit("今日は晴れ"),
+            Some("今日は晴れ".to_string())
+        );
+        // 前回の末尾に句読点が付いても、新しい部分だけを取り出す
+        assert_eq!(segmenter.pending("今日は晴れ。明日は"), "明日は");
+        assert_eq!(
+            segmenter.commit("今日は晴れ。明日は雨"),
+            Some("明日は雨".to_string())
+        );
+        assert_eq!(segmenter.commit("今日は晴れ。明日は雨"), None);
+        // 区切った部分が言い直されても送り直さない
+        assert_eq!(segmenter.commit("今日は晴れ、明日は"), None);
+        assert_eq!(
+            segmenter.done("今日は晴れ。明日は雨です"),
+            Some("です".to_string())
+        );
+
+        // done の後は新しい全文なので、前と同じ言葉で始まっても削らない
+        assert_eq!(segmenter.commit("はい"), Some("はい".to_string()));
+        assert_eq!(segmenter.done("はい"), None);
+        assert_eq!(
+            segmenter.commit("はいそうです"),
+            Some("はいそうです".to_string())
+        );
+        assert_eq!(
+            segmenter.segments,
+            vec!["今日は晴れ", "明日は雨", "です", "はい", "はいそうです"]
+        );
+    }
+
+    #[test]
+    fn test_segmenter_waits_for_late_partial() {
+        let mut segmenter = Segmenter::default();
+        let start = Instant::now();
+        assert_eq!(segmenter.deadline(), None);
+        // 合図が無ければ途中経過では区切らない
+        assert_eq!(segmenter.commit_requested("今日は"), None);
+
+        // 無音で合図したときは、最後の言葉の途中経過がまだ届いていない
+        segmenter.request(start);
+        assert_eq!(segmenter.deadline(), Some(start + SEGMENT_GRACE));
+        segmenter.request(start + Duration::from_millis(100));
+        assert_eq!(segmenter.deadline(), Some(start + SEGMENT_GRACE));
+        assert_eq!(
+            segmenter.commit_requested("今日は晴れです"),
+            Some("今日は晴れです".to_string())
+        );
+        assert_eq!(segmenter.deadline(), None);
+        assert_eq!(segmenter.commit_requested("今日は晴れです。明日"), None);
+        assert_eq!(segmenter.pending("今日は晴れです。明日"), "明日");
+    }
+
+    #[test]
+    fn test_turns_interleave_in_speaking_order() {
+        let labels = vec!["me".to_string(), "them".to_string()];
+        let lines = |turns: &Turns| turns.lines(&labels, |t| t.trim().to_string());
+        let mut turns = Turns::new(2);
+        assert_eq!(lines(&turns), "");
+
+        turns.update(1, "hi");
+        turns.update(1, "hi there");
+        assert_eq!(lines(&turns), "them: hi there");
+        turns.update(0, "hello");
+        // 相手が話したので、続きは次の行になる
+        turns.update(1, "hi there how are you");
+        turns.update(0, "hello I'm fine");
+        assert_eq!(
+            lines(&turns),
+            "them: hi there\nme: hello\nthem: how are you\nme: I'm fine"
+        );
+
+        // 割り込まれていない接続は確定したテキストに置き換える
+        let mut turns = Turns::new(2);
+        turns.update(0, "えーと、はい");
+        turns.finish(0, "えーとはい");
+        turns.finish(1, "");
+        assert_eq!(lines(&turns), "me: えーとはい");
+    }
+
+    #[test]
+    fn test_stream_url_encodes_language() {
+        let client = SpeechToTextClient::new(String::new(), " ja&x=1 ".to_string(), false);
+        let url = client.stream_url(16000).unwrap();
+        assert!(url.starts_with(XAI_STT_WS_URL));
+        assert!(url.contains("sample_rate=16000"));
+        assert!(url.contains("language=ja%26x%3D1&"));
+    }
+
     #[test]
     fn test_remove_punctuation() {
         assert_eq!(remove_punctuation("こんにちは、世界。"), "こんにちは 世界");
@@ -0,0 +1,364 @@
+use crate::auto_input;
+use crate::vrchat::VRChatClient;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver};
+
+/// シェルコマンドの出力をモデルに返すときの最大文字数
+const MAX_TOOL_OUTPUT_CHARS: usize = 2000;
+
+/// アシスタントが呼び出せるツールの種類
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolKind {
+    /// アクティブウィンドウにテキストを入力する
+    TypeText,
+    /// VRChat chatbox にテキストを送る
+    SendChatbox,
+    /// VRChat で QvPen を呼び出す
+    CallQvpen,
+    /// クリップボードにテキストをコピーする
+    CopyToClipboard,
+    /// 設定したコマンドを実行する (引数は固定で、モデルからは指定できない)
+    ShellCommand {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// 設定ファイルで宣言するツール
+/// ここに書かれたものだけがモデルから呼び出せる
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    #[serde(flatten)]
+    pub kind: ToolKind,
+    /// 実行前にユーザーの確認を求めるか
+    /// 省略時はシェルコマンドだけ確認する (確認しないなら明示的に false を書く)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm: Option<bool>,
+}
+
+impl ToolDefinition {
+    pub fn needs_confirmation(&self) -> bool {
+        self.confirm
+            .unwrap_or(matches!(self.kind, ToolKind::ShellCommand { .. }))
+    }
+
+    fn takes_text(&self) -> bool {
+        matches!(
+            self.kind,
+            ToolKind::TypeText | ToolKind::SendChatbox | ToolKind::CopyToClipboard
+        )
+    }
+
+    /// 確認ダイアログなどに出す説明
+    pub fn describe_call(&self, arguments: &str) -> String {
+        match &self.kind {
+            ToolKind::ShellCommand { program, args } => {
+                format!("{}: {} {}", self.name, program, args.join(" "))
+                    .trim_end()
+                    .to_string()
+            }
+            _ if self.takes_text() => match parse_text_argument(arguments) {
+                Ok(text) => format!("{}: {}", self.name, text),
+                Err(_) => format!("{}: {}", self.name, arguments),
+            },
+            _ => self.name.clone(),
+        }
+    }
+}
+
+pub fn default_tools() -> Vec<ToolDefinition> {
+    vec![
+        ToolDefinition {
+            name: "type_text".to_string(),
+            description: "Type text into the currently focused window".to_string(),
+            kind: ToolKind::TypeText,
+            confirm: None,
+        },
+        ToolDefinition {
+            name: "send_chatbox".to_string(),
+            description: "Show text in the VRChat chatbox".to_string(),
+            kind: ToolKind::SendChatbox,
+            confirm: None,
+        },
+        ToolDefinition {
+            name: "call_qvpen".to_string(),
+            description: "Pick up the QvPen in VRChat".to_string(),
+            kind: ToolKind::CallQvpen,
+            confirm: None,
+        },
+        ToolDefinition {
+            name: "copy_to_clipboard".to_string(),
+            description: "Copy text to the clipboard".to_string(),
+            kind: ToolKind::CopyToClipboard,
+            confirm: None,
+        },
+    ]
+}
+
+/// OpenAI の `tools` パラメータ用の関数定義を作る
+pub fn tool_schemas(tools: &[ToolDefinition]) -> Vec<Value> {
+    tools
+        .iter()
+        .map(|tool| {
+            let parameters = if tool.takes_text() {
+                json!({
+                    "type": "object",
+                    "properties": { "text": { "type": "string" } },
+                    "required": ["text"],
+                })
+            } else {
+                json!({ "type": "object", "properties": {} })
+            };
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": parameters,
+                },
+            })
+        })
+        .collect()
+}
+
+fn parse_text_argument(arguments: &str) -> Result<String, String> {
+    let value: Value =
+        serde_json::from_str(arguments).map_err(|e| format!("Invalid tool arguments: {}", e))?;
+    value["text"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| "Missing 'text' argument".to_string())
+}
+
+/// Run a tool in the background; the result text for the model arrives on the receiver
+/// chatbox への送信は送信キューに積むだけなのでその場で済ませ、
+/// それ以外 (入力・コマンドなど時間のかかるもの) はワーカースレッドで実行する
+pub fn spawn_execute(
+    tool: &ToolDefinition,
+    arguments: &str,
+    vrchat_client: &VRChatClient,
+) -> Receiver<Result<String, String>> {
+    let (sender, receiver) = channel();
+    if tool.kind == ToolKind::SendChatbox {
+        let result = parse_text_argument(arguments).and_then(|text| {
+            vrchat_client
+                .send_message(&text)
+                .map(|_| "sent".to_string())
+                .map_err(|e| e.to_string())
+        });
+        let _ = sender.send(result);
+        return receiver;
+    }
+    let kind = tool.kind.clone();
+    let arguments = arguments.to_string();
+    std::thread::spawn(move || {
+        let _ = sender.send(execute_blocking(&kind, &arguments));
+    });
+    receiver
+}
+
+/// Run a tool on the current thread and return the result text for the model
+fn execute_blocking(kind: &ToolKind, arguments: &str) -> Result<String, String> {
+    match kind {
+        ToolKind::TypeText => {
+            let text = parse_text_argument(arguments)?;
+            auto_input::type_text_sync(&text)?;
+            Ok("typed".to_string())
+        }
+        ToolKind::SendChatbox => Err("send_chatbox needs the VRChat client".to_string()),
+        ToolKind::CallQvpen => {
+            auto_input::call_qvpen_sync()?;
+            Ok("called".to_string())
+        }
+        ToolKind::CopyToClipboard => {
+            let text = parse_text_argument(arguments)?;
+            arboard::Clipboard::new()
+                .and_then(|mut clipboard| clipboard.set_text(text))
+                .map_err(|e| format!("Failed to copy: {}", e))?;
+            Ok("copied".to_string())
+        }
+        ToolKind::ShellCommand { program, args } => {
+            let output = Command::new(program)
+                .args(args)
+                .output()
+                .map_err(|e| format!("Failed to run '{}': {}", program, e))?;
+            let stdout = truncate_chars(&String::from_utf8_lossy(&output.stdout));
+            if output.status.success() {
+                Ok(stdout)
+            } else {
+                Err(format!(
+                    "exited with {}: {}",
+                    output.status,
+                    truncate_chars(&String::from_utf8_lossy(&output.stderr))
+                ))
+            }
+        }
+    }
+}
+
+fn truncate_chars(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(MAX_TOOL_OUTPUT_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+/// ツール呼び出しの履歴 (tool_history.jsonl に1行ずつ追記する)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    /// UNIX 時刻 (秒)
+    pub timestamp: u64,
+    pub name: String,
+    pub arguments: String,
+    pub ok: bool,
+    pub result: String,
+}
+
+impl ToolInvocation {
+    pub fn new(name: &str, arguments: &str, result: &Result<String, String>) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let (ok, result) = match result {
+            Ok(output) => (true, output.clone()),
+            Err(e) => (false, e.clone()),
+        };
+        Self {
+            timestamp,
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+            ok,
+            result,
+        }
+    }
+}
+
+/// Get the default tool history file path
+pub fn history_path() -> Result<PathBuf, String> {
+    let config_dir = dirs::config_dir().ok_or("Failed to get config directory")?;
+    Ok(config_dir.join("winh").join("tool_history.jsonl"))
+}
+
+/// Append an invocation to the tool history file
+pub fn log_invocation(invocation: &ToolInvocation) -> Result<(), String> {
+    let path = history_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create history directory: {}", e))?;
+    }
+    let line = serde_json::to_string(invocation)
+        .map_err(|e| format!("Failed to serialize tool invocation: {}", e))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open tool history: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write tool history: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_definitions_json() {
+        let tools: Vec<ToolDefinition> = serde_json::from_str(
+            r#"[
+                {"name": "type_text", "description": "Type", "type": "type_text"},
+                {"name": "open_notepad", "description": "Open notepad", "type": "shell_command",
+                 "program": "notepad.exe", "confirm": true}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(tools[0].kind, ToolKind::TypeText);
+        assert!(!tools[0].needs_confirmation());
+        assert_eq!(
+            tools[1].kind,
+            ToolKind::ShellCommand {
+                program: "notepad.exe".to_string(),
+                args: vec![],
+            }
+        );
+        assert!(tools[1].needs_confirmation());
+        assert_eq!(tools[1].describe_call("{}"), "open_notepad: notepad.exe");
+    }
+
+    #[test]
+    fn test_shell_commands_confirm_unless_opted_out() {
+        let tools: Vec<ToolDefinition> = serde_json::from_str(
+            r#"[
+                {"name": "ls", "description": "List", "type": "shell_command", "program": "ls"},
+                {"name": "date", "description": "Date", "type": "shell_command", "program": "date",
+                 "confirm": false},
+                {"name": "copy", "description": "Copy", "type": "copy_to_clipboard", "confirm": true}
+            ]"#,
+        )
+        .unwrap();
+        assert!(tools[0].needs_confirmation());
+        assert!(!tools[1].needs_confirmation());
+        assert!(tools[2].needs_confirmation());
+        // 省略した confirm は書き出さない (既定の扱いが後から変わっても追従する)
+        assert!(serde_json::to_value(&tools[0])
+            .unwrap()
+            .get("confirm")
+            .is_none());
+    }
+
+    #[test]
+    fn test_shell_command_runs_on_worker_thread() {
+        let tool = ToolDefinition {
+            name: "missing".to_string(),
+            description: String::new(),
+            kind: ToolKind::ShellCommand {
+                program: "winh-no-such-program".to_string(),
+                args: vec![],
+            },
+            confirm: Some(false),
+        };
+        let receiver = spawn_execute(&tool, "{}", &VRChatClient::new());
+        let result = receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap();
+        assert!(result.unwrap_err().starts_with("Failed to run"));
+    }
+
+    #[test]
+    fn test_tool_schemas() {
+        let schemas = tool_schemas(&default_tools());
+        assert_eq!(schemas.len(), 4);
+        assert_eq!(schemas[0]["function"]["name"], "type_text");
+        assert_eq!(schemas[0]["function"]["parameters"]["required"][0], "text");
+        assert_eq!(schemas[2]["function"]["name"], "call_qvpen");
+        assert!(schemas[2]["function"]["parameters"]["required"].is_null());
+    }
+
+    #[test]
+    fn test_text_argument() {
+        assert_eq!(
+            parse_text_argument(r#"{"text": "こんにちは"}"#).unwrap(),
+            "こんにちは"
+        );
+        assert!(parse_text_argument("{}").is_err());
+        assert!(parse_text_argument("not json").is_err());
+    }
+
+    #[test]
+    fn test_truncate_tool_output() {
+        assert_eq!(truncate_chars("  ok\n"), "ok");
+        let long = "あ".repeat(MAX_TOOL_OUTPUT_CHARS + 10);
+        let truncated = truncate_chars(&long);
+        assert_eq!(truncated.chars().count(), MAX_TOOL_OUTPUT_CHARS + 1);
+        assert!(truncated.ends_with('…'));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::assistant::Assistant;
+use crate::chat_completion::ChatCompletionClient;
+use crate::config::Config;
+use crate::conversation::ChatMessage;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// 翻訳に使うサービス
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranslationBackend {
+    /// OpenAI 互換の `/v1/chat/completions` に翻訳を頼む
+    ChatCompletion,
+    /// LibreTranslate 互換の `/translate`
+    LibreTranslate,
+}
+
+impl TranslationBackend {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TranslationBackend::ChatCompletion => "OpenAI-compatible LLM",
+            TranslationBackend::LibreTranslate => "LibreTranslate",
+        }
+    }
+}
+
+/// 翻訳結果をどう出力するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranslationOutput {
+    /// 訳文だけ
+    TranslationOnly,
+    /// "原文 / 訳文"
+    Both,
+}
+
+impl TranslationOutput {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TranslationOutput::TranslationOnly => "Translation only",
+            TranslationOutput::Both => "Original / Translation",
+        }
+    }
+}
+
+/// 翻訳元と翻訳先の言語 (ja, en などの言語コード)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranslationLanguages {
+    pub source_lang: String,
+    pub target_lang: String,
+}
+
+/// 押すとその言語の組で翻訳しながら録音を始めるホットキー
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranslationHotkey {
+    pub hotkey: String,
+    #[serde(flatten)]
+    pub languages: TranslationLanguages,
+}
+
+pub struct Translator {
+    backend: TranslationBackend,
+    url: String,
+    model: String,
+    api_key: String,
+}
+
+impl Translator {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            backend: config.translation_backend,
+            url: config.translation_url.clone(),
+            model: config.translation_model.clone(),
+            api_key: config.translation_api_key.clone(),
+        }
+    }
+
+    /// Translate text between the given languages
+    pub fn translate(
+        &self,
+        text: &str,
+        languages: &TranslationLanguages,
+    ) -> Result<String, String> {
+        let translated = match self.backend {
+            TranslationBackend::ChatCompletion => {
+                let client = ChatCompletionClient::new(
+                    self.url.clone(),
+                    self.api_key.clone(),
+                    self.model.clone(),
+                    system_prompt(languages),
+                    0.2,
+                    false,
+                    Vec::new(),
+                );
+                client
+                    .stream_chat(&[ChatMessage::user(text)], &mut |_| {})?
+                    .content
+            }
+            TranslationBackend::LibreTranslate => {
+                self.translate_with_libretranslate(text, languages)?
+            }
+        };
+        let translated = translated.trim().to_string();
+        println!("[Translator] {} → {}", text, translated);
+        Ok(translated)
+    }
+
+    fn translate_with_libretranslate(
+        &self,
+        text: &str,
+        languages: &TranslationLanguages,
+    ) -> Result<String, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+        let endpoint = format!("{}/translate", self.url.trim_end_matches('/'));
+        let body: Value = client
+            .post(&endpoint)
+            .json(&libretranslate_request(text, languages, &self.api_key))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to send to translation service: {}", e))?
+            .json()
+            .map_err(|e| format!("Failed to parse translation response: {}", e))?;
+        body["translatedText"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("Translation response has no translatedText: {}", body))
+    }
+}
+
+fn system_prompt(languages: &TranslationLanguages) -> String {
+    format!(
+        "Translate the user's message from {} to {}. \
+         Reply with the translation only, without quotes or explanations.",
+        languages.source_lang, languages.target_lang
+    )
+}
+
+fn libretranslate_request(text: &str, languages: &TranslationLanguages, api_key: &str) -> Value {
+    let mut request = json!({
+        "q": text,
+        "source": languages.source_lang,
+        "target": languages.target_lang,
+        "format": "text",
+    });
+    if !api_key.is_empty() {
+        request["api_key"] = json!(api_key);
+    }
+    request
+}
+
+/// 出力設定に合わせて原文と訳文を組み立てる
+pub fn format_output(original: &str, translation: &str, output: TranslationOutput) -> String {
+    match output {
+        TranslationOutput::TranslationOnly => translation.to_string(),
+        TranslationOutput::Both => format!("{} / {}", original, translation),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ja_en() -> TranslationLanguages {
+        TranslationLanguages {
+            source_lang: "ja".to_string(),
+            target_lang: "en".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_output() {
+        assert_eq!(
+            format_output("こんにちは", "Hello", TranslationOutput::TranslationOnly),
+            "Hello"
+        );
+        assert_eq!(
+            format_output("こんにちは", "Hello", TranslationOutput::Both),
+            "こんにちは / Hello"
+        );
+    }
+
+    #[test]
+    fn test_requests_mention_languages() {
+        let prompt = system_prompt(&ja_en());
+        assert!(prompt.contains("from ja to en"));
+
+        let request = libretranslate_request("こんにちは", &ja_en(), "");
+        assert_eq!(request["source"], "ja");
+        assert_eq!(request["target"], "en");
+        assert!(request.get("api_key").is_none());
+        assert_eq!(
+            libretranslate_request("こんにちは", &ja_en(), "key")["api_key"],
+            "key"
+        );
+    }
+
+    #[test]
+    fn test_translation_hotkey_json() {
+        let hotkey: TranslationHotkey = serde_json::from_str(
+            r#"{"hotkey": "Ctrl+Shift+E", "source_lang": "ja", "target_lang": "en"}"#,
+        )
+        .unwrap();
+        assert_eq!(hotkey.hotkey, "Ctrl+Shift+E");
+        assert_eq!(hotkey.languages, ja_en());
+    }
+}
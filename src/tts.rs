@@ -0,0 +1,518 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 音声合成エンジンの設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TtsEngine {
+    /// ローカルコマンド (piper など)
+    /// テキストを標準入力に渡し、WAV を標準出力から読む
+    /// args に `{output}` があれば、標準出力の代わりにその一時ファイルから WAV を読む
+    Command { program: String, args: Vec<String> },
+    /// VOICEVOX 互換の HTTP エンジン (`/audio_query` → `/synthesis`)
+    Voicevox { url: String, speaker: u32 },
+}
+
+impl TtsEngine {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TtsEngine::Command { .. } => "Command (piper etc.)",
+            TtsEngine::Voicevox { .. } => "VOICEVOX",
+        }
+    }
+
+    /// Synthesize text into WAV bytes
+    pub fn synthesize(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            TtsEngine::Command { program, args } => synthesize_with_command(program, args, text),
+            TtsEngine::Voicevox { url, speaker } => synthesize_with_voicevox(url, *speaker, text),
+        }
+    }
+}
+
+pub fn default_engine() -> TtsEngine {
+    TtsEngine::Voicevox {
+        url: "http://127.0.0.1:50021".to_string(),
+        speaker: 1,
+    }
+}
+
+fn synthesize_with_command(program: &str, args: &[String], text: &str) -> Result<Vec<u8>, String> {
+    let output_path = std::env::temp_dir().join(format!("winh-tts-{}.wav", std::process::id()));
+    let (args, uses_file) = expand_args(args, &output_path.to_string_lossy());
+
+    let mut child = Command::new(program)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start TTS command '{}': {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Failed to write to TTS command: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to run TTS command: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "TTS command exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    if uses_file {
+        let wav = std::fs::read(&output_path)
+            .map_err(|e| format!("Failed to read TTS output file: {}", e))?;
+        let _ = std::fs::remove_file(&output_path);
+        Ok(wav)
+    } else {
+        Ok(output.stdout)
+    }
+}
+
+/// `{output}` を出力ファイルのパスに置き換える (置き換えたかどうかも返す)
+fn expand_args(args: &[String], output_path: &str) -> (Vec<String>, bool) {
+    let uses_file = args.iter().any(|a| a.contains("{output}"));
+    let args = args
+        .iter()
+        .map(|a| a.replace("{output}", output_path))
+        .collect();
+    (args, uses_file)
+}
+
+fn synthesize_with_voicevox(url: &str, speaker: u32, text: &str) -> Result<Vec<u8>, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap_or_default();
+    let base = url.trim_end_matches('/');
+    let speaker = speaker.to_string();
+
+    let query: serde_json::Value = client
+        .post(format!("{}/audio_query", base))
+        .query(&[("text", text), ("speaker", speaker.as_str())])
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("VOICEVOX audio_query failed: {}", e))?
+        .json()
+        .map_err(|e| format!("Failed to parse VOICEVOX audio_query: {}", e))?;
+
+    let wav = client
+        .post(format!("{}/synthesis", base))
+        .query(&[("speaker", speaker.as_str())])
+        .json(&query)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("VOICEVOX synthesis failed: {}", e))?
+        .bytes()
+        .map_err(|e| format!("Failed to read VOICEVOX synthesis: {}", e))?;
+    Ok(wav.to_vec())
+}
+
+/// モノラルの音声データ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pcm {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+/// Decode WAV bytes into mono f32 samples
+pub fn decode_wav(bytes: &[u8]) -> Result<Pcm, String> {
+    let mut reader =
+        hound::WavReader::new(Cursor::new(bytes)).map_err(|e| format!("Invalid WAV: {}", e))?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Failed to read WAV samples: {}", e))?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Failed to read WAV samples: {}", e))?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok(Pcm {
+        samples,
+        sample_rate: spec.sample_rate,
+    })
+}
+
+/// 線形補間でサンプリングレートを変換する
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from_rate as f64 / to_rate as f64;
+    let len = (samples.len() as f64 / ratio).round() as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            let index = pos.floor() as usize;
+            let frac = (pos - index as f64) as f32;
+            let a = samples[index.min(samples.len() - 1)];
+            let b = samples[(index + 1).min(samples.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Get list of available output devices
+pub fn get_output_devices() -> Result<Vec<String>, String> {
+    let host = cpal::default_host();
+    let devices = host
+        .output_devices()
+        .map_err(|e| format!("Failed to get output devices: {}", e))?;
+    Ok(devices.filter_map(|d| d.name().ok()).collect())
+}
+
+struct SpeakRequest {
+    text: String,
+    generation: u64,
+}
+
+/// 返答を順に読み上げるプレイヤー
+/// 読み上げはバックグラウンドのキューで1件ずつ行い、stop() で再生中のものも含めて中断する
+pub struct TtsPlayer {
+    queue: Sender<SpeakRequest>,
+    generation: Arc<AtomicU64>,
+    speaking: Arc<AtomicBool>,
+    engine: Arc<Mutex<TtsEngine>>,
+    output_device: Arc<Mutex<Option<String>>>,
+}
+
+impl TtsPlayer {
+    pub fn new(engine: TtsEngine, output_device: Option<String>) -> Self {
+        let (queue, receiver) = channel::<SpeakRequest>();
+        let player = Self {
+            queue,
+            generation: Arc::new(AtomicU64::new(0)),
+            speaking: Arc::new(AtomicBool::new(false)),
+            engine: Arc::new(Mutex::new(engine)),
+            output_device: Arc::new(Mutex::new(output_device)),
+        };
+        let generation = Arc::clone(&player.generation);
+        let speaking = Arc::clone(&player.speaking);
+        let engine = Arc::clone(&player.engine);
+        let output_device = Arc::clone(&player.output_device);
+        std::thread::spawn(move || {
+            run_tts_queue(receiver, generation, speaking, engine, output_device)
+        });
+        player
+    }
+
+    /// 設定変更を反映する (次の読み上げから有効)
+    pub fn configure(&self, engine: TtsEngine, output_device: Option<String>) {
+        *self.engine.lock().unwrap() = engine;
+        *self.output_device.lock().unwrap() = output_device;
+    }
+
+    /// Queue text to be spoken
+    pub fn speak(&self, text: &str) {
+        let request = SpeakRequest {
+            text: text.to_string(),
+            generation: self.generation.load(Ordering::SeqCst),
+        };
+        if self.queue.send(request).is_err() {
+            eprintln!("[TTS] Queue is closed");
+        }
+    }
+
+    /// Stop the current playback and drop everything queued
+    pub fn stop(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.speaking.load(Ordering::SeqCst)
+    }
+}
+
+fn run_tts_queue(
+    receiver: Receiver<SpeakRequest>,
+    generation: Arc<AtomicU64>,
+    speaking: Arc<AtomicBool>,
+    engine: Arc<Mutex<TtsEngine>>,
+    output_device: Arc<Mutex<Option<String>>>,
+) {
+    while let Ok(request) = receiver.recv() {
+        let cancelled = || generation.load(Ordering::SeqCst) != request.generation;
+        if cancelled() {
+            continue;
+        }
+        speaking.store(true, Ordering::SeqCst);
+        let engine = engine.lock().unwrap().clone();
+        let device = output_device.lock().unwrap().clone();
+        let result = engine
+            .synthesize(&request.text)
+            .and_then(|wav| decode_wav(&wav))
+            .and_then(|pcm| {
+                if cancelled() {
+                    return Ok(());
+                }
+                play(&pcm, device.as_deref(), &cancelled)
+            });
+        if let Err(e) = result {
+            eprintln!("[TTS] {}", e);
+        }
+        speaking.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 出力デバイスで再生し、終わるか cancelled() が true になるまで待つ
+pub fn play(
+    pcm: &Pcm,
+    device_name: Option<&str>,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<(), String> {
+    let host = cpal::default_host();
+    let device = if let Some(name) = device_name {
+        host.output_devices()
+            .map_err(|e| format!("Failed to get output devices: {}", e))?
+            .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+            .ok_or(format!("Output device '{}' not found", name))?
+    } else {
+        host.default_output_device()
+            .ok_or("No output device available")?
+    };
+    let config = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get default output config: {}", e))?;
+    let stream_config = config.config();
+    let samples = Arc::new(resample(
+        &pcm.samples,
+        pcm.sample_rate,
+        stream_config.sample_rate.0,
+    ));
+    let total = samples.len();
+    let playback = Playback {
+        samples,
+        position: Arc::new(AtomicUsize::new(0)),
+        block_frames: Arc::new(AtomicUsize::new(0)),
+    };
+
+    // 仮想ケーブルなど f32 以外のデバイスには、その形式に変換して渡す
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build_output_stream::<f32>(&device, &stream_config, &playback),
+        cpal::SampleFormat::I16 => build_output_stream::<i16>(&device, &stream_config, &playback),
+        cpal::SampleFormat::U16 => build_output_stream::<u16>(&device, &stream_config, &playback),
+        cpal::SampleFormat::I32 => build_output_stream::<i32>(&device, &stream_config, &playback),
+        format => Err(format!("Unsupported output sample format: {:?}", format)),
+    }?;
+    stream
+        .play()
+        .map_err(|e| format!("Failed to play stream: {}", e))?;
+
+    // 最後のサンプルを渡した後も 2 ブロック分は止めずに待ち、
+    // デバイスのバッファに残った末尾まで鳴らしてから stream を drop する
+    while playback.position.load(Ordering::Relaxed)
+        < total + 2 * playback.block_frames.load(Ordering::Relaxed).max(1)
+    {
+        if cancelled() {
+            println!("[TTS] Playback interrupted");
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    Ok(())
+}
+
+/// 再生するサンプルと、出力コールバックが進めた位置 (フレーム数)
+struct Playback {
+    samples: Arc<Vec<f32>>,
+    position: Arc<AtomicUsize>,
+    /// 直近のコールバックで書いたフレーム数 (末尾を鳴らし切るまで待つのに使う)
+    block_frames: Arc<AtomicUsize>,
+}
+
+fn build_output_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    playback: &Playback,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let samples = Arc::clone(&playback.samples);
+    let position = Arc::clone(&playback.position);
+    let block_frames = Arc::clone(&playback.block_frames);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut pos = position.load(Ordering::Relaxed);
+                for frame in data.chunks_mut(channels) {
+                    let sample = samples.get(pos).copied().unwrap_or(0.0);
+                    frame.fill(T::from_sample(sample));
+                    pos += 1;
+                }
+                block_frames.store(data.len() / channels.max(1), Ordering::Relaxed);
+                position.store(pos, Ordering::Relaxed);
+            },
+            |err| eprintln!("An error occurred on the TTS output stream: {}", err),
+            None,
+        )
+        .map_err(|e| format!("Failed to build output stream: {}", e))
+}
+
+/// コマンドの引数の文字列を分ける
+/// 空白で区切り、"…" と '…' の中の空白は区切りにしない
+/// (Windows のパスを壊さないように \ はエスケープとして扱わない)
+pub fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+/// split_args で元に戻せるように、空白や引用符を含む引数を引用符で囲んでつなげる
+pub fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) || arg.contains('"') {
+                if arg.contains('"') {
+                    format!("'{}'", arg)
+                } else {
+                    format!("\"{}\"", arg)
+                }
+            } else if arg.contains('\'') {
+                format!("\"{}\"", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_bytes(spec: hound::WavSpec, samples: &[i16]) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+        for &s in samples {
+            writer.write_sample(s).unwrap();
+        }
+        writer.finalize().unwrap();
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn test_decode_stereo_wav_to_mono() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 24000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let pcm = decode_wav(&wav_bytes(spec, &[16384, 0, -32768, -32768])).unwrap();
+        assert_eq!(pcm.sample_rate, 24000);
+        assert_eq!(pcm.samples, vec![0.25, -1.0]);
+    }
+
+    #[test]
+    fn test_resample() {
+        let samples = [0.0, 1.0, 0.0, -1.0];
+        assert_eq!(resample(&samples, 24000, 24000), samples.to_vec());
+
+        let up = resample(&samples, 24000, 48000);
+        assert_eq!(up.len(), 8);
+        assert_eq!(up[1], 0.5);
+
+        let down = resample(&samples, 48000, 24000);
+        assert_eq!(down, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_expand_output_placeholder() {
+        let args = vec![
+            "--model".to_string(),
+            "ja.onnx".to_string(),
+            "--output_file".to_string(),
+            "{output}".to_string(),
+        ];
+        let (expanded, uses_file) = expand_args(&args, "/tmp/out.wav");
+        assert!(uses_file);
+        assert_eq!(expanded[3], "/tmp/out.wav");
+
+        let (_, uses_file) = expand_args(&args[..2], "/tmp/out.wav");
+        assert!(!uses_file);
+    }
+
+    #[test]
+    fn test_split_and_join_args() {
+        let args =
+            split_args(r#"--model "C:\Program Files\piper\ja.onnx" --output_file {output} '' x"#);
+        assert_eq!(
+            args,
+            vec![
+                "--model",
+                r"C:\Program Files\piper\ja.onnx",
+                "--output_file",
+                "{output}",
+                "",
+                "x"
+            ]
+        );
+        assert_eq!(split_args(&join_args(&args)), args);
+        assert!(split_args("  ").is_empty());
+    }
+
+    #[test]
+    fn test_engine_config_json() {
+        let engine: TtsEngine = serde_json::from_str(
+            r#"{"type": "command", "program": "piper", "args": ["--output_file", "{output}"]}"#,
+        )
+        .unwrap();
+        assert_eq!(engine.label(), "Command (piper etc.)");
+        assert_eq!(
+            serde_json::to_value(default_engine()).unwrap()["type"],
+            "voicevox"
+        );
+    }
+}
@@ -1,8 +1,12 @@
-use rosc::decoder;
+use crate::osc_trigger::{OscTriggerEvent, TriggerEngine};
 use rosc::encoder;
 use rosc::{OscMessage, OscPacket, OscType};
-use std::net::UdpSocket;
-use std::sync::mpsc::Sender;
+use serde::{Deserialize, Serialize};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum VRChatError {
@@ -21,52 +25,189 @@ impl std::fmt::Display for VRChatError {
 
 impl std::error::Error for VRChatError {}
 
+/// VRChat の chatbox スパム制限 (約1.5秒) を避けるための送信間隔
+pub const CHATBOX_MIN_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// VRChat の chatbox に一度に表示できる最大文字数 (UTF-16 単位)
+pub const CHATBOX_MAX_LEN: usize = 144;
+
+enum ChatboxCommand {
+    /// hold: 次のページを送るまで表示しておく時間, last_page: メッセージの最終ページか
+    /// notify: 通知音を鳴らすか (ストリーミング途中の表示では鳴らさない)
+    Message {
+        text: String,
+        hold: Duration,
+        last_page: bool,
+        notify: bool,
+    },
+    Typing(bool),
+}
+
+/// アバターに反映する winh の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AvatarState {
+    Idle,
+    Recording,
+    Transcribing,
+    AwaitingReply,
+    Error,
+}
+
+/// OSC で送るアバターパラメータの値
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterValue {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+}
+
+impl From<&ParameterValue> for OscType {
+    fn from(value: &ParameterValue) -> Self {
+        match value {
+            ParameterValue::Bool(b) => OscType::Bool(*b),
+            ParameterValue::Int(i) => OscType::Int(*i),
+            ParameterValue::Float(f) => OscType::Float(*f),
+        }
+    }
+}
+
+/// state に遷移したときに `/avatar/parameters/{parameter}` へ value を送る
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AvatarStateBinding {
+    pub state: AvatarState,
+    pub parameter: String,
+    pub value: ParameterValue,
+}
+
+/// "winh/Listening" と "winh/Thinking" の2つの bool で状態を表すデフォルト設定
+pub fn default_state_bindings() -> Vec<AvatarStateBinding> {
+    let binding = |state, parameter: &str, value| AvatarStateBinding {
+        state,
+        parameter: parameter.to_string(),
+        value: ParameterValue::Bool(value),
+    };
+    vec![
+        binding(AvatarState::Idle, "winh/Listening", false),
+        binding(AvatarState::Idle, "winh/Thinking", false),
+        binding(AvatarState::Recording, "winh/Listening", true),
+        binding(AvatarState::Recording, "winh/Thinking", false),
+        binding(AvatarState::Transcribing, "winh/Listening", false),
+        binding(AvatarState::Transcribing, "winh/Thinking", true),
+        binding(AvatarState::AwaitingReply, "winh/Listening", false),
+        binding(AvatarState::AwaitingReply, "winh/Thinking", true),
+        binding(AvatarState::Error, "winh/Listening", false),
+        binding(AvatarState::Error, "winh/Thinking", false),
+    ]
+}
+
+/// VRChat の OSC 送信クライアント
+/// chatbox への送信はバックグラウンドのキューを通し、送信間隔を空けて順番に送る
+/// アバターパラメータはキューを通さず即座に送る
 pub struct VRChatClient {
-    pub target_addr: String,
+    queue: Sender<ChatboxCommand>,
+    target_addr: String,
+    parameter_socket: Option<UdpSocket>,
+    /// 長文を複数ページに分けたときの各ページの表示時間
+    pub page_dwell: Duration,
+    /// ページ末尾に "(1/3)" のような番号を付けるか
+    pub page_marker: bool,
 }
 
 impl VRChatClient {
     pub fn new() -> Self {
+        Self::with_target("127.0.0.1:9000".to_string())
+    }
+
+    pub fn with_target(target_addr: String) -> Self {
+        Self::with_settings(target_addr, CHATBOX_MIN_INTERVAL)
+    }
+
+    pub fn with_settings(target_addr: String, min_interval: Duration) -> Self {
+        let (queue, receiver) = channel::<ChatboxCommand>();
+        let parameter_socket = UdpSocket::bind("0.0.0.0:0")
+            .map_err(|e| eprintln!("[VRChat OSC] Failed to bind parameter socket: {}", e))
+            .ok();
+        let worker_addr = target_addr.clone();
+        std::thread::spawn(move || run_chatbox_queue(worker_addr, min_interval, receiver));
         Self {
-            target_addr: "127.0.0.1:9000".to_string(),
+            queue,
+            target_addr,
+            parameter_socket,
+            page_dwell: Duration::from_secs(3),
+            page_marker: true,
         }
     }
 
-    /// Send a message to VRChat via OSC
+    /// Queue a message for the VRChat chatbox
+    /// 144文字を超える場合はページに分割し、page_dwell ずつ順に表示する
+    /// 送信後は typing インジケータを自動で false に戻す
     pub fn send_message(&self, message: &str) -> Result<(), VRChatError> {
-        let socket = UdpSocket::bind("0.0.0.0:0")
-            .map_err(|e| VRChatError::SocketError(format!("Failed to bind socket: {}", e)))?;
+        let pages = split_chatbox_pages(message, CHATBOX_MAX_LEN, self.page_marker);
+        let page_count = pages.len();
+        for (i, text) in pages.into_iter().enumerate() {
+            let last_page = i + 1 == page_count;
+            let hold = if last_page {
+                Duration::ZERO
+            } else {
+                self.page_dwell
+            };
+            self.queue
+                .send(ChatboxCommand::Message {
+                    text,
+                    hold,
+                    last_page,
+                    notify: true,
+                })
+                .map_err(|_| VRChatError::SendError("Chatbox queue is closed".to_string()))?;
+        }
+        Ok(())
+    }
 
-        self.send_chatbox_input(&socket, message, true)
+    /// Show a partial (still streaming) message in the chatbox
+    /// 144文字に収まらない分は先頭を省略し、typing インジケータは維持する
+    /// 呼び出し側で CHATBOX_MIN_INTERVAL 以上の間隔を空けること
+    pub fn send_preview(&self, message: &str) -> Result<(), VRChatError> {
+        self.queue
+            .send(ChatboxCommand::Message {
+                text: chatbox_tail(message, CHATBOX_MAX_LEN),
+                hold: Duration::ZERO,
+                last_page: false,
+                notify: false,
+            })
+            .map_err(|_| VRChatError::SendError("Chatbox queue is closed".to_string()))
     }
 
-    fn send_chatbox_input(
-        &self,
-        socket: &UdpSocket,
-        text: &str,
-        notify: bool,
-    ) -> Result<(), VRChatError> {
+    /// Toggle the chatbox typing indicator (`/chatbox/typing`)
+    pub fn set_typing(&self, typing: bool) -> Result<(), VRChatError> {
+        self.queue
+            .send(ChatboxCommand::Typing(typing))
+            .map_err(|_| VRChatError::SendError("Chatbox queue is closed".to_string()))
+    }
+
+    /// Set an avatar parameter (`/avatar/parameters/{name}`)
+    pub fn send_parameter(&self, name: &str, value: &ParameterValue) -> Result<(), VRChatError> {
+        let socket = self
+            .parameter_socket
+            .as_ref()
+            .ok_or_else(|| VRChatError::SocketError("Parameter socket is not bound".to_string()))?;
         let msg = OscMessage {
-            addr: "/chatbox/input".to_string(),
-            args: vec![
-                OscType::String(text.to_string()),
-                OscType::Bool(true),   // immediate
-                OscType::Bool(notify), // notify sound
-            ],
+            addr: format!("/avatar/parameters/{}", name),
+            args: vec![value.into()],
         };
-
-        self.send_osc_message(socket, msg)
+        send_osc_message(socket, &self.target_addr, msg)
     }
 
-    fn send_osc_message(&self, socket: &UdpSocket, msg: OscMessage) -> Result<(), VRChatError> {
-        let packet = OscPacket::Message(msg);
-        let msg_buf = encoder::encode(&packet)
-            .map_err(|e| VRChatError::SendError(format!("Failed to encode OSC message: {}", e)))?;
-
-        socket
-            .send_to(&msg_buf, &self.target_addr)
-            .map_err(|e| VRChatError::SendError(format!("Failed to send OSC message: {}", e)))?;
-
+    /// Send every parameter bound to the given state
+    pub fn send_state(
+        &self,
+        state: AvatarState,
+        bindings: &[AvatarStateBinding],
+    ) -> Result<(), VRChatError> {
+        for binding in bindings.iter().filter(|b| b.state == state) {
+            self.send_parameter(&binding.parameter, &binding.value)?;
+        }
         Ok(())
     }
 }
@@ -77,65 +218,260 @@ impl Default for VRChatClient {
     }
 }
 
-/// VRChat から OSC (port=9001) で MuteSelf パラメータを受信し、
-/// 1秒以内に False→True と切り替わったら sender に GestureRight の値を送信する
-pub fn start_mute_listener(sender: Sender<i32>) {
-    std::thread::spawn(move || {
-        let socket = match UdpSocket::bind("0.0.0.0:9001") {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("[VRChat OSC Listener] Failed to bind port 9001: {}", e);
-                return;
+/// 前回の chatbox 送信からの経過時間をもとに、次の送信まで待つ時間を返す
+fn pacing_delay(last_sent: Option<Instant>, now: Instant, min_interval: Duration) -> Duration {
+    match last_sent {
+        Some(t) => min_interval.saturating_sub(now.saturating_duration_since(t)),
+        None => Duration::ZERO,
+    }
+}
+
+fn run_chatbox_queue(
+    target_addr: String,
+    min_interval: Duration,
+    receiver: Receiver<ChatboxCommand>,
+) {
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(s) => s,
+        Err(e) => {
+            let err = VRChatError::SocketError(format!("Failed to bind socket: {}", e));
+            eprintln!("[VRChat OSC] {}", err);
+            return;
+        }
+    };
+
+    let mut last_sent: Option<Instant> = None;
+    // 直前に送ったページを表示しておく時間 (最低でも min_interval)
+    let mut last_hold = min_interval;
+
+    while let Ok(command) = receiver.recv() {
+        let result = match command {
+            ChatboxCommand::Typing(typing) => send_chatbox_typing(&socket, &target_addr, typing),
+            ChatboxCommand::Message {
+                text,
+                hold,
+                last_page,
+                notify,
+            } => {
+                let delay = pacing_delay(last_sent, Instant::now(), last_hold);
+                if !delay.is_zero() {
+                    std::thread::sleep(delay);
+                }
+                let result = send_chatbox_input(&socket, &target_addr, &text, notify);
+                last_sent = Some(Instant::now());
+                last_hold = hold.max(min_interval);
+                if last_page {
+                    result.and_then(|_| send_chatbox_typing(&socket, &target_addr, false))
+                } else {
+                    result
+                }
             }
         };
-        socket
-            .set_read_timeout(Some(std::time::Duration::from_millis(500)))
-            .ok();
-        println!(
-            "[VRChat OSC Listener] Listening on port 9001 for MuteSelf/GestureRight parameters"
-        );
+        if let Err(e) = result {
+            eprintln!("[VRChat OSC] {}", e);
+        }
+    }
+}
+
+/// VRChat と同じく UTF-16 のコードユニット数で文字数を数える
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '．' | '.' | '!' | '?' | '\n')
+}
+
+/// max_len に収まるようにテキストの末尾を切り出す (省略した場合は先頭に "…")
+fn chatbox_tail(text: &str, max_len: usize) -> String {
+    let text = text.trim();
+    if utf16_len(text) <= max_len {
+        return text.to_string();
+    }
+    let budget = max_len.saturating_sub(1);
+    let mut len = 0;
+    let mut start = text.len();
+    for (i, c) in text.char_indices().rev() {
+        len += c.len_utf16();
+        if len > budget {
+            break;
+        }
+        start = i;
+    }
+    format!("…{}", &text[start..])
+}
+
+/// テキストを max_len 以内のページに分割する
+/// 文末 → 空白 → 読点 の順に区切り位置を探し、見つからなければ文字単位で切る
+/// (サロゲートペアの途中では切らない)
+pub fn split_chatbox_pages(text: &str, max_len: usize, with_marker: bool) -> Vec<String> {
+    let text = text.trim();
+    if utf16_len(text) <= max_len {
+        return vec![text.to_string()];
+    }
+    if !with_marker {
+        return split_by_budget(text, max_len);
+    }
+
+    // " (12/34)" の桁数はページ数次第なので、収まるまで桁数を増やして分割し直す
+    let mut digits = 1;
+    loop {
+        let marker_len = 4 + digits * 2;
+        let pages = split_by_budget(text, max_len.saturating_sub(marker_len).max(1));
+        if pages.len().to_string().len() <= digits {
+            let total = pages.len();
+            return pages
+                .into_iter()
+                .enumerate()
+                .map(|(i, page)| format!("{} ({}/{})", page, i + 1, total))
+                .collect();
+        }
+        digits += 1;
+    }
+}
+
+fn split_by_budget(text: &str, budget: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() {
+        if utf16_len(rest) <= budget {
+            pages.push(rest.to_string());
+            break;
+        }
+
+        // budget に収まる最長の prefix (バイト位置) を求める
+        let mut fit_end = 0;
+        let mut used = 0;
+        for (i, c) in rest.char_indices() {
+            if used + c.len_utf16() > budget {
+                break;
+            }
+            used += c.len_utf16();
+            fit_end = i + c.len_utf8();
+        }
+
+        // 区切り位置はページの後半にあるときだけ採用する (極端に短いページを避ける)
+        // 後半に無ければ長い URL なども文字単位で切る
+        let head = &rest[..fit_end];
+        let find_break = |pred: fn(char) -> bool, min_break: usize| {
+            head.char_indices()
+                .rev()
+                .find(|&(i, c)| pred(c) && i + c.len_utf8() > min_break)
+                .map(|(i, c)| i + c.len_utf8())
+        };
+        let half = fit_end / 2;
+        let cut = find_break(is_sentence_end, half)
+            .or_else(|| find_break(char::is_whitespace, half))
+            .or_else(|| find_break(|c| matches!(c, '、' | '，' | ','), half))
+            .unwrap_or(fit_end);
+
+        pages.push(rest[..cut].trim().to_string());
+        rest = rest[cut..].trim_start();
+    }
+
+    pages
+}
+
+fn send_chatbox_input(
+    socket: &UdpSocket,
+    target_addr: &str,
+    text: &str,
+    notify: bool,
+) -> Result<(), VRChatError> {
+    let msg = OscMessage {
+        addr: "/chatbox/input".to_string(),
+        args: vec![
+            OscType::String(text.to_string()),
+            OscType::Bool(true),   // immediate
+            OscType::Bool(notify), // notify sound
+        ],
+    };
+
+    send_osc_message(socket, target_addr, msg)
+}
+
+fn send_chatbox_typing(
+    socket: &UdpSocket,
+    target_addr: &str,
+    typing: bool,
+) -> Result<(), VRChatError> {
+    let msg = OscMessage {
+        addr: "/chatbox/typing".to_string(),
+        args: vec![OscType::Bool(typing)],
+    };
+
+    send_osc_message(socket, target_addr, msg)
+}
+
+fn send_osc_message(
+    socket: &UdpSocket,
+    target_addr: &str,
+    msg: OscMessage,
+) -> Result<(), VRChatError> {
+    let packet = OscPacket::Message(msg);
+    let msg_buf = encoder::encode(&packet)
+        .map_err(|e| VRChatError::SendError(format!("Failed to encode OSC message: {}", e)))?;
+
+    socket
+        .send_to(&msg_buf, target_addr)
+        .map_err(|e| VRChatError::SendError(format!("Failed to send OSC message: {}", e)))?;
+
+    Ok(())
+}
+
+/// OSC 受信スレッドのハンドル
+/// drop するとスレッドを停止してポートを解放する
+pub struct OscListener {
+    pub local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for OscListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // 同じポートで再 bind できるよう、ソケットが閉じるまで待つ
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
 
+/// VRChat から OSC (bind_addr) でアバターパラメータを受信し、
+/// TriggerEngine のルールが発火したら sender にイベントを送信する
+/// ポートの bind に失敗した場合はエラーを返す
+pub fn start_osc_listener(
+    bind_addr: &str,
+    mut engine: TriggerEngine,
+    sender: Sender<OscTriggerEvent>,
+) -> Result<OscListener, VRChatError> {
+    let socket = UdpSocket::bind(bind_addr).map_err(|e| {
+        VRChatError::SocketError(format!("Failed to bind OSC port {}: {}", bind_addr, e))
+    })?;
+    let local_addr = socket
+        .local_addr()
+        .map_err(|e| VRChatError::SocketError(format!("Failed to get local address: {}", e)))?;
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_millis(500)))
+        .ok();
+    println!(
+        "[VRChat OSC Listener] Listening on {} for avatar parameters",
+        local_addr
+    );
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_clone = Arc::clone(&stop);
+
+    let handle = std::thread::spawn(move || {
         let mut buf = [0u8; 65535];
-        // False を受け取った時刻を記録する
-        let mut unmute_time: Option<std::time::Instant> = None;
-        // GestureRight の現在値
-        let mut gesture_right: i32 = 0;
 
-        loop {
+        'recv: while !stop_clone.load(Ordering::Relaxed) {
             match socket.recv_from(&mut buf) {
                 Ok((size, _addr)) => {
-                    if let Ok((_, OscPacket::Message(msg))) = decoder::decode_udp(&buf[..size]) {
-                        if msg.addr == "/avatar/parameters/GestureRight" {
-                            gesture_right = match msg.args.first() {
-                                Some(OscType::Int(i)) => *i,
-                                Some(OscType::Float(f)) => *f as i32,
-                                _ => gesture_right,
-                            };
-                            println!("[VRChat OSC Listener] GestureRight={}", gesture_right);
-                        } else if msg.addr == "/avatar/parameters/MuteSelf" {
-                            let is_muted = match msg.args.first() {
-                                Some(OscType::Bool(b)) => *b,
-                                Some(OscType::Int(i)) => *i != 0,
-                                Some(OscType::Float(f)) => *f != 0.0,
-                                _ => continue,
-                            };
-                            println!("[VRChat OSC Listener] MuteSelf={}", is_muted);
-
-                            if !is_muted {
-                                // False (ミュート解除) → 時刻を記録
-                                unmute_time = Some(std::time::Instant::now());
-                            } else if let Some(t) = unmute_time.take() {
-                                // True (ミュート) → 直前の False から 1秒以内なら録音開始
-                                if t.elapsed() <= std::time::Duration::from_secs(1) {
-                                    println!(
-                                        "[VRChat OSC Listener] Mute toggle detected → trigger recording (gesture_right={})",
-                                        gesture_right
-                                    );
-                                    if sender.send(gesture_right).is_err() {
-                                        break;
-                                    }
-                                }
-                            }
+                    for event in engine.handle_packet(&buf[..size], Instant::now()) {
+                        if sender.send(event).is_err() {
+                            break 'recv;
                         }
                     }
                 }
@@ -150,4 +486,235 @@ pub fn start_mute_listener(sender: Sender<i32>) {
         }
         println!("[VRChat OSC Listener] Stopped");
     });
+
+    Ok(OscListener {
+        local_addr,
+        stop,
+        handle: Some(handle),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::decoder;
+
+    #[test]
+    fn test_pacing_delay() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(1500);
+        assert_eq!(pacing_delay(None, now, interval), Duration::ZERO);
+        assert_eq!(
+            pacing_delay(Some(now), now + Duration::from_millis(500), interval),
+            Duration::from_millis(1000)
+        );
+        assert_eq!(
+            pacing_delay(Some(now), now + Duration::from_secs(2), interval),
+            Duration::ZERO
+        );
+    }
+
+    fn assert_pages_fit(pages: &[String]) {
+        for page in pages {
+            assert!(
+                utf16_len(page) <= CHATBOX_MAX_LEN,
+                "page too long ({}): {}",
+                utf16_len(page),
+                page
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_short_message_is_single_page() {
+        let pages = split_chatbox_pages("> こんにちは", CHATBOX_MAX_LEN, true);
+        assert_eq!(pages, vec!["> こんにちは".to_string()]);
+    }
+
+    #[test]
+    fn test_split_cjk_on_sentence_boundary() {
+        let sentence = "今日はとても良い天気なので公園まで散歩に行きました。";
+        let text = sentence.repeat(8);
+        let pages = split_chatbox_pages(&text, CHATBOX_MAX_LEN, false);
+        assert!(pages.len() > 1);
+        assert_pages_fit(&pages);
+        for page in &pages {
+            assert!(page.ends_with('。'), "not split on sentence end: {}", page);
+        }
+        assert_eq!(pages.concat(), text);
+    }
+
+    #[test]
+    fn test_split_with_page_marker() {
+        let text = "あいうえお かきくけこ ".repeat(30);
+        let pages = split_chatbox_pages(&text, CHATBOX_MAX_LEN, true);
+        assert_pages_fit(&pages);
+        let total = pages.len();
+        assert!(total > 1);
+        for (i, page) in pages.iter().enumerate() {
+            assert!(page.ends_with(&format!(" ({}/{})", i + 1, total)));
+        }
+    }
+
+    #[test]
+    fn test_split_does_not_break_surrogate_pairs() {
+        // 😀 は UTF-16 で2コードユニット
+        let text = "😀".repeat(100);
+        let pages = split_chatbox_pages(&text, CHATBOX_MAX_LEN, false);
+        assert_pages_fit(&pages);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].chars().count(), 72);
+        assert_eq!(pages.concat(), text);
+    }
+
+    #[test]
+    fn test_chatbox_tail_keeps_latest_text() {
+        assert_eq!(chatbox_tail("こんにちは", CHATBOX_MAX_LEN), "こんにちは");
+
+        let text = format!("{}おわり", "あ".repeat(200));
+        let tail = chatbox_tail(&text, CHATBOX_MAX_LEN);
+        assert_eq!(utf16_len(&tail), CHATBOX_MAX_LEN);
+        assert!(tail.starts_with('…'));
+        assert!(tail.ends_with("おわり"));
+    }
+
+    #[test]
+    fn test_split_long_url_is_hard_wrapped() {
+        let url = format!("https://example.com/{}", "a".repeat(300));
+        let text = format!("AI> see {}", url);
+        let pages = split_chatbox_pages(&text, CHATBOX_MAX_LEN, false);
+        assert_pages_fit(&pages);
+        // 先頭の空白で切って "AI> see" だけのページにはしない
+        assert_eq!(utf16_len(&pages[0]), CHATBOX_MAX_LEN);
+        assert!(pages[0].starts_with("AI> see https://example.com/"));
+        assert_eq!(pages.concat(), text);
+    }
+
+    #[test]
+    fn test_split_breaks_on_whitespace_in_second_half() {
+        let text = format!("{} {} tail", "a".repeat(20), "b".repeat(120));
+        let pages = split_chatbox_pages(&text, CHATBOX_MAX_LEN, false);
+        assert_pages_fit(&pages);
+        assert_eq!(pages[0], format!("{} {}", "a".repeat(20), "b".repeat(120)));
+        assert_eq!(pages[1], "tail");
+    }
+
+    #[test]
+    fn test_state_bindings_json() {
+        let json = r#"[
+            {"state": "recording", "parameter": "winh/Listening", "value": true},
+            {"state": "awaiting_reply", "parameter": "winh/Mode", "value": 2},
+            {"state": "error", "parameter": "winh/Glow", "value": 0.5}
+        ]"#;
+        let bindings: Vec<AvatarStateBinding> = serde_json::from_str(json).unwrap();
+        assert_eq!(bindings[0].value, ParameterValue::Bool(true));
+        assert_eq!(bindings[1].state, AvatarState::AwaitingReply);
+        assert_eq!(bindings[1].value, ParameterValue::Int(2));
+        assert_eq!(bindings[2].value, ParameterValue::Float(0.5));
+    }
+
+    #[test]
+    fn test_send_state_parameters() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let client = VRChatClient::with_target(receiver.local_addr().unwrap().to_string());
+
+        client
+            .send_state(AvatarState::Recording, &default_state_bindings())
+            .unwrap();
+
+        let mut buf = [0u8; 1024];
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let (size, _) = receiver.recv_from(&mut buf).unwrap();
+            if let Ok((_, OscPacket::Message(msg))) = decoder::decode_udp(&buf[..size]) {
+                received.push((msg.addr, msg.args));
+            }
+        }
+        assert_eq!(
+            received,
+            vec![
+                (
+                    "/avatar/parameters/winh/Listening".to_string(),
+                    vec![OscType::Bool(true)]
+                ),
+                (
+                    "/avatar/parameters/winh/Thinking".to_string(),
+                    vec![OscType::Bool(false)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_osc_listener_reports_bind_failure() {
+        let engine = || TriggerEngine::new(vec![], 1.0);
+        let (sender, _receiver) = channel::<OscTriggerEvent>();
+        let listener = start_osc_listener("127.0.0.1:0", engine(), sender.clone()).unwrap();
+        let addr = listener.local_addr.to_string();
+        assert!(matches!(
+            start_osc_listener(&addr, engine(), sender.clone()),
+            Err(VRChatError::SocketError(_))
+        ));
+
+        // drop するとポートが解放される
+        drop(listener);
+        assert!(start_osc_listener(&addr, engine(), sender).is_ok());
+    }
+
+    #[test]
+    fn test_osc_listener_forwards_trigger_events() {
+        let engine = TriggerEngine::new(crate::osc_trigger::default_rules(), 1.0);
+        let (sender, receiver) = channel::<OscTriggerEvent>();
+        let listener = start_osc_listener("127.0.0.1:0", engine, sender).unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for muted in [false, true] {
+            let msg = OscMessage {
+                addr: "/avatar/parameters/MuteSelf".to_string(),
+                args: vec![OscType::Bool(muted)],
+            };
+            send_osc_message(&socket, &listener.local_addr.to_string(), msg).unwrap();
+        }
+
+        let event = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(event.action, crate::osc_trigger::OscAction::StartRecording);
+    }
+
+    #[test]
+    fn test_queue_paces_consecutive_messages() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let addr = receiver.local_addr().unwrap().to_string();
+        let client = VRChatClient::with_settings(addr, Duration::from_millis(200));
+
+        client.set_typing(true).unwrap();
+        client.send_message("first").unwrap();
+        client.send_message("second").unwrap();
+
+        let mut buf = [0u8; 1024];
+        let mut inputs = Vec::new();
+        let mut typing = Vec::new();
+        while inputs.len() < 2 || typing.len() < 3 {
+            let (size, _) = receiver.recv_from(&mut buf).unwrap();
+            if let Ok((_, OscPacket::Message(msg))) = decoder::decode_udp(&buf[..size]) {
+                match (msg.addr.as_str(), msg.args.first()) {
+                    ("/chatbox/input", Some(OscType::String(s))) => {
+                        inputs.push((s.clone(), Instant::now()))
+                    }
+                    ("/chatbox/typing", Some(OscType::Bool(b))) => typing.push(*b),
+                    _ => {}
+                }
+            }
+        }
+
+        assert_eq!(inputs[0].0, "first");
+        assert_eq!(inputs[1].0, "second");
+        assert!(inputs[1].1.duration_since(inputs[0].1) >= Duration::from_millis(150));
+        assert_eq!(typing, vec![true, false, false]);
+    }
 }